@@ -167,17 +167,17 @@ pub fn arrays(client: &Client) -> Result<()> {
     Ok(())
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap())]
+#[path_verify(crate::mock_home::client())]
 fn get_vegetable_plot_front_0_accessors(client: &Client) -> Result<V> {
     client.get_value("garden.vegetable_plot_at_front")
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap(); [0, 1, 2, 3])]
+#[path_verify(crate::mock_home::client(); [0, 1, 2, 3])]
 fn get_vegetable_plot_front_1_accessor(client: &Client, index: usize) -> Result<V> {
     client.get_value(format!("garden.vegetable_plot_at_front[{index}]"))
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap(); [0, 1, 2, 3]; [0, 1, 2])]
+#[path_verify(crate::mock_home::client(); [0, 1, 2, 3]; [0, 1, 2])]
 fn get_vegetable_plot_front_2_accessors(
     client: &Client,
     index0: usize,
@@ -188,7 +188,7 @@ fn get_vegetable_plot_front_2_accessors(
     ))
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap(); [0, 1, 2, 3]; [0, 1, 2]; [0, 1, 2, 3, 4, 5, 6, 7])]
+#[path_verify(crate::mock_home::client(); [0, 1, 2, 3]; [0, 1, 2]; [0, 1, 2, 3, 4, 5, 6, 7])]
 fn get_vegetable_plot_front_3_accessors(
     client: &Client,
     index0: usize,
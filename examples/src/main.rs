@@ -6,6 +6,8 @@ use twincat::{path_verify, Client, State, Variable as V};
 
 mod arrays;
 mod complex_types;
+#[cfg(test)]
+mod mock_home;
 mod notifications;
 mod persistent;
 
@@ -75,14 +77,14 @@ fn toggle_state(client: &Client) -> Result<()> {
     Ok(())
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap(); ALL_ROOMS)]
+#[path_verify(crate::mock_home::client(); ALL_ROOMS)]
 fn get_room_luminosity(client: &Client, room: &str) -> Result<u16> {
     client
         .get_value(format!("main.{room}.actual_luminosity_lumens"))?
         .try_into()
 }
 
-#[path_verify(twincat::Client::builder().connect().unwrap(); ALL_ROOMS; [0, 10, 20, 60, 100, 512, 1000, 2856])]
+#[path_verify(crate::mock_home::client(); ALL_ROOMS; [0, 10, 20, 60, 100, 512, 1000, 2856])]
 fn set_room_luminosity(client: &Client, room: &str, luminosity: u16) -> Result<()> {
     client.set_value(
         format!("main.{room}.actual_luminosity_lumens"),
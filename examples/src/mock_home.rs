@@ -0,0 +1,90 @@
+//! The `home` PLC project, served by `twincat::mock` so that the tests do not need a PLC
+
+use std::sync::OnceLock;
+
+use twincat::mock::{Declaration, Server};
+use twincat::{Client, Variable as V};
+
+static SERVER: OnceLock<Server> = OnceLock::new();
+
+pub fn client() -> Client {
+    let server = SERVER.get_or_init(|| server().unwrap());
+    Client::builder()
+        .with_tcp_target(server.address())
+        .connect()
+        .unwrap()
+}
+
+fn server() -> std::io::Result<Server> {
+    let room = [
+        Declaration::new("target_luminosity_lumens", "UINT"),
+        Declaration::new("target_temperature_oc", "REAL"),
+        Declaration::new("name", "STRING").persistent(),
+        Declaration::new("actual_luminosity_lumens", "UINT"),
+        Declaration::new("actual_temperature_oc", "REAL"),
+        Declaration::new("is_occupied", "BOOL"),
+        Declaration::new("heating_on", "BOOL"),
+    ];
+    let mut kitchen = room.to_vec();
+    kitchen.extend([
+        Declaration::new("n_broccolis", "USINT"),
+        Declaration::new("fridge", "Fridge").persistent(),
+        Declaration::new("description", "STRING"),
+        Declaration::new("i", "USINT"),
+    ]);
+
+    Server::builder()
+        .with_enum("Food", "INT")
+        .with_enum("Plant", "INT")
+        .with_struct(
+            "Fridge",
+            &[
+                Declaration::new("top_shelf", "ARRAY [0..7] OF Food"),
+                Declaration::new("middle_shelf", "ARRAY [0..7] OF Food"),
+                Declaration::new("bottom_shelf", "ARRAY [0..7] OF Food"),
+                Declaration::new("drawer", "ARRAY [0..3] OF Food"),
+                Declaration::new("door_shelf", "ARRAY [0..3] OF Food"),
+            ],
+        )
+        .with_struct("Room", &room)
+        .with_struct("Kitchen", &kitchen)
+        .with_symbol(Declaration::new("main.kitchen", "Kitchen"))
+        .with_symbol(Declaration::new("main.dining_room", "Room"))
+        .with_symbol(Declaration::new("main.living_room", "Room"))
+        .with_symbol(Declaration::new("main.bedroom", "ARRAY [0..3] OF Room"))
+        .with_symbol(Declaration::new("main.bathroom", "ARRAY [0..0] OF Room"))
+        .with_symbol(Declaration::new("main.i", "USINT"))
+        .with_symbol(Declaration::new("garden.plants", "ARRAY [0..255] OF Plant").persistent())
+        .with_symbol(Declaration::new(
+            "garden.vegetable_plot_at_front",
+            "ARRAY [0..4] OF ARRAY [0..2] OF ARRAY [0..7] OF Plant",
+        ))
+        .with_symbol(Declaration::new(
+            "garden.vegetable_plot_at_back",
+            "ARRAY [0..3,0..5,0..6] OF Plant",
+        ))
+        .with_symbol(Declaration::new("house.ADDRESS", "STRING"))
+        .with_symbol(Declaration::new("house.N_BEDROOMS", "USINT"))
+        .with_symbol(Declaration::new("house.N_BATHROOMS", "USINT"))
+        .with_value(
+            "house.ADDRESS",
+            V::String(String::from("13 Apple Cart Way")),
+        )
+        .with_value("house.N_BEDROOMS", V::U8(4))
+        .with_value("house.N_BATHROOMS", V::U8(1))
+        .start()
+}
+
+#[test]
+fn home() {
+    let client = client();
+
+    assert_eq!(
+        client.get_value("house.ADDRESS").unwrap(),
+        V::String(String::from("13 Apple Cart Way"))
+    );
+
+    crate::arrays::arrays(&client).unwrap();
+    crate::complex_types::complex_types(&client).unwrap();
+    crate::persistent::persistent(&client).unwrap();
+}
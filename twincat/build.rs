@@ -2,6 +2,13 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    println!("cargo:rerun-if-changed=wrapper.h");
+
+    // TcAdsDll only exists on Windows; elsewhere the native AMS/TCP transport is used
+    if env::var("CARGO_CFG_WINDOWS").is_err() {
+        return;
+    }
+
     let target = env::var("TARGET").unwrap();
 
     let lib_dir = if target.contains("i686") {
@@ -13,8 +20,6 @@ fn main() {
     println!("cargo:rustc-link-search=native={}", lib_dir);
     println!("cargo:rustc-link-lib=dylib=TcAdsDll");
 
-    println!("cargo:rerun-if-changed=wrapper.h");
-
     let bindings = bindgen::Builder::default()
         .header("wrapper.h")
         .clang_arg("--include-directory=C:/TwinCAT/AdsApi/TcAdsDll/Include")
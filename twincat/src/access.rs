@@ -0,0 +1,121 @@
+//! What reading or writing a value takes, shared by `Client` and `AsyncClient`,
+//! which each send the requests in their own way
+
+use std::io::{Error, ErrorKind, Result};
+
+use super::beckhoff;
+use super::partial::{self, PartialWrites};
+use super::symbols_and_data_types::{Bits, Symbol, SymbolsAndDataTypes};
+use super::variables::{self, Variable};
+
+/// A value encoded for the symbol it is written to
+pub(super) enum Write {
+    /// All of the symbol's bytes, at its own address
+    Bytes(Vec<u8>),
+    /// A `BIT` struct member: the byte `byte_offset` into the struct around it is read,
+    /// these bits of it replaced by `value`, and written back
+    Bits {
+        bits: Bits,
+        byte_offset: usize,
+        value: u8,
+    },
+    /// A struct which leaves some of its bytes out, as `PartialWrites` allows:
+    /// each byte from its start, with a mask of the bits to write
+    Partial {
+        size_bytes: usize,
+        masked: Vec<(u8, u8)>,
+    },
+}
+
+impl Write {
+    /// `value` as `Client::set_value` writes it to `value_name`
+    pub(super) fn of_value(
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_name: &str,
+        value: &Variable,
+        partial_writes: PartialWrites,
+    ) -> Result<Self> {
+        let data_types = symbols_and_data_types.data_types();
+        let (symbol_info, data_type_info) =
+            symbols_and_data_types.get_symbol_and_data_type(value_name)?;
+        let bytes = match value {
+            Variable::Struct(fields)
+                if symbol_info.data_type_id() == 65 && partial_writes != PartialWrites::Reject =>
+            {
+                let masked = variables::struct_to_masked_bytes(data_types, symbol_info, fields)?;
+                if masked.iter().any(|&(_, mask)| mask != 0xff) {
+                    partial::check(value_name, partial_writes, &masked)?;
+                    return Ok(Self::Partial {
+                        size_bytes: data_type_info.size_bytes(),
+                        masked,
+                    });
+                }
+                masked.into_iter().map(|(byte, _)| byte).collect()
+            }
+            _ => value.to_bytes(data_types, symbol_info, data_type_info)?,
+        };
+        Ok(Self::of_bytes(symbol_info, bytes))
+    }
+
+    /// `value` as `Client::set_value_from_str` writes it to `value_name`
+    pub(super) fn of_str(
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_name: &str,
+        value: &str,
+    ) -> Result<Self> {
+        let (symbol_info, data_type_info) =
+            symbols_and_data_types.get_symbol_and_data_type(value_name)?;
+        let bytes = variables::str_and_symbol_to_bytes(
+            value,
+            symbols_and_data_types.data_types(),
+            symbol_info,
+            data_type_info,
+        )?;
+        Ok(Self::of_bytes(symbol_info, bytes))
+    }
+
+    fn of_bytes(symbol: &Symbol, bytes: Vec<u8>) -> Self {
+        match symbol.bits() {
+            Some(bits) => Self::Bits {
+                bits,
+                byte_offset: symbol.offset(),
+                value: bytes.first().copied().unwrap_or_default(),
+            },
+            None => Self::Bytes(bytes),
+        }
+    }
+}
+
+/// The struct around a `BIT` struct member, whose address the member's byte is found from
+pub(super) fn bits_parent(value_name: &str) -> Result<&str> {
+    match value_name.rsplit_once('.') {
+        Some((parent_name, _)) => Ok(parent_name),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{value_name} is not a struct member"),
+        )),
+    }
+}
+
+/// The index group, index offset and size in an `AdsSymbolEntry`
+pub(super) fn symbol_address(symbol_entry: &[u8]) -> Result<(u32, u32, u32)> {
+    const SIZE_SYMBOL_ENTRY: usize = std::mem::size_of::<beckhoff::AdsSymbolEntry>();
+
+    let u32_at = |index| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&symbol_entry[index..index + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    if symbol_entry.len() < SIZE_SYMBOL_ENTRY {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected a symbol entry of at least {SIZE_SYMBOL_ENTRY} bytes, got {}",
+                symbol_entry.len()
+            ),
+        ));
+    }
+
+    // entryLength, iGroup, iOffs, size, ...
+    Ok((u32_at(4), u32_at(8), u32_at(12)))
+}
@@ -0,0 +1,259 @@
+//! AMS Net IDs and addresses, written as TwinCAT writes them: `5.21.69.109.1.1:851`
+
+use std::env;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use super::beckhoff;
+
+/// Six bytes, usually the IP address of the device followed by `.1.1`
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AmsNetId([u8; 6]);
+
+/// An AMS Net ID and the AMS port of an ADS device on it, such as 851 for the first PLC runtime
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AmsAddress {
+    net_id: AmsNetId,
+    port: u16,
+}
+
+impl AmsNetId {
+    pub fn new(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn bytes(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Parses the value of the environment variable `key`
+    pub fn from_env(key: &str) -> Result<Self> {
+        from_env(key)
+    }
+}
+
+impl AmsAddress {
+    pub fn new(net_id: AmsNetId, port: u16) -> Self {
+        Self { net_id, port }
+    }
+
+    pub fn net_id(&self) -> AmsNetId {
+        self.net_id
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Parses the value of the environment variable `key`
+    pub fn from_env(key: &str) -> Result<Self> {
+        from_env(key)
+    }
+}
+
+/// The local router, and the first PLC runtime
+impl Default for AmsAddress {
+    fn default() -> Self {
+        Self {
+            net_id: AmsNetId([127, 0, 0, 1, 1, 1]),
+            port: 851,
+        }
+    }
+}
+
+impl From<[u8; 6]> for AmsNetId {
+    fn from(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<beckhoff::AmsAddr> for AmsAddress {
+    fn from(address: beckhoff::AmsAddr) -> Self {
+        Self {
+            net_id: AmsNetId(address.netId.b),
+            port: address.port,
+        }
+    }
+}
+
+impl From<AmsAddress> for beckhoff::AmsAddr {
+    fn from(address: AmsAddress) -> Self {
+        Self {
+            netId: beckhoff::AmsNetId_ {
+                b: address.net_id.0,
+            },
+            port: address.port,
+        }
+    }
+}
+
+impl fmt::Display for AmsNetId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}.{e}.{g}")
+    }
+}
+
+impl fmt::Display for AmsAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.net_id, self.port)
+    }
+}
+
+impl FromStr for AmsNetId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = [0; 6];
+        let mut parts = s.split('.');
+        for byte in bytes.iter_mut() {
+            *byte = match parts.next().map(|p| p.parse::<u8>()) {
+                Some(Ok(b)) => b,
+                _ => return Err(invalid(s, "AMS Net ID")),
+            };
+        }
+        if parts.next().is_some() {
+            return Err(invalid(s, "AMS Net ID"));
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl FromStr for AmsAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (net_id, port) = match s.rsplit_once(':') {
+            Some(np) => np,
+            None => return Err(invalid(s, "AMS address")),
+        };
+        let port = match port.parse() {
+            Ok(p) => p,
+            Err(_) => return Err(invalid(s, "AMS address")),
+        };
+        Ok(Self {
+            net_id: net_id.parse()?,
+            port,
+        })
+    }
+}
+
+fn invalid(s: &str, what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("{s} is not an {what}; expected the form 5.21.69.109.1.1:851"),
+    )
+}
+
+fn from_env<T: FromStr<Err = Error>>(key: &str) -> Result<T> {
+    match env::var(key) {
+        Ok(value) => value.trim().parse(),
+        Err(e) => Err(Error::new(ErrorKind::NotFound, format!("{key}: {e}"))),
+    }
+}
+
+/// Written as strings, as in `"5.21.69.109.1.1:851"`
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+
+    use super::{AmsAddress, AmsNetId};
+
+    impl Serialize for AmsNetId {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl Serialize for AmsAddress {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AmsNetId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(de::Error::custom)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AmsAddress {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(de::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let address: AmsAddress = "5.21.69.109.1.1:851".parse().unwrap();
+        assert_eq!(address.net_id().bytes(), [5, 21, 69, 109, 1, 1]);
+        assert_eq!(address.port(), 851);
+        assert_eq!(address.to_string(), "5.21.69.109.1.1:851");
+
+        let net_id: AmsNetId = "192.168.0.10.1.1".parse().unwrap();
+        assert_eq!(net_id, AmsNetId::new([192, 168, 0, 10, 1, 1]));
+        assert_eq!(net_id.to_string(), "192.168.0.10.1.1");
+    }
+
+    #[test]
+    fn reject_malformed() {
+        for s in [
+            "",
+            "5.21.69.109.1",
+            "5.21.69.109.1.1.1",
+            "5.21.69.256.1.1",
+            "5.21.69.109.1.x",
+        ] {
+            assert_eq!(
+                s.parse::<AmsNetId>().unwrap_err().kind(),
+                ErrorKind::InvalidInput
+            );
+        }
+        for s in [
+            "5.21.69.109.1.1",
+            "5.21.69.109.1.1:",
+            "5.21.69.109.1.1:70000",
+        ] {
+            assert_eq!(
+                s.parse::<AmsAddress>().unwrap_err().kind(),
+                ErrorKind::InvalidInput
+            );
+        }
+    }
+
+    #[test]
+    fn read_from_env() {
+        env::set_var("TWINCAT_TEST_AMS_ADDRESS", "10.0.0.7.1.1:852");
+        assert_eq!(
+            AmsAddress::from_env("TWINCAT_TEST_AMS_ADDRESS").unwrap(),
+            AmsAddress::new(AmsNetId::new([10, 0, 0, 7, 1, 1]), 852)
+        );
+        assert_eq!(
+            AmsNetId::from_env("TWINCAT_TEST_AMS_NET_ID_UNSET")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_as_strings() {
+        let address = AmsAddress::new(AmsNetId::new([5, 21, 69, 109, 1, 1]), 851);
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, "\"5.21.69.109.1.1:851\"");
+        assert_eq!(serde_json::from_str::<AmsAddress>(&json).unwrap(), address);
+        assert!(serde_json::from_str::<AmsNetId>("\"5.21\"").is_err());
+    }
+}
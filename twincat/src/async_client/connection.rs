@@ -0,0 +1,364 @@
+//! One AMS/TCP connection, shared by every in-flight request
+
+use std::collections::HashMap;
+use std::io::{Error, Result};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+#[cfg(feature = "notifications")]
+use tokio::sync::mpsc;
+use tokio::sync::{self, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::transport::tcp::{self, AmsHeader, Command};
+#[cfg(feature = "notifications")]
+use crate::transport::NotificationAttributes;
+use crate::{beckhoff, result, AmsNetId};
+
+/// The sender waiting for each response, by invoke ID.
+/// `None` once the connection has closed.
+type Pending = Arc<Mutex<Option<HashMap<u32, oneshot::Sender<(u32, Vec<u8>)>>>>>;
+
+/// The receiver of each notification's samples, by notification handle
+#[cfg(feature = "notifications")]
+type Subscribers = Arc<sync::Mutex<HashMap<u32, mpsc::UnboundedSender<Vec<u8>>>>>;
+
+pub(super) struct Connection {
+    target: beckhoff::AmsAddr,
+    source: beckhoff::AmsAddr,
+    writer: sync::Mutex<OwnedWriteHalf>,
+    invoke_id: AtomicU32,
+    pending: Pending,
+    #[cfg(feature = "notifications")]
+    subscribers: Subscribers,
+    receiver: JoinHandle<()>,
+    #[cfg(feature = "notifications")]
+    dispatcher: JoinHandle<()>,
+}
+
+impl Connection {
+    /// See `tcp::local_address` for the default `local_net_id`
+    pub(super) async fn connect(
+        socket_address: SocketAddr,
+        target: beckhoff::AmsAddr,
+        local_net_id: Option<AmsNetId>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let stream = match tokio::time::timeout(timeout, TcpStream::connect(socket_address)).await {
+            Ok(s) => s?,
+            Err(_) => return Err(timed_out()),
+        };
+        stream.set_nodelay(true)?;
+
+        let source = tcp::local_address(stream.local_addr()?, local_net_id)?;
+
+        let (reader, writer) = stream.into_split();
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+
+        #[cfg(feature = "notifications")]
+        let subscribers: Subscribers = Arc::new(sync::Mutex::new(HashMap::new()));
+        #[cfg(feature = "notifications")]
+        let (notifications, notification_receiver) = mpsc::unbounded_channel();
+        #[cfg(feature = "notifications")]
+        let dispatcher = tokio::spawn(dispatch_notifications(
+            notification_receiver,
+            subscribers.clone(),
+        ));
+
+        let receiver = tokio::spawn(receive(
+            reader,
+            pending.clone(),
+            #[cfg(feature = "notifications")]
+            notifications,
+        ));
+
+        Ok(Self {
+            target,
+            source,
+            writer: sync::Mutex::new(writer),
+            invoke_id: AtomicU32::new(1),
+            pending,
+            #[cfg(feature = "notifications")]
+            subscribers,
+            receiver,
+            #[cfg(feature = "notifications")]
+            dispatcher,
+        })
+    }
+
+    /// Sends one request and waits up to `timeout` for its response,
+    /// while other requests may be in flight.
+    /// Returns the response data after the ADS result code.
+    async fn request(&self, command: Command, data: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let invoke_id = self.invoke_id.fetch_add(1, Ordering::Relaxed);
+
+        let (sender, receiver) = oneshot::channel();
+        match self.pending.lock() {
+            Ok(mut pending) => match pending.as_mut() {
+                Some(p) => {
+                    p.insert(invoke_id, sender);
+                }
+                None => return Err(tcp::connection_closed()),
+            },
+            Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+
+        let header = AmsHeader {
+            target: self.target,
+            source: self.source,
+            command_id: command as u16,
+            state_flags: tcp::STATE_FLAG_ADS_COMMAND,
+            error_code: 0,
+            invoke_id,
+        };
+        let mut frame = Vec::new();
+        tcp::write_frame(&mut frame, &header, data)?;
+        if let Err(e) = self.writer.lock().await.write_all(&frame).await {
+            self.forget(invoke_id);
+            return Err(e);
+        }
+
+        let (error_code, response) = match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(r)) => r,
+            Ok(Err(_)) => return Err(tcp::connection_closed()),
+            Err(_) => {
+                self.forget(invoke_id);
+                (beckhoff::ADSERR_CLIENT_SYNCTIMEOUT, Vec::new())
+            }
+        };
+        result::process(error_code as i32)?;
+
+        result::process(tcp::u32_at(&response, 0)? as i32)?;
+        Ok(response[4..].to_vec())
+    }
+
+    fn forget(&self, invoke_id: u32) {
+        if let Ok(mut pending) = self.pending.lock() {
+            if let Some(p) = pending.as_mut() {
+                p.remove(&invoke_id);
+            }
+        }
+    }
+
+    /// Returns the data read
+    pub(super) async fn read(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        length: usize,
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        let request = tcp::read_request(index_group, index_offset, length);
+        let response = self.request(Command::Read, &request, timeout).await?;
+        read_data(&response, length)
+    }
+
+    pub(super) async fn write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<()> {
+        let request = tcp::write_request(index_group, index_offset, data);
+        self.request(Command::Write, &request, timeout).await?;
+        Ok(())
+    }
+
+    /// Returns the data read
+    pub(super) async fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_length: usize,
+        write_data: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        let request = tcp::read_write_request(index_group, index_offset, read_length, write_data);
+        let response = self.request(Command::ReadWrite, &request, timeout).await?;
+        read_data(&response, read_length)
+    }
+
+    /// Returns the ADS state and the device state
+    pub(super) async fn read_state(&self, timeout: Duration) -> Result<(u16, u16)> {
+        let response = self.request(Command::ReadState, &[], timeout).await?;
+        Ok((tcp::u16_at(&response, 0)?, tcp::u16_at(&response, 2)?))
+    }
+
+    pub(super) async fn write_control(
+        &self,
+        ads_state: u16,
+        device_state: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<()> {
+        let request = tcp::write_control_request(ads_state, device_state, data);
+        self.request(Command::WriteControl, &request, timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Each sample is sent to the returned receiver until the notification is deleted.
+    /// Returns the notification handle.
+    #[cfg(feature = "notifications")]
+    pub(super) async fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        timeout: Duration,
+    ) -> Result<(u32, mpsc::UnboundedReceiver<Vec<u8>>)> {
+        let request = tcp::add_notification_request(index_group, index_offset, attributes);
+
+        // Hold the lock until the handle is recorded, so that the first sample is not dropped
+        let mut subscribers = self.subscribers.lock().await;
+        let response = self
+            .request(Command::AddDeviceNotification, &request, timeout)
+            .await?;
+        let notification_handle = tcp::u32_at(&response, 0)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        subscribers.insert(notification_handle, sender);
+
+        Ok((notification_handle, receiver))
+    }
+
+    #[cfg(feature = "notifications")]
+    pub(super) async fn delete_notification(
+        &self,
+        notification_handle: u32,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.subscribers.lock().await.remove(&notification_handle);
+
+        self.request(
+            Command::DeleteDeviceNotification,
+            &notification_handle.to_le_bytes(),
+            timeout,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.receiver.abort();
+        #[cfg(feature = "notifications")]
+        self.dispatcher.abort();
+    }
+}
+
+/// Hands each response to the request waiting for it, until the connection closes
+async fn receive(
+    mut reader: OwnedReadHalf,
+    pending: Pending,
+    #[cfg(feature = "notifications")] notifications: mpsc::UnboundedSender<Vec<u8>>,
+) {
+    while let Ok((header, data)) = read_frame(&mut reader).await {
+        if header.command_id == Command::DeviceNotification as u16 {
+            #[cfg(feature = "notifications")]
+            let _ = notifications.send(data);
+            continue;
+        }
+
+        let sender = match pending.lock() {
+            Ok(mut p) => p.as_mut().and_then(|p| p.remove(&header.invoke_id)),
+            Err(_) => break,
+        };
+        if let Some(sender) = sender {
+            let _ = sender.send((header.error_code, data));
+        }
+    }
+
+    if let Ok(mut p) = pending.lock() {
+        *p = None;
+    }
+}
+
+/// Samples are handed on by their own task, so that waiting for a new subscription to be
+/// recorded cannot hold up responses
+#[cfg(feature = "notifications")]
+async fn dispatch_notifications(
+    mut notifications: mpsc::UnboundedReceiver<Vec<u8>>,
+    subscribers: Subscribers,
+) {
+    while let Some(data) = notifications.recv().await {
+        let samples = match tcp::notification_samples(&data) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        let subscribers = subscribers.lock().await;
+        for (notification_handle, sample) in samples {
+            if let Some(subscriber) = subscribers.get(&notification_handle) {
+                let _ = subscriber.send(sample.to_vec());
+            }
+        }
+    }
+
+    // Let every subscription know that there will be no more samples
+    subscribers.lock().await.clear();
+}
+
+/// Reads the whole frame before parsing it with the blocking transport's parser
+async fn read_frame(reader: &mut OwnedReadHalf) -> Result<(AmsHeader, Vec<u8>)> {
+    let mut frame = vec![0; tcp::AMS_TCP_HEADER_LENGTH];
+    reader.read_exact(&mut frame).await?;
+    let length = tcp::packet_length(&frame)?;
+
+    frame.resize(tcp::AMS_TCP_HEADER_LENGTH + length, 0);
+    reader
+        .read_exact(&mut frame[tcp::AMS_TCP_HEADER_LENGTH..])
+        .await?;
+
+    tcp::read_frame(&mut frame.as_slice())
+}
+
+/// Returns the data of a read response (length, data), which holds at most `length` bytes
+fn read_data(response: &[u8], length: usize) -> Result<Vec<u8>> {
+    let mut buffer = vec![0; length];
+    let n_read = tcp::copy_read_data(response, &mut buffer)?;
+    buffer.truncate(n_read);
+    Ok(buffer)
+}
+
+fn timed_out() -> Error {
+    Error::new(
+        std::io::ErrorKind::TimedOut,
+        "Timed out connecting to the AMS/TCP target",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::ErrorKind;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn reject_oversized_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(&[0, 0, 0xff, 0xff, 0xff, 0xff])
+                .await
+                .unwrap();
+        });
+
+        let (mut reader, _writer) = TcpStream::connect(address).await.unwrap().into_split();
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,612 @@
+//! An `async` counterpart to `Client`, for use within a tokio runtime
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod connection;
+use connection::Connection;
+#[cfg(feature = "notifications")]
+mod subscription;
+#[cfg(feature = "notifications")]
+pub use subscription::Subscription;
+
+use super::access::{self, Write};
+use super::dereference;
+use super::partial::{self, PartialWrites};
+use super::sum;
+use super::symbols_and_data_types::{self, SymbolsAndDataTypes, UploadInfo};
+use super::variables::{StringEncoding, Variable};
+use super::{beckhoff, AmsAddress, AmsNetId, State};
+
+/// Offers the same requests as `Client`, as `async fn`s over AMS/TCP.
+/// Requests are matched to their responses by invoke ID, so any number of them may be in flight
+/// at once over the one connection, which is shared by all clones.
+/// The connection is closed once the last clone is dropped.
+///
+/// Pointers are followed with `^` as `ClientBuilder::with_dereferencing` allows,
+/// but the `REFERENCE TO` members of a struct which is read are left out, as they are by a `Client`
+/// which does not dereference.
+#[derive(Clone)]
+pub struct AsyncClient {
+    connection: Arc<Connection>,
+    symbols_and_data_types: Arc<SymbolsAndDataTypes>,
+    ams_address: AmsAddress,
+    timeout: Duration,
+    dereferencing: bool,
+    partial_writes: PartialWrites,
+}
+
+impl AsyncClient {
+    /// `timeout` bounds the connection and, unless overridden, each request
+    pub(super) async fn connect(
+        socket_address: SocketAddr,
+        target: AmsAddress,
+        local_net_id: Option<AmsNetId>,
+        timeout: Duration,
+        string_encoding: StringEncoding,
+        dereferencing: bool,
+        partial_writes: PartialWrites,
+    ) -> Result<Self> {
+        let connection =
+            Connection::connect(socket_address, target.into(), local_net_id, timeout).await?;
+        let symbols_and_data_types = upload(&connection, timeout)
+            .await?
+            .with_string_encoding(string_encoding);
+
+        Ok(Self {
+            connection: Arc::new(connection),
+            symbols_and_data_types: Arc::new(symbols_and_data_types),
+            ams_address: target,
+            timeout,
+            dereferencing,
+            partial_writes,
+        })
+    }
+
+    /// A clone whose requests wait at most `timeout` for their response,
+    /// then fail with `ErrorKind::TimedOut`.
+    /// The connection is shared with `self`.
+    pub fn with_timeout(&self, timeout: Duration) -> AsyncClient {
+        AsyncClient {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    pub fn ams_address(&self) -> AmsAddress {
+        self.ams_address
+    }
+
+    pub fn symbols_and_data_types(&self) -> &SymbolsAndDataTypes {
+        &self.symbols_and_data_types
+    }
+
+    pub async fn get_value(&self, value_name: impl AsRef<str>) -> Result<Variable> {
+        self.check_dereferences(value_name.as_ref()).await?;
+        let data_types = self.symbols_and_data_types.data_types();
+        let (symbol_info, data_type_info) = self
+            .symbols_and_data_types
+            .get_symbol_and_data_type(value_name.as_ref())?;
+        let bytes = match symbol_info.bits() {
+            Some(bits) => {
+                let (index_group, index_offset) = self
+                    .bits_address(value_name.as_ref(), symbol_info.offset())
+                    .await?;
+                let byte = self
+                    .connection
+                    .read(index_group, index_offset, 1, self.timeout)
+                    .await?;
+                vec![bits.get(byte.first().copied().unwrap_or_default())]
+            }
+            None => {
+                self.get_raw_bytes(value_name.as_ref(), data_type_info.size_bytes())
+                    .await?
+            }
+        };
+
+        Variable::from_bytes(data_types, symbol_info, data_type_info, &bytes)
+    }
+
+    pub async fn set_value(&self, value_name: impl AsRef<str>, value: Variable) -> Result<()> {
+        self.check_dereferences(value_name.as_ref()).await?;
+        let write = Write::of_value(
+            &self.symbols_and_data_types,
+            value_name.as_ref(),
+            &value,
+            self.partial_writes,
+        )?;
+        self.write_value(value_name.as_ref(), write).await
+    }
+
+    pub async fn set_value_from_str(&self, value_name: impl AsRef<str>, value: &str) -> Result<()> {
+        self.check_dereferences(value_name.as_ref()).await?;
+        let write = Write::of_str(&self.symbols_and_data_types, value_name.as_ref(), value)?;
+        self.write_value(value_name.as_ref(), write).await
+    }
+
+    pub async fn get_ads_state(&self) -> Result<State> {
+        let (ads_state, _device_state) = self.connection.read_state(self.timeout).await?;
+
+        State::from_beckhoff(ads_state as i32)
+    }
+
+    pub async fn set_ads_state(&self, state: State) -> Result<()> {
+        let current_state = self.get_ads_state().await?;
+        if state == current_state {
+            return Ok(());
+        }
+
+        let u16_state = state.to_beckhoff() as u16;
+
+        self.connection
+            .write_control(u16_state, 0, &[], self.timeout)
+            .await
+    }
+
+    /// Reads each pointer which `value_name` dereferences, and fails if any is null
+    async fn check_dereferences(&self, value_name: &str) -> Result<()> {
+        let symbols_and_data_types = &self.symbols_and_data_types;
+        for pointer_name in dereference::dereferenced_pointers(
+            symbols_and_data_types,
+            value_name,
+            self.dereferencing,
+        )? {
+            let (_, data_type) = symbols_and_data_types.get_symbol_and_data_type(pointer_name)?;
+            let bytes = self
+                .get_raw_bytes(pointer_name, data_type.size_bytes())
+                .await?;
+            dereference::check_not_null(
+                pointer_name,
+                dereference::pointer_address(pointer_name, &bytes)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// At the address the target reports for it now, so that one through a pointer follows
+    /// where it points
+    async fn get_raw_bytes(&self, value_name: &str, size_bytes: usize) -> Result<Vec<u8>> {
+        let (index_group, index_offset, _) = self.symbol_address(value_name).await?;
+        self.connection
+            .read(index_group, index_offset, size_bytes, self.timeout)
+            .await
+    }
+
+    async fn write_value(&self, value_name: &str, write: Write) -> Result<()> {
+        match write {
+            Write::Bytes(bytes) => self.set_raw_bytes(value_name, bytes).await,
+            Write::Bits {
+                bits,
+                byte_offset,
+                value,
+            } => {
+                // Not atomic, as with `Client`
+                let (index_group, index_offset) =
+                    self.bits_address(value_name, byte_offset).await?;
+                let byte = self
+                    .connection
+                    .read(index_group, index_offset, 1, self.timeout)
+                    .await?;
+                let byte = bits.set(byte.first().copied().unwrap_or_default(), value);
+                self.connection
+                    .write(index_group, index_offset, &[byte], self.timeout)
+                    .await
+            }
+            Write::Partial { size_bytes, masked } => match self.partial_writes {
+                PartialWrites::Reject => partial::check(value_name, PartialWrites::Reject, &masked),
+                PartialWrites::SumWrite => {
+                    let address = self.symbol_address(value_name).await?;
+                    let requests = partial::run_requests(value_name, address, &masked)?;
+                    let requests = requests
+                        .iter()
+                        .map(|(index_group, index_offset, bytes)| {
+                            (*index_group, *index_offset, bytes.as_slice())
+                        })
+                        .collect::<Vec<(u32, u32, &[u8])>>();
+                    for chunk in requests.chunks(sum::MAX_SUB_COMMANDS) {
+                        let response = self
+                            .connection
+                            .read_write(
+                                beckhoff::ADSIGRP_SUMUP_WRITE,
+                                chunk.len() as u32,
+                                4 * chunk.len(),
+                                &sum::sum_write_data(chunk),
+                                self.timeout,
+                            )
+                            .await?;
+                        for result in sum::sum_write_results(&response, chunk.len())? {
+                            result?;
+                        }
+                    }
+                    Ok(())
+                }
+                PartialWrites::ReadModifyWrite => {
+                    let mut bytes = self.get_raw_bytes(value_name, size_bytes).await?;
+                    partial::patch(value_name, &mut bytes, &masked)?;
+                    self.set_raw_bytes(value_name, bytes).await
+                }
+            },
+        }
+    }
+
+    async fn set_raw_bytes(&self, value_name: &str, bytes: Vec<u8>) -> Result<()> {
+        let (index_group, index_offset, size) = self.symbol_address(value_name).await?;
+        if bytes.len() > size as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{value_name} has size {size}, cannot write {} bytes",
+                    bytes.len()
+                ),
+            ));
+        }
+
+        self.connection
+            .write(index_group, index_offset, &bytes, self.timeout)
+            .await
+    }
+
+    /// The index group, index offset and size of `value_name`, as the target reports them
+    async fn symbol_address(&self, value_name: &str) -> Result<(u32, u32, u32)> {
+        let symbol_entry = self
+            .connection
+            .read_write(
+                beckhoff::ADSIGRP_SYM_INFOBYNAMEEX,
+                0,
+                std::mem::size_of::<beckhoff::AdsSymbolEntry>(),
+                value_name.as_bytes(),
+                self.timeout,
+            )
+            .await?;
+        access::symbol_address(&symbol_entry)
+    }
+
+    /// Of the byte which holds a `BIT` struct member, `byte_offset` into the struct around it
+    async fn bits_address(&self, value_name: &str, byte_offset: usize) -> Result<(u32, u32)> {
+        let (index_group, index_offset, _) = self
+            .symbol_address(access::bits_parent(value_name)?)
+            .await?;
+        Ok((index_group, index_offset + byte_offset as u32))
+    }
+}
+
+async fn upload(connection: &Connection, timeout: Duration) -> Result<SymbolsAndDataTypes> {
+    let upload_info = connection
+        .read(
+            beckhoff::ADSIGRP_SYM_UPLOADINFO2,
+            0,
+            symbols_and_data_types::UPLOAD_INFO_LENGTH,
+            timeout,
+        )
+        .await?;
+    let upload_info = UploadInfo::from_bytes(&upload_info)?;
+
+    let symbol_bytes = connection
+        .read(
+            beckhoff::ADSIGRP_SYM_UPLOAD,
+            0,
+            upload_info.symbol_size,
+            timeout,
+        )
+        .await?;
+    let data_type_bytes = connection
+        .read(
+            beckhoff::ADSIGRP_SYM_DT_UPLOAD,
+            0,
+            upload_info.data_type_size,
+            timeout,
+        )
+        .await?;
+
+    upload_info.parse(&symbol_bytes, &data_type_bytes)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::{connect_async, fixture, struct_of, Declaration, Server};
+    use crate::{Client, StartIndex};
+
+    fn server() -> Server {
+        fixture(
+            &[
+                ("main.counter", "UDINT"),
+                ("main.speeds", "ARRAY [1..3] OF LREAL"),
+                ("main.name", "STRING(12)"),
+            ],
+            &[
+                ("main.counter", Variable::U32(3)),
+                ("main.name", Variable::String(String::from("Mixer"))),
+            ],
+        )
+        .start()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_and_set_values() {
+        let server = server();
+        let client = connect_async(&server).await;
+
+        assert_eq!(
+            client.get_value("main.name").await.unwrap(),
+            Variable::String(String::from("Mixer"))
+        );
+
+        client
+            .set_value("main.speeds[2]", Variable::F64(1.5))
+            .await
+            .unwrap();
+        client
+            .set_value_from_str("main.speeds[3]", "-4")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.speeds").await.unwrap(),
+            Variable::Array(
+                StartIndex::Some(1),
+                vec![Variable::F64(0.0), Variable::F64(1.5), Variable::F64(-4.0)]
+            )
+        );
+
+        assert!(client.get_value("main.missing").await.is_err());
+        assert!(client
+            .set_value(
+                "main.name",
+                Variable::String(String::from("Far too long a name"))
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn requests_in_flight_together() {
+        let server = server();
+        let client = connect_async(&server).await;
+
+        let requests = (0..16).map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move { client.get_value("main.counter").await })
+        });
+        for request in requests.collect::<Vec<_>>() {
+            assert_eq!(request.await.unwrap().unwrap(), Variable::U32(3));
+        }
+
+        let (counter, name, state) = tokio::join!(
+            client.get_value("main.counter"),
+            client.get_value("main.name"),
+            client.get_ads_state(),
+        );
+        assert_eq!(counter.unwrap(), Variable::U32(3));
+        assert_eq!(name.unwrap(), Variable::String(String::from("Mixer")));
+        assert_eq!(state.unwrap(), State::Run);
+    }
+
+    #[tokio::test]
+    async fn get_and_set_bits() {
+        let server = Server::builder()
+            .with_struct(
+                "Status",
+                &[
+                    Declaration::new("ready", "BIT"),
+                    Declaration::new("busy", "BIT"),
+                    Declaration::new("code", "SINT"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.status", "Status"))
+            .with_value("main.status.busy", Variable::Bool(true))
+            .start()
+            .unwrap();
+        let client = connect_async(&server).await;
+
+        assert_eq!(
+            client.get_value("main.status.busy").await.unwrap(),
+            Variable::Bool(true)
+        );
+
+        // The other bit of the byte is kept
+        client
+            .set_value("main.status.ready", Variable::Bool(true))
+            .await
+            .unwrap();
+        client
+            .set_value_from_str("main.status.busy", "false")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.status").await.unwrap(),
+            Variable::Struct(vec![
+                (String::from("ready"), Variable::Bool(true)),
+                (String::from("busy"), Variable::Bool(false)),
+                (String::from("code"), Variable::I8(0)),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn get_and_set_through_pointers() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.first", "INT"))
+            .with_symbol(Declaration::new("main.second", "INT"))
+            .with_symbol(Declaration::new("main.current", "POINTER TO INT"))
+            .with_symbol(Declaration::new("main.null_ptr", "POINTER TO INT"))
+            .with_value("main.first", Variable::I16(1))
+            .with_pointer("main.current", "main.first")
+            .start()
+            .unwrap();
+
+        assert_eq!(
+            connect_async(&server)
+                .await
+                .get_value("main.current^")
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_dereferencing()
+            .connect_async()
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.current^").await.unwrap(),
+            Variable::I16(1)
+        );
+        server.set_pointer("main.current", "main.second").unwrap();
+        client
+            .set_value_from_str("main.current^", "2")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.second").await.unwrap(),
+            Variable::I16(2)
+        );
+        assert_eq!(
+            client.get_value("main.current^").await.unwrap(),
+            Variable::I16(2)
+        );
+        assert_eq!(
+            client
+                .set_value("main.null_ptr^", Variable::I16(3))
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[tokio::test]
+    async fn set_partial_structs() {
+        let server = Server::builder()
+            .with_struct(
+                "Axis",
+                &[
+                    Declaration::new("ready", "BIT"),
+                    Declaration::new("busy", "BIT"),
+                    Declaration::new("speed", "INT"),
+                    Declaration::new("position", "DINT"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.axis", "Axis"))
+            .with_value("main.axis.busy", Variable::Bool(true))
+            .with_value("main.axis.position", Variable::I32(100))
+            .start()
+            .unwrap();
+        let builder = |partial_writes| {
+            Client::builder()
+                .with_tcp_target(server.address())
+                .with_partial_writes(partial_writes)
+        };
+
+        assert_eq!(
+            connect_async(&server)
+                .await
+                .set_value("main.axis", struct_of(&[("speed", Variable::I16(7))]))
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Unsupported
+        );
+
+        let client = builder(PartialWrites::SumWrite)
+            .connect_async()
+            .await
+            .unwrap();
+        client
+            .set_value("main.axis", struct_of(&[("speed", Variable::I16(7))]))
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .set_value("main.axis", struct_of(&[("ready", Variable::Bool(true))]))
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Unsupported
+        );
+
+        let client = builder(PartialWrites::ReadModifyWrite)
+            .connect_async()
+            .await
+            .unwrap();
+        client
+            .set_value("main.axis", struct_of(&[("ready", Variable::Bool(true))]))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.axis").await.unwrap(),
+            struct_of(&[
+                ("ready", Variable::Bool(true)),
+                ("busy", Variable::Bool(true)),
+                ("speed", Variable::I16(7)),
+                ("position", Variable::I32(100)),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn get_and_set_ads_state() {
+        let server = server();
+        let client = connect_async(&server).await;
+
+        client.set_ads_state(State::Stop).await.unwrap();
+        assert_eq!(client.get_ads_state().await.unwrap(), State::Stop);
+    }
+
+    #[tokio::test]
+    async fn require_tcp_target() {
+        assert_eq!(
+            Client::builder()
+                .connect_async()
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+
+    #[tokio::test]
+    async fn time_out_slow_requests() {
+        let server = server();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_timeout(Duration::from_millis(50))
+            .connect_async()
+            .await
+            .unwrap();
+
+        server
+            .set_response_delay(Duration::from_millis(200))
+            .unwrap();
+
+        assert_eq!(
+            client.get_ads_state().await.unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+        assert_eq!(
+            client
+                .with_timeout(Duration::from_secs(2))
+                .get_ads_state()
+                .await
+                .unwrap(),
+            State::Run
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_once_closed() {
+        let server = server();
+        let client = connect_async(&server).await;
+
+        drop(server);
+
+        assert!(client.get_value("main.counter").await.is_err());
+    }
+}
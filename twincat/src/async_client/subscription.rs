@@ -0,0 +1,225 @@
+use std::io::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::connection::Connection;
+use super::AsyncClient;
+use crate::notifications::{self, AdsTransmissionMode, Time};
+use crate::symbols_and_data_types::SymbolsAndDataTypes;
+use crate::transport::{tcp, NotificationAttributes};
+use crate::{beckhoff, Variable};
+
+/// The samples of one notification request.
+/// The notification is deleted by `unsubscribe`, or in the background when dropped.
+pub struct Subscription {
+    connection: Arc<Connection>,
+    symbols_and_data_types: Arc<SymbolsAndDataTypes>,
+    value_name: String,
+    value_handle: u32,
+    notification_handle: u32,
+    samples: mpsc::UnboundedReceiver<Vec<u8>>,
+    subscribed: bool,
+    /// That of the client which subscribed, for unsubscribing
+    timeout: Duration,
+}
+
+impl AsyncClient {
+    pub async fn subscribe(
+        &self,
+        value_name: impl Into<String>,
+        ads_transmission_mode: AdsTransmissionMode,
+        max_delay: Option<Time>,
+        cycle_time: Option<Time>,
+    ) -> Result<Subscription> {
+        let value_name = value_name.into();
+
+        let (_, value_data_type) = self
+            .symbols_and_data_types
+            .get_symbol_and_data_type(&value_name)?;
+
+        let attributes = NotificationAttributes {
+            length: value_data_type.size_bytes() as u32,
+            transmission_mode: ads_transmission_mode.to_beckhoff(),
+            max_delay: notifications::time_to_beckhoff(&max_delay)?,
+            cycle_time: notifications::time_to_beckhoff(&cycle_time)?,
+        };
+
+        let value_handle = self.create_handle(&value_name).await?;
+        let (notification_handle, samples) = match self
+            .connection
+            .add_notification(
+                beckhoff::ADSIGRP_SYM_VALBYHND,
+                value_handle,
+                &attributes,
+                self.timeout,
+            )
+            .await
+        {
+            Ok(n) => n,
+            Err(e) => {
+                let _ = self.release_handle(value_handle).await;
+                return Err(e);
+            }
+        };
+
+        Ok(Subscription {
+            connection: self.connection.clone(),
+            symbols_and_data_types: self.symbols_and_data_types.clone(),
+            value_name,
+            value_handle,
+            notification_handle,
+            samples,
+            subscribed: true,
+            timeout: self.timeout,
+        })
+    }
+
+    async fn create_handle(&self, value_name: &str) -> Result<u32> {
+        let handle = self
+            .connection
+            .read_write(
+                beckhoff::ADSIGRP_SYM_HNDBYNAME,
+                0,
+                std::mem::size_of::<u32>(),
+                value_name.as_bytes(),
+                self.timeout,
+            )
+            .await?;
+        tcp::u32_at(&handle, 0)
+    }
+
+    async fn release_handle(&self, handle: u32) -> Result<()> {
+        self.connection
+            .write(
+                beckhoff::ADSIGRP_SYM_RELEASEHND,
+                0,
+                &handle.to_le_bytes(),
+                self.timeout,
+            )
+            .await
+    }
+}
+
+impl Subscription {
+    pub fn value_name(&self) -> &str {
+        &self.value_name
+    }
+
+    pub fn notification_handle(&self) -> u32 {
+        self.notification_handle
+    }
+
+    /// Waits for the next sample.
+    /// Samples which cannot be decoded are skipped.
+    /// Returns `None` once the connection has closed.
+    pub async fn recv(&mut self) -> Option<Variable> {
+        loop {
+            let sample = self.samples.recv().await?;
+
+            let data_types = self.symbols_and_data_types.data_types();
+            let (symbol_info, data_type_info) = match self
+                .symbols_and_data_types
+                .get_symbol_and_data_type(&self.value_name)
+            {
+                Ok(sdt) => sdt,
+                Err(_) => continue,
+            };
+            if let Ok(v) = Variable::from_bytes(data_types, symbol_info, data_type_info, &sample) {
+                return Some(v);
+            }
+        }
+    }
+
+    /// Deletes the notification request, and releases the handle of the value
+    pub async fn unsubscribe(mut self) -> Result<()> {
+        self.subscribed = false;
+        delete(
+            &self.connection,
+            self.value_handle,
+            self.notification_handle,
+            self.timeout,
+        )
+        .await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if !self.subscribed {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let connection = self.connection.clone();
+            let value_handle = self.value_handle;
+            let notification_handle = self.notification_handle;
+            let timeout = self.timeout;
+            runtime.spawn(async move {
+                let _ = delete(&connection, value_handle, notification_handle, timeout).await;
+            });
+        }
+    }
+}
+
+async fn delete(
+    connection: &Connection,
+    value_handle: u32,
+    notification_handle: u32,
+    timeout: Duration,
+) -> Result<()> {
+    connection
+        .delete_notification(notification_handle, timeout)
+        .await?;
+
+    connection
+        .write(
+            beckhoff::ADSIGRP_SYM_RELEASEHND,
+            0,
+            &value_handle.to_le_bytes(),
+            timeout,
+        )
+        .await
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::{Declaration, Server};
+    use crate::Client;
+
+    #[tokio::test]
+    async fn receive_samples_on_change() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.level", "INT"))
+            .with_value("main.level", Variable::I16(4))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .connect_async()
+            .await
+            .unwrap();
+
+        let mut subscription = client
+            .subscribe("main.level", AdsTransmissionMode::OnChange, None, None)
+            .await
+            .unwrap();
+        assert_eq!(subscription.value_name(), "main.level");
+        assert_eq!(subscription.recv().await, Some(Variable::I16(4)));
+
+        client
+            .set_value("main.level", Variable::I16(-2))
+            .await
+            .unwrap();
+        assert_eq!(subscription.recv().await, Some(Variable::I16(-2)));
+
+        subscription.unsubscribe().await.unwrap();
+
+        assert!(client
+            .subscribe("main.level", AdsTransmissionMode::None, None, None)
+            .await
+            .is_err());
+    }
+}
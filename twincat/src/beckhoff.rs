@@ -6,8 +6,14 @@
 #![allow(clippy::derivable_impls)]
 #![allow(clippy::upper_case_acronyms)]
 
+#[cfg(windows)]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+#[cfg(not(windows))]
+mod tc_ads_def;
+#[cfg(not(windows))]
+pub use tc_ads_def::*;
+
 impl Default for AmsAddr {
     fn default() -> Self {
         Self {
@@ -0,0 +1,212 @@
+//! The subset of `TcAdsDef.h` used by this crate, for targets without TcAdsDll
+
+use std::os::raw::c_int;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AmsNetId_ {
+    pub b: [u8; 6],
+}
+pub type AmsNetId = AmsNetId_;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AmsAddr {
+    pub netId: AmsNetId,
+    pub port: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct AdsSymbolEntry {
+    pub entryLength: u32,
+    pub iGroup: u32,
+    pub iOffs: u32,
+    pub size: u32,
+    pub dataType: u32,
+    pub flags: u32,
+    pub nameLength: u16,
+    pub typeLength: u16,
+    pub commentLength: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct AdsSymbolUploadInfo2 {
+    pub nSymbols: u32,
+    pub nSymSize: u32,
+    pub nDatatypes: u32,
+    pub nDatatypeSize: u32,
+    pub nMaxDynSymbols: u32,
+    pub nUsedDynSymbols: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct AdsDatatypeEntry {
+    pub entryLength: u32,
+    pub version: u32,
+    pub hashValue: u32,
+    pub typeHashValue: u32,
+    pub size: u32,
+    pub offs: u32,
+    pub dataType: u32,
+    pub flags: u32,
+    pub nameLength: u16,
+    pub typeLength: u16,
+    pub commentLength: u16,
+    pub arrayDim: u16,
+    pub subItems: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct AdsDatatypeArrayInfo {
+    pub lBound: i32,
+    pub elements: u32,
+}
+
+pub type nAdsState = c_int;
+pub const nAdsState_ADSSTATE_INVALID: nAdsState = 0;
+pub const nAdsState_ADSSTATE_IDLE: nAdsState = 1;
+pub const nAdsState_ADSSTATE_RESET: nAdsState = 2;
+pub const nAdsState_ADSSTATE_INIT: nAdsState = 3;
+pub const nAdsState_ADSSTATE_START: nAdsState = 4;
+pub const nAdsState_ADSSTATE_RUN: nAdsState = 5;
+pub const nAdsState_ADSSTATE_STOP: nAdsState = 6;
+pub const nAdsState_ADSSTATE_SAVECFG: nAdsState = 7;
+pub const nAdsState_ADSSTATE_LOADCFG: nAdsState = 8;
+pub const nAdsState_ADSSTATE_POWERFAILURE: nAdsState = 9;
+pub const nAdsState_ADSSTATE_POWERGOOD: nAdsState = 10;
+pub const nAdsState_ADSSTATE_ERROR: nAdsState = 11;
+pub const nAdsState_ADSSTATE_SHUTDOWN: nAdsState = 12;
+pub const nAdsState_ADSSTATE_SUSPEND: nAdsState = 13;
+pub const nAdsState_ADSSTATE_RESUME: nAdsState = 14;
+pub const nAdsState_ADSSTATE_CONFIG: nAdsState = 15;
+pub const nAdsState_ADSSTATE_RECONFIG: nAdsState = 16;
+pub const nAdsState_ADSSTATE_STOPPING: nAdsState = 17;
+pub const nAdsState_ADSSTATE_INCOMPATIBLE: nAdsState = 18;
+pub const nAdsState_ADSSTATE_EXCEPTION: nAdsState = 19;
+pub const nAdsState_ADSSTATE_MAXSTATES: nAdsState = 20;
+
+pub type nAdsTransMode = c_int;
+pub const nAdsTransMode_ADSTRANS_NOTRANS: nAdsTransMode = 0;
+pub const nAdsTransMode_ADSTRANS_CLIENTCYCLE: nAdsTransMode = 1;
+pub const nAdsTransMode_ADSTRANS_CLIENTONCHA: nAdsTransMode = 2;
+pub const nAdsTransMode_ADSTRANS_SERVERCYCLE: nAdsTransMode = 3;
+pub const nAdsTransMode_ADSTRANS_SERVERONCHA: nAdsTransMode = 4;
+pub const nAdsTransMode_ADSTRANS_SERVERCYCLE2: nAdsTransMode = 5;
+pub const nAdsTransMode_ADSTRANS_SERVERONCHA2: nAdsTransMode = 6;
+pub const nAdsTransMode_ADSTRANS_CLIENT1REQ: nAdsTransMode = 10;
+
+pub const ADSIGRP_SYM_HNDBYNAME: u32 = 0xF003;
+pub const ADSIGRP_SYM_VALBYNAME: u32 = 0xF004;
+pub const ADSIGRP_SYM_VALBYHND: u32 = 0xF005;
+pub const ADSIGRP_SYM_RELEASEHND: u32 = 0xF006;
+pub const ADSIGRP_SYM_INFOBYNAME: u32 = 0xF007;
+pub const ADSIGRP_SYM_VERSION: u32 = 0xF008;
+pub const ADSIGRP_SYM_INFOBYNAMEEX: u32 = 0xF009;
+pub const ADSIGRP_SYM_DOWNLOAD: u32 = 0xF00A;
+pub const ADSIGRP_SYM_UPLOAD: u32 = 0xF00B;
+pub const ADSIGRP_SYM_UPLOADINFO: u32 = 0xF00C;
+pub const ADSIGRP_SYM_DT_UPLOAD: u32 = 0xF00E;
+pub const ADSIGRP_SYM_UPLOADINFO2: u32 = 0xF00F;
+pub const ADSIGRP_SUMUP_READ: u32 = 0xF080;
+pub const ADSIGRP_SUMUP_WRITE: u32 = 0xF081;
+pub const ADSIGRP_SUMUP_READWRITE: u32 = 0xF082;
+pub const ADSIGRP_SUMUP_READEX: u32 = 0xF083;
+pub const ADSIGRP_SUMUP_READEX2: u32 = 0xF084;
+pub const ADSIGRP_SUMUP_ADDDEVNOTE: u32 = 0xF085;
+pub const ADSIGRP_SUMUP_DELDEVNOTE: u32 = 0xF086;
+
+pub const ADSSYMBOLFLAG_PERSISTENT: u32 = 0x1;
+pub const ADSSYMBOLFLAG_BITVALUE: u32 = 0x2;
+pub const ADSSYMBOLFLAG_REFERENCETO: u32 = 0x4;
+pub const ADSSYMBOLFLAG_TYPEGUID: u32 = 0x8;
+pub const ADSSYMBOLFLAG_TCCOMIFACEPTR: u32 = 0x10;
+pub const ADSSYMBOLFLAG_READONLY: u32 = 0x20;
+
+pub const ADSDATATYPEFLAG_DATATYPE: u32 = 0x1;
+pub const ADSDATATYPEFLAG_DATAITEM: u32 = 0x2;
+pub const ADSDATATYPEFLAG_REFERENCETO: u32 = 0x4;
+pub const ADSDATATYPEFLAG_METHODDEREF: u32 = 0x8;
+pub const ADSDATATYPEFLAG_OVERSAMPLE: u32 = 0x10;
+pub const ADSDATATYPEFLAG_BITVALUES: u32 = 0x20;
+pub const ADSDATATYPEFLAG_PROPITEM: u32 = 0x40;
+pub const ADSDATATYPEFLAG_TYPEGUID: u32 = 0x80;
+pub const ADSDATATYPEFLAG_PERSISTENT: u32 = 0x100;
+pub const ADSDATATYPEFLAG_COPYMASK: u32 = 0x200;
+pub const ADSDATATYPEFLAG_TCCOMIFACEPTR: u32 = 0x400;
+pub const ADSDATATYPEFLAG_METHODINFOS: u32 = 0x800;
+pub const ADSDATATYPEFLAG_ATTRIBUTES: u32 = 0x1000;
+pub const ADSDATATYPEFLAG_ENUMINFOS: u32 = 0x2000;
+pub const ADSDATATYPEFLAG_UNION: u32 = 0x0800_0000;
+
+pub const ADSERR_NOERR: u32 = 0x00;
+pub const ERR_ADSERRS: u32 = 0x0700;
+
+pub const ADSERR_DEVICE_ERROR: u32 = ERR_ADSERRS;
+pub const ADSERR_DEVICE_SRVNOTSUPP: u32 = 0x01 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDGRP: u32 = 0x02 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDOFFSET: u32 = 0x03 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDACCESS: u32 = 0x04 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDSIZE: u32 = 0x05 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDDATA: u32 = 0x06 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOTREADY: u32 = 0x07 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_BUSY: u32 = 0x08 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDCONTEXT: u32 = 0x09 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOMEMORY: u32 = 0x0A + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDPARM: u32 = 0x0B + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOTFOUND: u32 = 0x0C + ERR_ADSERRS;
+pub const ADSERR_DEVICE_SYNTAX: u32 = 0x0D + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INCOMPATIBLE: u32 = 0x0E + ERR_ADSERRS;
+pub const ADSERR_DEVICE_EXISTS: u32 = 0x0F + ERR_ADSERRS;
+pub const ADSERR_DEVICE_SYMBOLNOTFOUND: u32 = 0x10 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_SYMBOLVERSIONINVALID: u32 = 0x11 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDSTATE: u32 = 0x12 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_TRANSMODENOTSUPP: u32 = 0x13 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOTIFYHNDINVALID: u32 = 0x14 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_CLIENTUNKNOWN: u32 = 0x15 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOMOREHDLS: u32 = 0x16 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDWATCHSIZE: u32 = 0x17 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOTINIT: u32 = 0x18 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_TIMEOUT: u32 = 0x19 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_NOINTERFACE: u32 = 0x1A + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDINTERFACE: u32 = 0x1B + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDCLSID: u32 = 0x1C + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDOBJID: u32 = 0x1D + ERR_ADSERRS;
+pub const ADSERR_DEVICE_PENDING: u32 = 0x1E + ERR_ADSERRS;
+pub const ADSERR_DEVICE_ABORTED: u32 = 0x1F + ERR_ADSERRS;
+pub const ADSERR_DEVICE_WARNING: u32 = 0x20 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_INVALIDARRAYIDX: u32 = 0x21 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_SYMBOLNOTACTIVE: u32 = 0x22 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_ACCESSDENIED: u32 = 0x23 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSENOTFOUND: u32 = 0x24 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSEEXPIRED: u32 = 0x25 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSEEXCEEDED: u32 = 0x26 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSEINVALID: u32 = 0x27 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSESYSTEMID: u32 = 0x28 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSENOTIMELIMIT: u32 = 0x29 + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSEFUTUREISSUE: u32 = 0x2A + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSETIMETOLONG: u32 = 0x2B + ERR_ADSERRS;
+pub const ADSERR_DEVICE_EXCEPTION: u32 = 0x2C + ERR_ADSERRS;
+pub const ADSERR_DEVICE_LICENSEDUPLICATED: u32 = 0x2D + ERR_ADSERRS;
+pub const ADSERR_DEVICE_SIGNATUREINVALID: u32 = 0x2E + ERR_ADSERRS;
+pub const ADSERR_DEVICE_CERTIFICATEINVALID: u32 = 0x2F + ERR_ADSERRS;
+
+pub const ADSERR_CLIENT_ERROR: u32 = 0x40 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_INVALIDPARM: u32 = 0x41 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_LISTEMPTY: u32 = 0x42 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_VARUSED: u32 = 0x43 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_DUPLINVOKEID: u32 = 0x44 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_SYNCTIMEOUT: u32 = 0x45 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_W32ERROR: u32 = 0x46 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_TIMEOUTINVALID: u32 = 0x47 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_PORTNOTOPEN: u32 = 0x48 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_NOAMSADDR: u32 = 0x49 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_SYNCINTERNAL: u32 = 0x50 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_ADDHASH: u32 = 0x51 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_REMOVEHASH: u32 = 0x52 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_NOMORESYM: u32 = 0x53 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_SYNCRESINVALID: u32 = 0x54 + ERR_ADSERRS;
+pub const ADSERR_CLIENT_SYNCPORTLOCKED: u32 = 0x55 + ERR_ADSERRS;
@@ -1,75 +1,449 @@
 use std::io::Result;
+#[cfg(feature = "tcp")]
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::{beckhoff, symbols_and_data_types};
+use super::ams_address::{AmsAddress, AmsNetId};
+use super::beckhoff;
+use super::handles::Handles;
+#[cfg(feature = "notifications")]
+use super::notifications;
+use super::partial::PartialWrites;
+use super::refresh::{SharedSymbols, SymbolsChangedCallback};
+use super::retry::{RetryPolicy, Retrying};
+use super::symbols_and_data_types::SymbolsAndDataTypes;
+use super::transport::{self, AdsTransport};
+use super::variables::StringEncoding;
 
+#[derive(Clone)]
 pub struct ClientBuilder {
-    ams_address: beckhoff::AmsAddr,
+    ams_address: AmsAddress,
+    transport: Option<Arc<dyn AdsTransport>>,
+    symbols_changed_callback: Option<SymbolsChangedCallback>,
+    retry_policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    string_encoding: StringEncoding,
+    dereferencing: bool,
+    partial_writes: PartialWrites,
+    #[cfg(feature = "tcp")]
+    tcp_target: Option<SocketAddr>,
+    #[cfg(feature = "tcp")]
+    local_ams_net_id: Option<AmsNetId>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<transport::tls::TlsConfig>,
 }
 
 impl ClientBuilder {
-    pub fn with_ams_address(mut self, address: [u8; 6]) -> Self {
-        self.ams_address.netId.b = address;
+    /// Such as `"5.21.69.109.1.1:851".parse()?`, or `AmsAddress::from_env("PLC_AMS_ADDRESS")?`
+    pub fn with_ams_address(mut self, address: AmsAddress) -> Self {
+        self.ams_address = address;
+        self
+    }
+
+    pub fn with_ams_net_id(mut self, net_id: AmsNetId) -> Self {
+        self.ams_address = AmsAddress::new(net_id, self.ams_address.port());
         self
     }
 
     pub fn with_ams_port(mut self, port: u16) -> Self {
-        self.ams_address.port = port;
+        self.ams_address = AmsAddress::new(self.ams_address.net_id(), port);
+        self
+    }
+
+    /// Connect directly to the target's AMS router over TCP (usually port 48898),
+    /// without going through TcAdsDll.
+    /// The target must have a route to this machine's AMS Net ID.
+    #[cfg(feature = "tcp")]
+    pub fn with_tcp_target(mut self, address: SocketAddr) -> Self {
+        self.tcp_target = Some(address);
+        self
+    }
+
+    /// The AMS Net ID to present to the TCP target.
+    /// Defaults to the local IP address followed by `.1.1`.
+    #[cfg(feature = "tcp")]
+    pub fn with_local_ams_net_id(mut self, net_id: AmsNetId) -> Self {
+        self.local_ams_net_id = Some(net_id);
+        self
+    }
+
+    /// Secure the connection to the TCP target with Secure ADS,
+    /// in which case the target is usually on port 8016 (`SECURE_ADS_PORT`)
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls_config: super::TlsConfig) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Send every request through `transport`, such as a proxy or a fake target,
+    /// instead of opening TcAdsDll or a TCP connection
+    pub fn with_transport(mut self, transport: impl AdsTransport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Called whenever the symbols and data types are uploaded again after a change on the target
+    pub fn with_symbols_changed_callback(
+        mut self,
+        callback: impl Fn(&SymbolsAndDataTypes) + Send + Sync + 'static,
+    ) -> Self {
+        self.symbols_changed_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Without a retry policy, each request is tried once
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// How long each request waits for its response before failing with `ErrorKind::TimedOut`.
+    /// Defaults to the transport's own timeout (5 seconds for TcAdsDll and AMS/TCP).
+    /// See `Client::with_timeout` to override it for some requests.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How the target stores `STRING`s; defaults to Windows-1252, as TwinCAT does
+    pub fn with_string_encoding(mut self, string_encoding: StringEncoding) -> Self {
+        self.string_encoding = string_encoding;
+        self
+    }
+
+    /// Follows `POINTER TO` and `REFERENCE TO`: `^` in paths such as `main.config_ptr^.limit`,
+    /// and the `REFERENCE TO` members of structs which are read (though not by `AsyncClient`).
+    /// Each pointer is read and checked for null first, which costs a round trip.
+    pub fn with_dereferencing(mut self) -> Self {
+        self.dereferencing = true;
+        self
+    }
+
+    /// How `set_value` writes a `Variable::Struct` which leaves out some fields;
+    /// defaults to `PartialWrites::Reject`
+    pub fn with_partial_writes(mut self, partial_writes: PartialWrites) -> Self {
+        self.partial_writes = partial_writes;
         self
     }
 
     pub fn connect(&self) -> Result<Client> {
-        unsafe { beckhoff::AdsPortOpen() };
-        let port = unsafe { beckhoff::AdsPortOpenEx() };
-
-        let symbols_and_data_types = match symbols_and_data_types::upload(&self.ams_address, port) {
-            Ok(s) => s,
-            Err(e) => {
-                unsafe { beckhoff::AdsPortCloseEx(port) };
-                unsafe { beckhoff::AdsPortClose() };
-                return Err(e);
+        let transport = match &self.transport {
+            Some(t) => t.clone(),
+            None => Arc::from(self.open_transport()?),
+        };
+
+        let recovered = Arc::new(AtomicBool::new(false));
+        let transport: Arc<dyn AdsTransport> = match &self.retry_policy {
+            Some(retry_policy) => {
+                // A transport given to `with_transport` cannot be opened again
+                let open = match self.transport {
+                    Some(_) => None,
+                    None => {
+                        let builder = self.clone();
+                        Some(Box::new(move || builder.open_transport()) as _)
+                    }
+                };
+                Arc::new(Retrying::new(
+                    retry_policy.clone(),
+                    transport,
+                    open,
+                    recovered.clone(),
+                ))
             }
+            None => transport,
         };
 
+        let symbols = SharedSymbols::upload(
+            transport.clone(),
+            self.timeout,
+            self.string_encoding,
+            self.symbols_changed_callback.clone(),
+            recovered,
+        )?;
+
         Ok(Client {
+            handles: Arc::new(Handles::new(transport.clone())),
+            symbols: Arc::new(symbols),
+            #[cfg(feature = "notifications")]
+            notifications: Arc::new(notifications::Registry::new(transport.clone())),
+            transport,
             ams_address: self.ams_address,
-            port,
-            symbols_and_data_types,
+            timeout: self.timeout,
+            dereferencing: self.dereferencing,
+            partial_writes: self.partial_writes,
         })
     }
+
+    /// Connects over AMS/TCP, so requires `with_tcp_target`
+    #[cfg(feature = "tokio")]
+    pub async fn connect_async(&self) -> Result<super::AsyncClient> {
+        #[cfg(feature = "tls")]
+        if self.tls_config.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "AsyncClient does not support Secure ADS; use ClientBuilder::connect",
+            ));
+        }
+
+        match self.tcp_target {
+            Some(tcp_target) => {
+                super::AsyncClient::connect(
+                    tcp_target,
+                    self.ams_address,
+                    self.local_ams_net_id,
+                    self.timeout.unwrap_or(transport::tcp::TIMEOUT),
+                    self.string_encoding,
+                    self.dereferencing,
+                    self.partial_writes,
+                )
+                .await
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "AsyncClient only supports AMS/TCP; use ClientBuilder::with_tcp_target",
+            )),
+        }
+    }
+
+    /// Opens the transport that `connect` would use, for example to wrap it in a proxy
+    /// which is then given to `with_transport`
+    pub fn open_transport(&self) -> Result<Box<dyn AdsTransport>> {
+        #[cfg(feature = "tls")]
+        if let (Some(tcp_target), Some(tls_config)) = (self.tcp_target, &self.tls_config) {
+            let tcp = transport::Tcp::connect_tls(
+                tcp_target,
+                self.ams_address.into(),
+                self.local_ams_net_id,
+                tls_config,
+                self.timeout.unwrap_or(transport::tcp::TIMEOUT),
+            )?;
+            return Ok(Box::new(tcp));
+        }
+
+        #[cfg(feature = "tcp")]
+        if let Some(tcp_target) = self.tcp_target {
+            let tcp = transport::Tcp::connect(
+                tcp_target,
+                self.ams_address.into(),
+                self.local_ams_net_id,
+                self.timeout.unwrap_or(transport::tcp::TIMEOUT),
+            )?;
+            return Ok(Box::new(tcp));
+        }
+
+        open_default_transport(self.ams_address.into(), self.timeout)
+    }
 }
 
-#[derive(Clone)]
-pub struct Client {
+#[cfg(windows)]
+fn open_default_transport(
     ams_address: beckhoff::AmsAddr,
-    port: i32,
-    symbols_and_data_types: symbols_and_data_types::SymbolsAndDataTypes,
+    timeout: Option<Duration>,
+) -> Result<Box<dyn AdsTransport>> {
+    Ok(Box::new(transport::TcAdsDll::open(ams_address, timeout)?))
 }
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        #[cfg(feature = "notifications")]
-        let _ = self.drop_notification_requests();
+#[cfg(not(windows))]
+fn open_default_transport(
+    _: beckhoff::AmsAddr,
+    _: Option<Duration>,
+) -> Result<Box<dyn AdsTransport>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "TcAdsDll is only available on Windows; use ClientBuilder::with_tcp_target",
+    ))
+}
 
-        unsafe { beckhoff::AdsPortCloseEx(self.port) };
-        unsafe { beckhoff::AdsPortClose() };
-    }
+/// Cheap to clone: clones share the transport (the TcAdsDll port or the TCP connection),
+/// cached handles, symbols and notifications, and may be handed to other threads.
+/// The transport is closed, cached handles released, and notifications deleted,
+/// once the last clone is dropped.
+#[derive(Clone)]
+pub struct Client {
+    transport: Arc<dyn AdsTransport>,
+    symbols: Arc<SharedSymbols>,
+    handles: Arc<Handles>,
+    ams_address: AmsAddress,
+    timeout: Option<Duration>,
+    dereferencing: bool,
+    partial_writes: PartialWrites,
+    #[cfg(feature = "notifications")]
+    notifications: Arc<notifications::Registry>,
 }
 
+// Clients are handed to worker threads, so must stay `Send` and `Sync`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Client>();
+    assert_send_sync::<ClientBuilder>();
+};
+
 impl Client {
     pub fn builder() -> ClientBuilder {
-        let mut ams_address = beckhoff::AmsAddr::default();
-        unsafe { beckhoff::AdsGetLocalAddress(&mut ams_address) };
+        #[cfg(windows)]
+        let ams_address = AmsAddress::from(transport::TcAdsDll::local_address());
+        #[cfg(not(windows))]
+        let ams_address = AmsAddress::default();
 
-        ClientBuilder { ams_address }
+        ClientBuilder {
+            ams_address,
+            transport: None,
+            symbols_changed_callback: None,
+            retry_policy: None,
+            timeout: None,
+            string_encoding: StringEncoding::default(),
+            dereferencing: false,
+            partial_writes: PartialWrites::default(),
+            #[cfg(feature = "tcp")]
+            tcp_target: None,
+            #[cfg(feature = "tcp")]
+            local_ams_net_id: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+        }
     }
 
-    pub(super) fn ams_address(&self) -> &beckhoff::AmsAddr {
-        &self.ams_address
+    /// A clone whose requests wait at most `timeout` for their response,
+    /// such as `client.with_timeout(Duration::from_millis(200)).get_value("main.speed")`.
+    /// Handles, symbols and data types are shared with `self`.
+    /// With TcAdsDll, whose timeout belongs to the port, the requests go through a port kept for
+    /// `timeout`, which is opened by the first of them.
+    pub fn with_timeout(&self, timeout: Duration) -> Client {
+        let mut client = self.clone();
+        client.timeout = Some(timeout);
+        client
+    }
+
+    /// The transport, bounded by this client's timeout
+    pub(super) fn transport(&self) -> Box<dyn AdsTransport + '_> {
+        transport::timed(&*self.transport, self.timeout)
+    }
+    pub(super) fn dereferencing(&self) -> bool {
+        self.dereferencing
+    }
+    pub(super) fn partial_writes(&self) -> PartialWrites {
+        self.partial_writes
     }
-    pub(super) fn port(&self) -> i32 {
-        self.port
+    pub(super) fn handles(&self) -> &Handles {
+        &self.handles
     }
-    pub fn symbols_and_data_types(&self) -> &symbols_and_data_types::SymbolsAndDataTypes {
-        &self.symbols_and_data_types
+    pub(super) fn symbols(&self) -> &Arc<SharedSymbols> {
+        &self.symbols
+    }
+    #[cfg(feature = "notifications")]
+    pub(super) fn notifications(&self) -> &Arc<notifications::Registry> {
+        &self.notifications
+    }
+    /// The target given to the builder; a transport given to `with_transport` may go elsewhere
+    pub fn ams_address(&self) -> AmsAddress {
+        self.ams_address
+    }
+    /// These may be replaced after a change on the target; see `refresh_symbols_and_data_types`
+    pub fn symbols_and_data_types(&self) -> Arc<SymbolsAndDataTypes> {
+        self.symbols.current()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use std::io::ErrorKind;
+
+    use crate::mock::{Declaration, Server};
+    use crate::Variable;
+
+    #[test]
+    fn time_out_slow_requests() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.level", "INT"))
+            .with_value("main.level", Variable::I16(40))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_timeout(Duration::from_millis(50))
+            .connect()
+            .unwrap();
+
+        server
+            .set_response_delay(Duration::from_millis(200))
+            .unwrap();
+
+        assert_eq!(
+            client.get_value("main.level").unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+        assert_eq!(
+            client
+                .with_timeout(Duration::from_secs(2))
+                .get_value("main.level")
+                .unwrap(),
+            Variable::I16(40)
+        );
+        // The override only applies to the clone
+        assert_eq!(
+            client.get_ads_state().unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn keep_handles_under_other_timeouts() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.level", "INT"))
+            .with_value("main.level", Variable::I16(40))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .connect()
+            .unwrap();
+
+        assert_eq!(client.get_value("main.level").unwrap(), Variable::I16(40));
+        for timeout_ms in [200, 300, 200] {
+            let timed = client.with_timeout(Duration::from_millis(timeout_ms));
+            timed.set_value("main.level", Variable::I16(41)).unwrap();
+            assert_eq!(timed.get_value("main.level").unwrap(), Variable::I16(41));
+        }
+        assert_eq!(client.get_value("main.level").unwrap(), Variable::I16(41));
+        // The handle made first serves every timeout
+        assert_eq!(server.n_handles().unwrap(), 1);
+    }
+
+    #[test]
+    fn share_between_threads() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.counts", "ARRAY [0..3] OF UDINT"))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .connect()
+            .unwrap();
+
+        let workers = (0..4)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || {
+                    let path = format!("main.counts[{i}]");
+                    for count in 0..20 {
+                        client.set_value(&path, Variable::U32(count)).unwrap();
+                        assert_eq!(client.get_value(&path).unwrap(), Variable::U32(count));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // Every clone has been dropped, but the connection stays open for the last one
+        assert_eq!(
+            client.get_value("main.counts[3]").unwrap(),
+            Variable::U32(19)
+        );
     }
 }
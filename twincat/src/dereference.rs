@@ -0,0 +1,315 @@
+//! `POINTER TO` and `REFERENCE TO`, which a `Client` follows once `ClientBuilder::with_dereferencing`
+//! allows it: explicitly with `^`, as in `main.config_ptr^.limit`, and implicitly for the
+//! `REFERENCE TO` members of a struct which is read.
+//!
+//! Each pointer is read before what it points to, so that a null pointer is caught here
+//! rather than followed by the target.
+
+use std::io::{Error, ErrorKind, Result};
+
+use super::client::Client;
+use super::symbols_and_data_types::{DataType, SymbolsAndDataTypes};
+use super::variables::Variable;
+
+/// How many references deep the members of a struct are followed
+const MAX_DEPTH: usize = 8;
+
+impl Client {
+    /// Reads each pointer which `value_name` dereferences, and fails if any is null
+    pub(super) fn check_dereferences(
+        &self,
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_name: &str,
+    ) -> Result<()> {
+        for pointer_name in
+            dereferenced_pointers(symbols_and_data_types, value_name, self.dereferencing())?
+        {
+            check_not_null(
+                pointer_name,
+                self.pointer_value(symbols_and_data_types, pointer_name)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the `REFERENCE TO` members of `value`, a struct read from `value_name`,
+    /// with what they refer to, and so on for the structs among its members.
+    /// A null reference, or one which leads back to a struct that is already being read
+    /// (or deeper than `MAX_DEPTH`), is left as `Variable::Void`.
+    /// `addresses` are those of the references followed so far.
+    pub(super) fn dereference_members(
+        &self,
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_name: &str,
+        data_type: &DataType,
+        value: &mut Variable,
+        addresses: &mut Vec<u64>,
+    ) -> Result<()> {
+        let elements = match value {
+            Variable::Struct(elements) => elements,
+            _ => return Ok(()),
+        };
+
+        let mut dereferenced = Vec::with_capacity(data_type.fields().len());
+        for field in data_type.fields() {
+            let field_name = format!("{value_name}.{}", field.name());
+            if field.is_reference() {
+                let address = self.pointer_value(symbols_and_data_types, &field_name)?;
+                let field_value =
+                    if address == 0 || addresses.contains(&address) || addresses.len() >= MAX_DEPTH
+                    {
+                        Variable::Void
+                    } else {
+                        addresses.push(address);
+                        let field_value = self.get_value_inner(
+                            symbols_and_data_types,
+                            &format!("{field_name}^"),
+                            addresses,
+                        );
+                        addresses.pop();
+                        field_value?
+                    };
+                dereferenced.push((field.name().to_string(), field_value));
+            } else if let Some(index) = elements.iter().position(|(n, _)| n == field.name()) {
+                let (name, mut field_value) = elements.remove(index);
+                let field_data_type = symbols_and_data_types.data_types().get(field.data_type())?;
+                self.dereference_members(
+                    symbols_and_data_types,
+                    &field_name,
+                    field_data_type,
+                    &mut field_value,
+                    addresses,
+                )?;
+                dereferenced.push((name, field_value));
+            }
+        }
+
+        *elements = dereferenced;
+        Ok(())
+    }
+
+    /// The address which a pointer or reference holds
+    fn pointer_value(
+        &self,
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        pointer_name: &str,
+    ) -> Result<u64> {
+        let (_, data_type) = symbols_and_data_types.get_symbol_and_data_type(pointer_name)?;
+        let bytes = self.get_raw_bytes(pointer_name, data_type.size_bytes())?;
+        pointer_address(pointer_name, &bytes)
+    }
+}
+
+/// The pointers which `value_name` dereferences, in the order they are followed,
+/// once it is known that `value_name` may be read or written
+pub(super) fn dereferenced_pointers<'a>(
+    symbols_and_data_types: &SymbolsAndDataTypes,
+    value_name: &'a str,
+    dereferencing: bool,
+) -> Result<Vec<&'a str>> {
+    if !value_name.contains('^') {
+        return Ok(Vec::new());
+    }
+    if !dereferencing {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{value_name} dereferences a pointer; allow it with ClientBuilder::with_dereferencing"),
+        ));
+    }
+
+    symbols_and_data_types.get_symbol_and_data_type(value_name)?;
+    Ok(value_name
+        .match_indices('^')
+        .map(|(index, _)| &value_name[..index])
+        .collect())
+}
+
+pub(super) fn check_not_null(pointer_name: &str, address: u64) -> Result<()> {
+    match address {
+        0 => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Cannot dereference {pointer_name}, which is null"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The address which a pointer or reference holds, from its bytes
+pub(super) fn pointer_address(pointer_name: &str, bytes: &[u8]) -> Result<u64> {
+    match bytes.len() {
+        4 => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64),
+        8 => {
+            let mut address = [0; 8];
+            address.copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(address))
+        }
+        n => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{pointer_name} has {n} bytes, so is not a pointer"),
+        )),
+    }
+}
+
+/// Sum commands use handles directly, so cannot check pointers before following them
+pub(super) fn check_not_dereferenced(value_name: &str) -> Result<()> {
+    if value_name.contains('^') {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "{value_name} dereferences a pointer; use Client::get_value and Client::set_value"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::{connect, struct_of, Declaration, Server};
+    use crate::Variable as V;
+
+    #[test]
+    fn get_and_set_through_pointers() {
+        let server = Server::builder()
+            .with_struct(
+                "Limits",
+                &[
+                    Declaration::new("low", "INT"),
+                    Declaration::new("high", "INT"),
+                ],
+            )
+            .with_struct(
+                "Config",
+                &[
+                    Declaration::new("limit", "INT"),
+                    Declaration::new("limits", "REFERENCE TO Limits"),
+                ],
+            )
+            .with_struct(
+                "Node",
+                &[
+                    Declaration::new("value", "INT"),
+                    Declaration::new("next", "REFERENCE TO Node"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.limits", "Limits"))
+            .with_symbol(Declaration::new("main.config", "Config"))
+            .with_symbol(Declaration::new("main.config_ptr", "POINTER TO Config"))
+            .with_symbol(Declaration::new("main.count", "INT"))
+            .with_symbol(Declaration::new("main.count_ptr", "POINTER TO INT"))
+            .with_symbol(Declaration::new("main.null_ptr", "POINTER TO INT"))
+            .with_symbol(Declaration::new("main.head", "Node"))
+            .with_symbol(Declaration::new("main.tail", "Node"))
+            .with_value("main.limits.high", V::I16(90))
+            .with_value("main.config.limit", V::I16(40))
+            .with_value("main.count", V::I16(3))
+            .with_value("main.head.value", V::I16(1))
+            .with_value("main.tail.value", V::I16(2))
+            .with_pointer("main.config.limits", "main.limits")
+            .with_pointer("main.config_ptr", "main.config")
+            .with_pointer("main.count_ptr", "main.count")
+            .with_pointer("main.head.next", "main.tail")
+            .with_pointer("main.tail.next", "main.head")
+            .start()
+            .unwrap();
+
+        // Dereferencing is opt-in, and references are otherwise left out
+        let client = connect(&server);
+        assert_eq!(
+            client.get_value("main.count_ptr^").unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            client.get_value("main.config").unwrap(),
+            struct_of(&[("limit", V::I16(40))])
+        );
+
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_dereferencing()
+            .connect()
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.config_ptr^.limit").unwrap(),
+            V::I16(40)
+        );
+        client.set_value("main.count_ptr^", V::I16(7)).unwrap();
+        assert_eq!(client.get_value("main.count").unwrap(), V::I16(7));
+        client
+            .set_value_from_str("main.config_ptr^.limits^.low", "-5")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.config").unwrap(),
+            struct_of(&[
+                ("limit", V::I16(40)),
+                (
+                    "limits",
+                    struct_of(&[("low", V::I16(-5)), ("high", V::I16(90))])
+                ),
+            ])
+        );
+
+        // The cycle is followed until it comes back to where it started
+        assert_eq!(
+            client.get_value("main.head").unwrap(),
+            struct_of(&[
+                ("value", V::I16(1)),
+                (
+                    "next",
+                    struct_of(&[
+                        ("value", V::I16(2)),
+                        (
+                            "next",
+                            struct_of(&[("value", V::I16(1)), ("next", V::Void)])
+                        ),
+                    ])
+                ),
+            ])
+        );
+
+        assert_eq!(
+            client.get_value("main.null_ptr^").unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            client.get_value("main.count^").unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            client.get_values(&["main.count_ptr^"]).unwrap()[0]
+                .as_ref()
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn follow_pointers_which_change() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.first", "INT"))
+            .with_symbol(Declaration::new("main.second", "INT"))
+            .with_symbol(Declaration::new("main.current", "POINTER TO INT"))
+            .with_value("main.first", V::I16(1))
+            .with_value("main.second", V::I16(2))
+            .with_pointer("main.current", "main.first")
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_dereferencing()
+            .connect()
+            .unwrap();
+
+        assert_eq!(client.get_value("main.current^").unwrap(), V::I16(1));
+        let n_handles = server.n_handles().unwrap();
+
+        server.set_pointer("main.current", "main.second").unwrap();
+        assert_eq!(client.get_value("main.current^").unwrap(), V::I16(2));
+
+        // The handle through the pointer is released after each read
+        assert_eq!(server.n_handles().unwrap(), n_handles);
+    }
+}
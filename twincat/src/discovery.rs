@@ -0,0 +1,229 @@
+//! Finds TwinCAT devices on the local network, by the broadcast which their routers answer
+//! on UDP port 48899.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! for device in twincat::discovery::discover(Duration::from_secs(1)).unwrap() {
+//!     println!("{} {} TwinCAT {}", device.hostname(), device.ams_net_id(), device.twincat_version());
+//! }
+//! ```
+
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::udp::{self, tag, Message, Service};
+use super::{AmsAddress, AmsNetId, Client, ClientBuilder};
+
+/// The port of the AMS router, for `ClientBuilder::with_tcp_target`
+#[cfg(feature = "tcp")]
+const AMS_TCP_PORT: u16 = 48898;
+
+/// A device which answered `discover`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+    ip_address: IpAddr,
+    hostname: String,
+    ams_net_id: AmsNetId,
+    twincat_version: TwinCatVersion,
+    os_version: OsVersion,
+}
+
+/// Such as 3.1.4024
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TwinCatVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub build: u16,
+}
+
+/// As reported by the device.
+/// `platform` is 2 for Windows (NT and later); other platforms are reported as their number.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OsVersion {
+    pub platform: u32,
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+/// Broadcasts to the whole local network, and collects answers until `timeout` has passed
+pub fn discover(timeout: Duration) -> Result<Vec<Device>> {
+    discover_at(SocketAddr::from((Ipv4Addr::BROADCAST, udp::PORT)), timeout)
+}
+
+/// Sends the request to `address` only, such as the broadcast address of one subnet
+/// or a single device, and collects answers until `timeout` has passed
+pub fn discover_at(address: SocketAddr, timeout: Duration) -> Result<Vec<Device>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+
+    let request = Message {
+        invoke_id: 0,
+        service: Service::Identify,
+        is_response: false,
+        ams_net_id: AmsNetId::default(),
+        ams_port: 10000,
+        tags: Vec::new(),
+    };
+    socket.send_to(&request.to_bytes(), address)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut devices: Vec<Device> = Vec::new();
+    let mut buffer = [0; 2048];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        // Anything else on the port, including our own broadcast, is ignored
+        let device = match Message::from_bytes(&buffer[..length]) {
+            Ok(m) if m.is_response && m.service == Service::Identify => {
+                Device::from_response(source.ip(), &m)
+            }
+            _ => continue,
+        };
+        if !devices.iter().any(|d| d.ams_net_id == device.ams_net_id) {
+            devices.push(device);
+        }
+    }
+
+    Ok(devices)
+}
+
+impl Device {
+    fn from_response(ip_address: IpAddr, response: &Message) -> Self {
+        let hostname = response
+            .tag(tag::HOSTNAME)
+            .map(udp::tag_string)
+            .unwrap_or_default();
+
+        let twincat_version = match response.tag(tag::TWINCAT_VERSION) {
+            Some([major, minor, build_low, build_high, ..]) => TwinCatVersion {
+                major: *major,
+                minor: *minor,
+                build: u16::from_le_bytes([*build_low, *build_high]),
+            },
+            _ => TwinCatVersion::default(),
+        };
+
+        // OSVERSIONINFO: dwOSVersionInfoSize, dwMajorVersion, dwMinorVersion, dwBuildNumber, dwPlatformId, ...
+        let os_version = match response.tag(tag::OS_VERSION) {
+            Some(data) => OsVersion {
+                major: udp::u32_at(data, 4).unwrap_or_default(),
+                minor: udp::u32_at(data, 8).unwrap_or_default(),
+                build: udp::u32_at(data, 12).unwrap_or_default(),
+                platform: udp::u32_at(data, 16).unwrap_or_default(),
+            },
+            None => OsVersion::default(),
+        };
+
+        Self {
+            ip_address,
+            hostname,
+            ams_net_id: response.ams_net_id,
+            twincat_version,
+            os_version,
+        }
+    }
+
+    /// The address the answer came from
+    pub fn ip_address(&self) -> IpAddr {
+        self.ip_address
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn ams_net_id(&self) -> AmsNetId {
+        self.ams_net_id
+    }
+
+    pub fn twincat_version(&self) -> TwinCatVersion {
+        self.twincat_version
+    }
+
+    pub fn os_version(&self) -> OsVersion {
+        self.os_version
+    }
+
+    /// The ADS device at `port` on this device, such as 851 for the first PLC runtime
+    pub fn ams_address(&self, port: u16) -> AmsAddress {
+        AmsAddress::new(self.ams_net_id, port)
+    }
+
+    /// A builder targeting this device's AMS Net ID (and, with the `tcp` feature,
+    /// its AMS router over TCP), with the default port of 851.
+    /// The device must still have a route to this machine.
+    pub fn client_builder(&self) -> ClientBuilder {
+        let builder = Client::builder().with_ams_net_id(self.ams_net_id);
+        #[cfg(feature = "tcp")]
+        let builder = builder.with_tcp_target(SocketAddr::new(self.ip_address, AMS_TCP_PORT));
+        builder
+    }
+}
+
+impl fmt::Display for TwinCatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.platform {
+            2 => write!(f, "Windows")?,
+            p => write!(f, "Platform {p}")?,
+        }
+        write!(f, " {}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::Responder;
+
+    #[test]
+    fn discover_responder() {
+        let responder = Responder::builder()
+            .with_hostname("CX-5140")
+            .with_ams_net_id(AmsNetId::new([5, 21, 69, 109, 1, 1]))
+            .with_twincat_version(TwinCatVersion {
+                major: 3,
+                minor: 1,
+                build: 4026,
+            })
+            .start()
+            .unwrap();
+
+        let devices = discover_at(responder.address(), Duration::from_millis(200)).unwrap();
+
+        assert_eq!(devices.len(), 1);
+        let device = &devices[0];
+        assert_eq!(device.hostname(), "CX-5140");
+        assert_eq!(device.ams_net_id().to_string(), "5.21.69.109.1.1");
+        assert_eq!(device.twincat_version().to_string(), "3.1.4026");
+        assert_eq!(device.os_version().to_string(), "Windows 10.0.19045");
+        assert_eq!(device.ip_address(), IpAddr::from(Ipv4Addr::LOCALHOST));
+        assert_eq!(device.ams_address(852).to_string(), "5.21.69.109.1.1:852");
+    }
+
+    #[test]
+    fn find_nothing_in_time() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let devices = discover_at(socket.local_addr().unwrap(), Duration::from_millis(50)).unwrap();
+        assert!(devices.is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Symbol handles, kept so that each read costs one round trip
+
+use std::collections::HashMap;
+use std::io::{Error, Result};
+use std::sync::{Arc, Mutex};
+
+use super::transport::AdsTransport;
+use super::{beckhoff, refresh};
+
+/// Shared by all clones of a `Client`; every handle is released when the last clone is dropped
+pub(super) struct Handles {
+    transport: Arc<dyn AdsTransport>,
+    by_value_name: Mutex<HashMap<String, u32>>,
+}
+
+impl Handles {
+    pub(super) fn new(transport: Arc<dyn AdsTransport>) -> Self {
+        Self {
+            transport,
+            by_value_name: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Calls `f` with the handle of `value_name`, creating the handle if there is none yet.
+    /// If the target no longer accepts the handle (after an online change), it is forgotten.
+    /// A handle through a pointer (`^`) is bound to where the pointer pointed when it was made,
+    /// so it is released after `f` instead of being kept.
+    /// Handles are created and released through `transport`, so that they share the caller's timeout.
+    pub(super) fn with_handle<T>(
+        &self,
+        transport: &dyn AdsTransport,
+        value_name: &str,
+        f: impl FnOnce(u32) -> Result<T>,
+    ) -> Result<T> {
+        if value_name.contains('^') {
+            let handle = create(transport, value_name)?;
+            let output = f(handle);
+            release(transport, handle);
+            return output;
+        }
+
+        let handle = self.get(transport, value_name)?;
+        let output = f(handle);
+        if let Err(e) = &output {
+            if refresh::symbols_changed(e) {
+                self.forget(transport, value_name, handle);
+            }
+        }
+        output
+    }
+
+    fn get(&self, transport: &dyn AdsTransport, value_name: &str) -> Result<u32> {
+        if let Some(handle) = self.lock()?.get(value_name) {
+            return Ok(*handle);
+        }
+
+        // Do not hold the lock while waiting on the target
+        let handle = create(transport, value_name)?;
+
+        let mut by_value_name = self.lock()?;
+        match by_value_name.get(value_name) {
+            // Another thread got there first
+            Some(h) => {
+                let h = *h;
+                drop(by_value_name);
+                release(transport, handle);
+                Ok(h)
+            }
+            None => {
+                by_value_name.insert(value_name.to_string(), handle);
+                Ok(handle)
+            }
+        }
+    }
+
+    fn forget(&self, transport: &dyn AdsTransport, value_name: &str, handle: u32) {
+        if let Ok(mut by_value_name) = self.by_value_name.lock() {
+            if by_value_name.get(value_name) == Some(&handle) {
+                by_value_name.remove(value_name);
+            }
+        }
+        release(transport, handle);
+    }
+
+    /// Releases every handle
+    pub(super) fn clear(&self) {
+        let handles = match self.by_value_name.lock() {
+            Ok(mut by_value_name) => by_value_name.drain().map(|(_, h)| h).collect(),
+            Err(_) => Vec::new(),
+        };
+        for handle in handles {
+            release(&*self.transport, handle);
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, u32>>> {
+        match self.by_value_name.lock() {
+            Ok(h) => Ok(h),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+    }
+}
+
+impl Drop for Handles {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+fn create(transport: &dyn AdsTransport, value_name: &str) -> Result<u32> {
+    let mut handle = [0; std::mem::size_of::<u32>()];
+    transport.read_write(
+        beckhoff::ADSIGRP_SYM_HNDBYNAME,
+        0,
+        &mut handle,
+        value_name.as_bytes(),
+    )?;
+    Ok(u32::from_le_bytes(handle))
+}
+
+/// A stale handle may already be gone, so failures are ignored
+fn release(transport: &dyn AdsTransport, handle: u32) {
+    let _ = transport.write(beckhoff::ADSIGRP_SYM_RELEASEHND, 0, &handle.to_le_bytes());
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use crate::mock::{Declaration, Server};
+    use crate::{Client, Variable};
+
+    #[test]
+    fn create_handles_again_after_online_change() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.flow", "DINT"))
+            .with_value("main.flow", Variable::I32(-7))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .connect()
+            .unwrap();
+
+        assert_eq!(client.get_value("main.flow").unwrap(), Variable::I32(-7));
+        let handle = client.handles().lock().unwrap()["main.flow"];
+
+        server.online_change().unwrap();
+
+        // The target rejects the old handle, so the symbols are uploaded again and a new handle is made
+        assert_eq!(client.get_value("main.flow").unwrap(), Variable::I32(-7));
+        assert_ne!(client.handles().lock().unwrap()["main.flow"], handle);
+        client.set_value("main.flow", Variable::I32(12)).unwrap();
+        assert_eq!(client.get_value("main.flow").unwrap(), Variable::I32(12));
+    }
+}
@@ -1,18 +1,47 @@
+#[cfg(feature = "tokio")]
+mod async_client;
+#[cfg(feature = "tokio")]
+pub use async_client::AsyncClient;
+#[cfg(all(feature = "tokio", feature = "notifications"))]
+pub use async_client::Subscription;
+mod access;
+mod ams_address;
+pub use ams_address::{AmsAddress, AmsNetId};
 mod beckhoff;
 mod client;
-pub use client::Client;
+pub use client::{Client, ClientBuilder};
+mod dereference;
+pub mod discovery;
+mod handles;
+#[cfg(feature = "mock")]
+pub mod mock;
 #[cfg(feature = "notifications")]
 mod notifications;
 #[cfg(feature = "notifications")]
 pub use notifications::{AdsTransmissionMode, Time};
+mod refresh;
+pub use refresh::SymbolsChangedCallback;
 mod result;
+mod retry;
+pub use retry::{ErrorClass, RetryPolicy};
+mod partial;
+pub use partial::PartialWrites;
+pub mod routes;
 mod rx;
 mod state;
 pub use state::State;
+mod sum;
 mod symbols_and_data_types;
+mod transport;
+#[cfg(feature = "tls")]
+pub use transport::tls::{TlsConfig, SECURE_ADS_PORT};
+pub use transport::AdsTransport;
+#[cfg(feature = "notifications")]
+pub use transport::{NotificationAttributes, NotificationCallback};
 mod tx;
+mod udp;
 mod variables;
-pub use variables::{StartIndex, Variable};
+pub use variables::{StartIndex, StringEncoding, Variable};
 mod verify;
 
 pub use twincat_derive::path_verify;
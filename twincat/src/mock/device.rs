@@ -0,0 +1,433 @@
+//! The mock's memory and ADS state, and how it answers each request
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::layout::{self, Layout, Location};
+use crate::beckhoff;
+use crate::routes::{self, Route};
+use crate::symbols_and_data_types::SymbolsAndDataTypes;
+use crate::transport::tcp::{self, Command};
+use crate::Variable;
+
+pub(super) struct Device {
+    layout: Layout,
+    symbol_upload: Vec<u8>,
+    data_type_upload: Vec<u8>,
+    symbols_and_data_types: SymbolsAndDataTypes,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    memory: Vec<u8>,
+    handles: HashMap<u32, Location>,
+    /// Handles from before an online change, which must be released
+    inactive_handles: HashSet<u32>,
+    next_handle: u32,
+    symbol_version: u8,
+    ads_state: u16,
+    device_state: u16,
+    /// How long to wait before answering each request
+    response_delay: Duration,
+    /// Of the router, which the system service lists and removes
+    routes: Vec<Route>,
+}
+
+/// An ADS error code
+pub(super) type AdsResult<T> = std::result::Result<T, u32>;
+
+impl Device {
+    pub(super) fn new(layout: Layout, ads_state: u16, routes: Vec<Route>) -> Result<Self> {
+        let symbol_upload = layout.symbol_upload();
+        let data_type_upload = layout.data_type_upload();
+        let upload_info = layout.upload_info(&symbol_upload, &data_type_upload);
+
+        // Values are encoded by the same code that a client uses, so parse the upload as a client would
+        let symbols_and_data_types = SymbolsAndDataTypes::from_bytes(
+            &symbol_upload,
+            tcp::u32_at(&upload_info, 0)?,
+            &data_type_upload,
+            tcp::u32_at(&upload_info, 8)?,
+        )?;
+
+        let memory = vec![0; layout.size_bytes()];
+
+        Ok(Self {
+            layout,
+            symbol_upload,
+            data_type_upload,
+            symbols_and_data_types,
+            inner: Mutex::new(Inner {
+                memory,
+                handles: HashMap::new(),
+                inactive_handles: HashSet::new(),
+                next_handle: 1,
+                symbol_version: 1,
+                ads_state,
+                device_state: 0,
+                response_delay: Duration::ZERO,
+                routes,
+            }),
+        })
+    }
+
+    pub(super) fn set_value(&self, path: &str, value: &Variable) -> Result<()> {
+        let (symbol, data_type) = self.symbols_and_data_types.get_symbol_and_data_type(path)?;
+        let bytes = value.to_bytes(self.symbols_and_data_types.data_types(), symbol, data_type)?;
+
+        let mut inner = self.lock()?;
+        let location = match self.layout.resolve(path, &inner.memory) {
+            Some(l) => l,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("Cannot find {path}"),
+                ))
+            }
+        };
+        if bytes.len() > location.size_bytes {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{path} has size {}, cannot write {} bytes",
+                    location.size_bytes,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        match symbol.bits() {
+            Some(bits) => {
+                let byte = &mut inner.memory[location.offset];
+                *byte = bits.set(*byte, bytes[0]);
+            }
+            None => {
+                inner.memory[location.offset..location.offset + bytes.len()].copy_from_slice(&bytes)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Points `pointer_path`, a `POINTER TO` or `REFERENCE TO`, at `target_path`
+    pub(super) fn set_pointer(&self, pointer_path: &str, target_path: &str) -> Result<()> {
+        let mut inner = self.lock()?;
+        let resolve = |path| match self.layout.resolve(path, &inner.memory) {
+            Some(l) => Ok(l),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Cannot find {path}"),
+            )),
+        };
+        let pointer = resolve(pointer_path)?;
+        let target = resolve(target_path)?;
+        if !pointer.data_type.starts_with("POINTER TO ")
+            && !pointer.data_type.starts_with("REFERENCE TO ")
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{pointer_path} is {}, not a pointer", pointer.data_type),
+            ));
+        }
+
+        let address = layout::address(target.offset).to_le_bytes();
+        inner.memory[pointer.offset..pointer.offset + address.len()].copy_from_slice(&address);
+        Ok(())
+    }
+
+    /// Existing handles become inactive, and the symbol version increases
+    pub(super) fn online_change(&self) -> Result<()> {
+        let mut inner = self.lock()?;
+        let handles = inner.handles.drain().map(|(h, _)| h).collect::<Vec<u32>>();
+        inner.inactive_handles.extend(handles);
+        inner.symbol_version = inner.symbol_version.wrapping_add(1);
+        Ok(())
+    }
+
+    pub(super) fn n_handles(&self) -> Result<usize> {
+        let inner = self.lock()?;
+        Ok(inner.handles.len() + inner.inactive_handles.len())
+    }
+
+    pub(super) fn set_response_delay(&self, delay: Duration) -> Result<()> {
+        self.lock()?.response_delay = delay;
+        Ok(())
+    }
+
+    pub(super) fn response_delay(&self) -> Duration {
+        self.lock().map_or(Duration::ZERO, |i| i.response_delay)
+    }
+
+    /// Returns the response after the ADS result code, or `None` if the command is not supported
+    pub(super) fn respond(&self, command_id: u16, data: &[u8]) -> Option<AdsResult<Vec<u8>>> {
+        match command_id {
+            c if c == Command::Read as u16 => Some(self.on_read(data)),
+            c if c == Command::Write as u16 => Some(self.on_write(data)),
+            c if c == Command::ReadWrite as u16 => Some(self.on_read_write(data)),
+            c if c == Command::ReadState as u16 => Some(self.on_read_state()),
+            c if c == Command::WriteControl as u16 => Some(self.on_write_control(data)),
+            _ => None,
+        }
+    }
+
+    fn on_read(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let mut read = self.read(u32_at(data, 0)?, u32_at(data, 4)?)?;
+        read.truncate(u32_at(data, 8)? as usize);
+        Ok(with_length(read))
+    }
+
+    fn on_write(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let write_data = slice_at(data, 12, u32_at(data, 8)?)?;
+        self.write(u32_at(data, 0)?, u32_at(data, 4)?, write_data)?;
+        Ok(Vec::new())
+    }
+
+    fn on_read_write(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let write_data = slice_at(data, 16, u32_at(data, 12)?)?;
+        let mut read = self.read_write(u32_at(data, 0)?, u32_at(data, 4)?, write_data)?;
+        read.truncate(u32_at(data, 8)? as usize);
+        Ok(with_length(read))
+    }
+
+    fn on_read_state(&self) -> AdsResult<Vec<u8>> {
+        let inner = self.lock_ads()?;
+        let mut response = inner.ads_state.to_le_bytes().to_vec();
+        response.extend(inner.device_state.to_le_bytes());
+        Ok(response)
+    }
+
+    fn on_write_control(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let ads_state = u16_at(data, 0)?;
+        let device_state = u16_at(data, 2)?;
+        let mut inner = self.lock_ads()?;
+        inner.ads_state = ads_state;
+        inner.device_state = device_state;
+        Ok(Vec::new())
+    }
+
+    pub(super) fn read(&self, index_group: u32, index_offset: u32) -> AdsResult<Vec<u8>> {
+        match index_group {
+            beckhoff::ADSIGRP_SYM_UPLOADINFO2 => Ok(self
+                .layout
+                .upload_info(&self.symbol_upload, &self.data_type_upload)),
+            beckhoff::ADSIGRP_SYM_UPLOAD => Ok(self.symbol_upload.clone()),
+            beckhoff::ADSIGRP_SYM_DT_UPLOAD => Ok(self.data_type_upload.clone()),
+            beckhoff::ADSIGRP_SYM_VERSION => Ok(vec![self.lock_ads()?.symbol_version]),
+            beckhoff::ADSIGRP_SYM_VALBYHND => {
+                let inner = self.lock_ads()?;
+                let location = inner.location(index_offset)?;
+                Ok(inner.memory[location.offset..location.offset + location.size_bytes].to_vec())
+            }
+            layout::INDEX_GROUP => {
+                let inner = self.lock_ads()?;
+                inner
+                    .memory
+                    .get(index_offset as usize..)
+                    .map(|m| m.to_vec())
+                    .ok_or(beckhoff::ADSERR_DEVICE_INVALIDOFFSET)
+            }
+            routes::INDEX_GROUP_ENUMERATE_REMOTE => self
+                .lock_ads()?
+                .routes
+                .get(index_offset as usize)
+                .map(|r| r.to_bytes())
+                .ok_or(beckhoff::ADSERR_DEVICE_NOTFOUND),
+            _ => Err(beckhoff::ADSERR_DEVICE_INVALIDGRP),
+        }
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> AdsResult<()> {
+        let mut inner = self.lock_ads()?;
+        match index_group {
+            beckhoff::ADSIGRP_SYM_RELEASEHND => {
+                let handle = u32_at(data, 0)?;
+                if inner.handles.remove(&handle).is_some() || inner.inactive_handles.remove(&handle)
+                {
+                    Ok(())
+                } else {
+                    Err(beckhoff::ADSERR_DEVICE_NOTFOUND)
+                }
+            }
+            beckhoff::ADSIGRP_SYM_VALBYHND => {
+                let location = inner.location(index_offset)?.clone();
+                if data.len() > location.size_bytes {
+                    return Err(beckhoff::ADSERR_DEVICE_INVALIDSIZE);
+                }
+                inner.memory[location.offset..location.offset + data.len()].copy_from_slice(data);
+                Ok(())
+            }
+            layout::INDEX_GROUP => {
+                match inner
+                    .memory
+                    .get_mut(index_offset as usize..index_offset as usize + data.len())
+                {
+                    Some(m) => {
+                        m.copy_from_slice(data);
+                        Ok(())
+                    }
+                    None => Err(beckhoff::ADSERR_DEVICE_INVALIDSIZE),
+                }
+            }
+            routes::INDEX_GROUP_DELETE_REMOTE => {
+                let name = name(data)?;
+                match inner.routes.iter().position(|r| r.name() == name) {
+                    Some(index) => {
+                        inner.routes.remove(index);
+                        Ok(())
+                    }
+                    None => Err(beckhoff::ADSERR_DEVICE_NOTFOUND),
+                }
+            }
+            _ => Err(beckhoff::ADSERR_DEVICE_INVALIDGRP),
+        }
+    }
+
+    fn read_write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> AdsResult<Vec<u8>> {
+        match index_group {
+            beckhoff::ADSIGRP_SUMUP_READ => self.sum_read(index_offset as usize, data),
+            beckhoff::ADSIGRP_SUMUP_WRITE => self.sum_write(index_offset as usize, data),
+            beckhoff::ADSIGRP_SUMUP_READWRITE => self.sum_read_write(index_offset as usize, data),
+            beckhoff::ADSIGRP_SYM_HNDBYNAME => {
+                let location = self.resolve(data)?;
+                let mut inner = self.lock_ads()?;
+                let handle = inner.next_handle;
+                inner.next_handle += 1;
+                inner.handles.insert(handle, location);
+                Ok(handle.to_le_bytes().to_vec())
+            }
+            beckhoff::ADSIGRP_SYM_INFOBYNAMEEX => {
+                let location = self.resolve(data)?;
+                Ok(layout::symbol_entry(&name(data)?, &location, false))
+            }
+            _ => Err(beckhoff::ADSERR_DEVICE_INVALIDGRP),
+        }
+    }
+
+    /// Each request is (index group, index offset, length).
+    /// Responds with the result of each request, then the data of each request.
+    fn sum_read(&self, n: usize, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let mut results = Vec::with_capacity(4 * n);
+        let mut output = Vec::new();
+        for i in 0..n {
+            let length = u32_at(data, 12 * i + 8)? as usize;
+            let mut read = match self.read(u32_at(data, 12 * i)?, u32_at(data, 12 * i + 4)?) {
+                Ok(r) => {
+                    results.extend(0u32.to_le_bytes());
+                    r
+                }
+                Err(e) => {
+                    results.extend(e.to_le_bytes());
+                    Vec::new()
+                }
+            };
+            read.resize(length, 0);
+            output.extend(read);
+        }
+        results.extend(output);
+        Ok(results)
+    }
+
+    /// Each request is (index group, index offset, length), followed by the data of each request.
+    /// Responds with the result of each request.
+    fn sum_write(&self, n: usize, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let mut output = Vec::with_capacity(4 * n);
+        let mut data_index = 12 * n;
+        for i in 0..n {
+            let length = u32_at(data, 12 * i + 8)?;
+            let write_data = slice_at(data, data_index, length)?;
+            data_index += length as usize;
+            let result = self.write(u32_at(data, 12 * i)?, u32_at(data, 12 * i + 4)?, write_data);
+            output.extend(result.err().unwrap_or(0).to_le_bytes());
+        }
+        Ok(output)
+    }
+
+    /// Each request is (index group, index offset, read length, write length),
+    /// followed by the write data of each request.
+    /// Responds with the result and read length of each request, then the data of each request.
+    fn sum_read_write(&self, n: usize, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let mut results = Vec::with_capacity(8 * n);
+        let mut output = Vec::new();
+        let mut data_index = 16 * n;
+        for i in 0..n {
+            let read_length = u32_at(data, 16 * i + 8)? as usize;
+            let write_length = u32_at(data, 16 * i + 12)?;
+            let write_data = slice_at(data, data_index, write_length)?;
+            data_index += write_length as usize;
+            match self.read_write(u32_at(data, 16 * i)?, u32_at(data, 16 * i + 4)?, write_data) {
+                Ok(mut r) => {
+                    r.truncate(read_length);
+                    results.extend(0u32.to_le_bytes());
+                    results.extend((r.len() as u32).to_le_bytes());
+                    output.extend(r);
+                }
+                Err(e) => {
+                    results.extend(e.to_le_bytes());
+                    results.extend(0u32.to_le_bytes());
+                }
+            }
+        }
+        results.extend(output);
+        Ok(results)
+    }
+
+    fn resolve(&self, name_bytes: &[u8]) -> AdsResult<Location> {
+        self.layout
+            .resolve(&name(name_bytes)?, &self.lock_ads()?.memory)
+            .ok_or(beckhoff::ADSERR_DEVICE_SYMBOLNOTFOUND)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Inner>> {
+        match self.inner.lock() {
+            Ok(i) => Ok(i),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+    }
+
+    fn lock_ads(&self) -> AdsResult<MutexGuard<'_, Inner>> {
+        self.inner.lock().map_err(|_| beckhoff::ADSERR_DEVICE_ERROR)
+    }
+}
+
+impl Inner {
+    fn location(&self, handle: u32) -> AdsResult<&Location> {
+        match self.handles.get(&handle) {
+            Some(l) => Ok(l),
+            None if self.inactive_handles.contains(&handle) => {
+                Err(beckhoff::ADSERR_DEVICE_SYMBOLNOTACTIVE)
+            }
+            None => Err(beckhoff::ADSERR_DEVICE_NOTFOUND),
+        }
+    }
+}
+
+/// Names may or may not be null-terminated
+fn name(bytes: &[u8]) -> AdsResult<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    match std::str::from_utf8(&bytes[..end]) {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => Err(beckhoff::ADSERR_DEVICE_INVALIDDATA),
+    }
+}
+
+fn with_length(data: Vec<u8>) -> Vec<u8> {
+    let mut output = (data.len() as u32).to_le_bytes().to_vec();
+    output.extend(data);
+    output
+}
+
+pub(super) fn u32_at(bytes: &[u8], index: usize) -> AdsResult<u32> {
+    tcp::u32_at(bytes, index).map_err(|_| beckhoff::ADSERR_DEVICE_INVALIDPARM)
+}
+
+fn u16_at(bytes: &[u8], index: usize) -> AdsResult<u16> {
+    tcp::u16_at(bytes, index).map_err(|_| beckhoff::ADSERR_DEVICE_INVALIDPARM)
+}
+
+fn slice_at(bytes: &[u8], index: usize, length: u32) -> AdsResult<&[u8]> {
+    bytes
+        .get(index..index + length as usize)
+        .ok_or(beckhoff::ADSERR_DEVICE_INVALIDSIZE)
+}
@@ -0,0 +1,814 @@
+//! Where each declared symbol lives in the mock's memory, and how it is described to a client
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use super::{Declaration, Enum};
+use crate::beckhoff;
+
+/// The index group that the mock's symbols are served from
+pub(super) const INDEX_GROUP: u32 = 0x4040;
+
+/// TwinCAT aligns each value to its own size, up to 8 bytes
+const MAX_ALIGNMENT: usize = 8;
+
+/// Pointers are 64-bit, as on an x64 runtime
+const POINTER_SIZE: usize = 8;
+/// The address of the start of the mock's memory, so that no symbol is at the null address
+const ADDRESS_BASE: u64 = 0x1000_0000;
+
+/// (name, ADS type, size)
+const BASE_TYPES: &[(&str, u32, usize)] = &[
+    ("BOOL", 33, 1),
+    ("BIT", 33, 1),
+    ("BYTE", 17, 1),
+    ("SINT", 16, 1),
+    ("USINT", 17, 1),
+    ("INT", 2, 2),
+    ("UINT", 18, 2),
+    ("WORD", 18, 2),
+    ("DINT", 3, 4),
+    ("UDINT", 19, 4),
+    ("DWORD", 19, 4),
+    ("LINT", 20, 8),
+    ("ULINT", 21, 8),
+    ("LWORD", 21, 8),
+    ("REAL", 4, 4),
+    ("LREAL", 5, 8),
+    ("TIME", 19, 4),
+    ("LTIME", 21, 8),
+    ("TOD", 19, 4),
+    ("TIME_OF_DAY", 19, 4),
+    ("DATE", 19, 4),
+    ("DT", 19, 4),
+    ("DATE_AND_TIME", 19, 4),
+];
+
+const STRING_LENGTH_DEFAULT: usize = 80;
+
+pub(super) struct Layout {
+    types: HashMap<String, Type>,
+    /// Data type names, in the order in which they are uploaded
+    type_names: Vec<String>,
+    symbols: Vec<Placed>,
+    size_bytes: usize,
+}
+
+struct Type {
+    ads_type: u32,
+    size_bytes: usize,
+    alignment: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    Base,
+    /// Described by its base type
+    Enum {
+        base: String,
+        members: Vec<(String, i64)>,
+    },
+    Array {
+        ranges: Vec<RangeInclusive<i32>>,
+        element: String,
+    },
+    Struct(Vec<Placed>),
+    /// Whose members are all at offset 0
+    Union(Vec<Placed>),
+    /// `POINTER TO` or `REFERENCE TO` this type
+    Pointer(String),
+    /// Another name for this type, which may be a subrange such as `INT(0..100)`
+    Alias(String),
+}
+
+/// A symbol or struct field, and its offset within its parent
+struct Placed {
+    name: String,
+    data_type: String,
+    offset: usize,
+    /// Of a `BIT` struct field, within the byte at `offset`
+    bit_offset: Option<usize>,
+    persistent: bool,
+}
+
+/// The memory behind an ADS path
+#[derive(Clone, Debug)]
+pub(super) struct Location {
+    pub(super) data_type: String,
+    pub(super) ads_type: u32,
+    pub(super) offset: usize,
+    pub(super) size_bytes: usize,
+}
+
+impl Layout {
+    pub(super) fn new(
+        enums: &[Enum],
+        structs: &[(String, Vec<Declaration>)],
+        unions: &[(String, Vec<Declaration>)],
+        aliases: &[(String, String)],
+        symbols: &[Declaration],
+    ) -> Result<Self> {
+        let mut layout = Self {
+            types: HashMap::new(),
+            type_names: Vec::new(),
+            symbols: Vec::new(),
+            size_bytes: 0,
+        };
+
+        let declared = Declared {
+            enums: enums.iter().map(|e| (e.0.as_str(), e)).collect(),
+            structs: structs
+                .iter()
+                .map(|(n, f)| (n.as_str(), f.as_slice()))
+                .collect(),
+            unions: unions
+                .iter()
+                .map(|(n, m)| (n.as_str(), m.as_slice()))
+                .collect(),
+            aliases: aliases
+                .iter()
+                .map(|(n, t)| (n.as_str(), t.as_str()))
+                .collect(),
+        };
+
+        let mut offset = 0;
+        for symbol in symbols {
+            check_not_bit(&symbol.data_type)?;
+            layout.add_type(&symbol.data_type, &declared, &mut Vec::new())?;
+            let data_type = &layout.types[&symbol.data_type];
+            offset = align(offset, data_type.alignment);
+            let size_bytes = data_type.size_bytes;
+            layout.symbols.push(Placed {
+                name: symbol.name.clone(),
+                data_type: symbol.data_type.clone(),
+                offset,
+                bit_offset: None,
+                persistent: symbol.persistent,
+            });
+            offset += size_bytes;
+        }
+        layout.size_bytes = offset;
+
+        Ok(layout)
+    }
+
+    /// `in_progress` guards against structs which contain themselves
+    fn add_type(
+        &mut self,
+        name: &str,
+        declared: &Declared,
+        in_progress: &mut Vec<String>,
+    ) -> Result<()> {
+        if self.types.contains_key(name) {
+            return Ok(());
+        }
+        if in_progress.iter().any(|n| n == name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{name} contains itself"),
+            ));
+        }
+        in_progress.push(name.to_string());
+
+        let data_type = if let Some(&(_, ads_type, size_bytes)) =
+            BASE_TYPES.iter().find(|(n, _, _)| *n == name)
+        {
+            Type {
+                ads_type,
+                size_bytes,
+                alignment: size_bytes,
+                kind: Kind::Base,
+            }
+        } else if let Some(length) = string_length(name, "STRING")? {
+            Type {
+                ads_type: 30,
+                size_bytes: length + 1,
+                alignment: 1,
+                kind: Kind::Base,
+            }
+        } else if let Some(length) = string_length(name, "WSTRING")? {
+            Type {
+                ads_type: 31,
+                size_bytes: 2 * (length + 1),
+                alignment: 2,
+                kind: Kind::Base,
+            }
+        } else if name.starts_with("ARRAY") {
+            let (ranges, element) = split_array(name)?;
+            check_not_bit(&element)?;
+            self.add_type(&element, declared, in_progress)?;
+            let element_type = &self.types[&element];
+            let n_elements = ranges
+                .iter()
+                .map(|r| (1 + r.end() - r.start()) as usize)
+                .product::<usize>();
+            Type {
+                ads_type: element_type.ads_type,
+                size_bytes: n_elements * element_type.size_bytes,
+                alignment: element_type.alignment,
+                kind: Kind::Array { ranges, element },
+            }
+        } else if let Some((_, base, members)) = declared.enums.get(name) {
+            self.add_type(base, declared, in_progress)?;
+            let base_type = &self.types[base];
+            if !members.is_empty() && !matches!(base_type.ads_type, 2 | 3 | 16..=21) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{name} has members, so its base type must be an integer"),
+                ));
+            }
+            Type {
+                ads_type: base_type.ads_type,
+                size_bytes: base_type.size_bytes,
+                alignment: base_type.alignment,
+                kind: Kind::Enum {
+                    base: base.to_string(),
+                    members: members.to_vec(),
+                },
+            }
+        } else if let Some(pointee) = pointee_name(name) {
+            // A struct may point to itself, so need not be complete yet
+            if !in_progress.iter().any(|n| n == pointee) {
+                self.add_type(pointee, declared, in_progress)?;
+            }
+            Type {
+                ads_type: 21,
+                size_bytes: POINTER_SIZE,
+                alignment: POINTER_SIZE,
+                kind: Kind::Pointer(pointee.to_string()),
+            }
+        } else if let Some(members) = declared.unions.get(name) {
+            // Every member starts at the first byte
+            let mut placed = Vec::new();
+            let mut size_bytes = 0;
+            let mut alignment = 1;
+            for member in members.iter() {
+                check_not_bit(&member.data_type)?;
+                self.add_type(&member.data_type, declared, in_progress)?;
+                let member_type = &self.types[&member.data_type];
+                size_bytes = size_bytes.max(member_type.size_bytes);
+                alignment = alignment.max(member_type.alignment);
+                placed.push(Placed {
+                    name: member.name.clone(),
+                    data_type: member.data_type.clone(),
+                    offset: 0,
+                    bit_offset: None,
+                    persistent: member.persistent,
+                });
+            }
+            Type {
+                ads_type: 65,
+                size_bytes: align(size_bytes, alignment),
+                alignment,
+                kind: Kind::Union(placed),
+            }
+        } else if let Some(fields) = declared.structs.get(name) {
+            let mut placed = Vec::new();
+            let mut offset = 0;
+            let mut alignment = 1;
+            // Consecutive `BIT`s share a byte, up to 8 of them
+            let mut bits_free = 0;
+            for field in fields.iter() {
+                self.add_type(&field.data_type, declared, in_progress)?;
+                let is_bit = field.data_type == "BIT";
+                if is_bit && bits_free > 0 {
+                    placed.push(Placed {
+                        name: field.name.clone(),
+                        data_type: field.data_type.clone(),
+                        offset: offset - 1,
+                        bit_offset: Some(8 - bits_free),
+                        persistent: field.persistent,
+                    });
+                    bits_free -= 1;
+                    continue;
+                }
+
+                let field_type = &self.types[&field.data_type];
+                offset = align(offset, field_type.alignment);
+                alignment = alignment.max(field_type.alignment);
+                bits_free = if is_bit { 7 } else { 0 };
+                placed.push(Placed {
+                    name: field.name.clone(),
+                    data_type: field.data_type.clone(),
+                    offset,
+                    bit_offset: is_bit.then_some(0),
+                    persistent: field.persistent,
+                });
+                offset += field_type.size_bytes;
+            }
+            Type {
+                ads_type: 65,
+                size_bytes: align(offset, alignment),
+                alignment,
+                kind: Kind::Struct(placed),
+            }
+        } else if let Some(target) = declared
+            .aliases
+            .get(name)
+            .copied()
+            .or_else(|| subrange_base(name))
+        {
+            let base = subrange_base(target).unwrap_or(target);
+            check_not_bit(base)?;
+            self.add_type(base, declared, in_progress)?;
+            let base_type = &self.types[base];
+            Type {
+                ads_type: base_type.ads_type,
+                size_bytes: base_type.size_bytes,
+                alignment: base_type.alignment,
+                kind: Kind::Alias(target.to_string()),
+            }
+        } else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{name} is neither a base type nor declared"),
+            ));
+        };
+
+        in_progress.pop();
+        self.types.insert(name.to_string(), data_type);
+        self.type_names.push(name.to_string());
+
+        Ok(())
+    }
+
+    pub(super) fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Symbol names and struct fields are matched regardless of case, as TwinCAT does.
+    /// `memory` holds the pointers which `^` follows.
+    pub(super) fn resolve(&self, path: &str, memory: &[u8]) -> Option<Location> {
+        let symbol = self
+            .symbols
+            .iter()
+            .filter(|s| {
+                path.get(..s.name.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(&s.name))
+                    && matches!(
+                        path[s.name.len()..].chars().next(),
+                        None | Some('.' | '[' | '^')
+                    )
+            })
+            .max_by_key(|s| s.name.len())?;
+
+        let mut data_type = &symbol.data_type;
+        let mut offset = symbol.offset;
+        let mut remainder = &path[symbol.name.len()..];
+
+        while !remainder.is_empty() {
+            let kind = &self.unaliased(data_type)?.kind;
+            if let Some(after_dot) = remainder.strip_prefix('.') {
+                let end = after_dot.find(['.', '[', '^']).unwrap_or(after_dot.len());
+                let fields = match kind {
+                    Kind::Struct(fields) | Kind::Union(fields) => fields,
+                    _ => return None,
+                };
+                let field = fields
+                    .iter()
+                    .find(|f| f.name.eq_ignore_ascii_case(&after_dot[..end]))?;
+                data_type = &field.data_type;
+                offset += field.offset;
+                remainder = &after_dot[end..];
+            } else if let Some(after_bracket) = remainder.strip_prefix('[') {
+                let end = after_bracket.find(']')?;
+                let (ranges, element) = match kind {
+                    Kind::Array { ranges, element } => (ranges, element),
+                    _ => return None,
+                };
+                let indices = after_bracket[..end]
+                    .split(',')
+                    .map(|i| i32::from_str(i.trim()).ok())
+                    .collect::<Option<Vec<i32>>>()?;
+                if indices.len() != ranges.len() {
+                    return None;
+                }
+                let mut index_flat = 0;
+                for (index, range) in indices.iter().zip(ranges) {
+                    if !range.contains(index) {
+                        return None;
+                    }
+                    index_flat = index_flat * (1 + range.end() - range.start()) as usize
+                        + (index - range.start()) as usize;
+                }
+                data_type = element;
+                offset += index_flat * self.types.get(element)?.size_bytes;
+                remainder = &after_bracket[end + 1..];
+            } else if let Some(after_caret) = remainder.strip_prefix('^') {
+                let pointee = match kind {
+                    Kind::Pointer(pointee) => pointee,
+                    _ => return None,
+                };
+                let mut address = [0; 8];
+                address.copy_from_slice(memory.get(offset..offset + POINTER_SIZE)?);
+                offset = offset_at(u64::from_le_bytes(address), memory.len())?;
+                data_type = pointee;
+                remainder = after_caret;
+            } else {
+                return None;
+            }
+        }
+
+        let resolved = self.types.get(data_type)?;
+        Some(Location {
+            data_type: data_type.clone(),
+            ads_type: resolved.ads_type,
+            offset,
+            size_bytes: resolved.size_bytes,
+        })
+    }
+
+    /// What an alias stands for, or else the type itself
+    fn unaliased(&self, name: &str) -> Option<&Type> {
+        let mut data_type = self.types.get(name)?;
+        while let Kind::Alias(target) = &data_type.kind {
+            data_type = self.types.get(subrange_base(target).unwrap_or(target))?;
+        }
+        Some(data_type)
+    }
+
+    /// The result of `ADSIGRP_SYM_UPLOADINFO2`
+    pub(super) fn upload_info(&self, symbol_upload: &[u8], data_type_upload: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(24);
+        output.extend((self.symbols.len() as u32).to_le_bytes());
+        output.extend((symbol_upload.len() as u32).to_le_bytes());
+        output.extend((self.type_names.len() as u32).to_le_bytes());
+        output.extend((data_type_upload.len() as u32).to_le_bytes());
+        output.extend(0u32.to_le_bytes());
+        output.extend(0u32.to_le_bytes());
+        output
+    }
+
+    /// The result of `ADSIGRP_SYM_UPLOAD`
+    pub(super) fn symbol_upload(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        for symbol in &self.symbols {
+            let data_type = &self.types[&symbol.data_type];
+            output.extend(symbol_entry(
+                &symbol.name,
+                &Location {
+                    data_type: symbol.data_type.clone(),
+                    ads_type: data_type.ads_type,
+                    offset: symbol.offset,
+                    size_bytes: data_type.size_bytes,
+                },
+                symbol.persistent,
+            ));
+        }
+        output
+    }
+
+    /// The result of `ADSIGRP_SYM_DT_UPLOAD`
+    pub(super) fn data_type_upload(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        for name in &self.type_names {
+            output.extend(self.data_type_entry(name));
+        }
+        output
+    }
+
+    fn data_type_entry(&self, name: &str) -> Vec<u8> {
+        let data_type = &self.types[name];
+
+        let (base_name, array_ranges, fields): (&str, &[RangeInclusive<i32>], &[Placed]) =
+            match &data_type.kind {
+                Kind::Base => ("", &[], &[]),
+                Kind::Enum { base, .. } => (base, &[], &[]),
+                Kind::Array { ranges, element } => (element, ranges, &[]),
+                Kind::Struct(fields) | Kind::Union(fields) => ("", &[], fields),
+                Kind::Pointer(pointee) => (pointee, &[], &[]),
+                Kind::Alias(target) => (target, &[], &[]),
+            };
+        let members: &[(String, i64)] = match &data_type.kind {
+            Kind::Enum { members, .. } => members,
+            _ => &[],
+        };
+
+        let mut tail = Vec::new();
+        for range in array_ranges {
+            tail.extend(range.start().to_le_bytes());
+            tail.extend(((1 + range.end() - range.start()) as u32).to_le_bytes());
+        }
+        for field in fields {
+            let field_type = &self.types[&field.data_type];
+            let mut flags = if field.persistent {
+                beckhoff::ADSDATATYPEFLAG_DATAITEM | beckhoff::ADSDATATYPEFLAG_PERSISTENT
+            } else {
+                beckhoff::ADSDATATYPEFLAG_DATAITEM
+            };
+            // The offset and size of a `BIT` are in bits
+            let (offset, size_bytes) = match field.bit_offset {
+                Some(bit_offset) => {
+                    flags |= beckhoff::ADSDATATYPEFLAG_BITVALUES;
+                    (8 * field.offset + bit_offset, 1)
+                }
+                None => (field.offset, field_type.size_bytes),
+            };
+            tail.extend(data_type_entry_bytes(
+                &DataTypeEntry {
+                    name: &field.name,
+                    base_name: &field.data_type,
+                    ads_type: field_type.ads_type,
+                    size_bytes,
+                    offset,
+                    flags,
+                    array_dimensions: 0,
+                    sub_items: 0,
+                },
+                &[],
+            ));
+        }
+        // Enum infos: the number of members, then each one's name length, name and value
+        let mut flags = beckhoff::ADSDATATYPEFLAG_DATATYPE;
+        if name.starts_with("REFERENCE TO ") {
+            flags |= beckhoff::ADSDATATYPEFLAG_REFERENCETO;
+        }
+        if let Kind::Union(_) = data_type.kind {
+            flags |= beckhoff::ADSDATATYPEFLAG_UNION;
+        }
+        if !members.is_empty() {
+            flags |= beckhoff::ADSDATATYPEFLAG_ENUMINFOS;
+            tail.extend((members.len() as u16).to_le_bytes());
+            for (member, value) in members {
+                tail.push(member.len() as u8);
+                tail.extend(member.as_bytes());
+                tail.push(0);
+                tail.extend(&value.to_le_bytes()[..data_type.size_bytes]);
+            }
+        }
+
+        data_type_entry_bytes(
+            &DataTypeEntry {
+                name,
+                base_name,
+                ads_type: data_type.ads_type,
+                size_bytes: data_type.size_bytes,
+                offset: 0,
+                flags,
+                array_dimensions: array_ranges.len(),
+                sub_items: fields.len(),
+            },
+            &tail,
+        )
+    }
+}
+
+struct Declared<'a> {
+    enums: HashMap<&'a str, &'a Enum>,
+    structs: HashMap<&'a str, &'a [Declaration]>,
+    unions: HashMap<&'a str, &'a [Declaration]>,
+    aliases: HashMap<&'a str, &'a str>,
+}
+
+struct DataTypeEntry<'a> {
+    name: &'a str,
+    base_name: &'a str,
+    ads_type: u32,
+    size_bytes: usize,
+    offset: usize,
+    flags: u32,
+    array_dimensions: usize,
+    sub_items: usize,
+}
+
+/// An `AdsSymbolEntry` followed by the name, type and (empty) comment
+pub(super) fn symbol_entry(name: &str, location: &Location, persistent: bool) -> Vec<u8> {
+    const DETAILS_LENGTH: usize = std::mem::size_of::<beckhoff::AdsSymbolEntry>();
+
+    let entry_length = DETAILS_LENGTH + name.len() + location.data_type.len() + 3;
+    let flags = if persistent {
+        beckhoff::ADSSYMBOLFLAG_PERSISTENT
+    } else {
+        0
+    };
+
+    let mut output = Vec::with_capacity(entry_length);
+    output.extend((entry_length as u32).to_le_bytes());
+    output.extend(INDEX_GROUP.to_le_bytes());
+    output.extend((location.offset as u32).to_le_bytes());
+    output.extend((location.size_bytes as u32).to_le_bytes());
+    output.extend(location.ads_type.to_le_bytes());
+    output.extend(flags.to_le_bytes());
+    output.extend((name.len() as u16).to_le_bytes());
+    output.extend((location.data_type.len() as u16).to_le_bytes());
+    output.extend(0u16.to_le_bytes());
+    output.extend(name.as_bytes());
+    output.push(0);
+    output.extend(location.data_type.as_bytes());
+    output.push(0);
+    output.push(0);
+    output
+}
+
+/// An `AdsDatatypeEntry` followed by the name, type, (empty) comment and `tail`
+fn data_type_entry_bytes(entry: &DataTypeEntry, tail: &[u8]) -> Vec<u8> {
+    const DETAILS_LENGTH: usize = std::mem::size_of::<beckhoff::AdsDatatypeEntry>();
+
+    let entry_length = DETAILS_LENGTH + entry.name.len() + entry.base_name.len() + 3 + tail.len();
+
+    let mut output = Vec::with_capacity(entry_length);
+    output.extend((entry_length as u32).to_le_bytes());
+    output.extend(1u32.to_le_bytes());
+    output.extend(0u32.to_le_bytes());
+    output.extend(0u32.to_le_bytes());
+    output.extend((entry.size_bytes as u32).to_le_bytes());
+    output.extend((entry.offset as u32).to_le_bytes());
+    output.extend(entry.ads_type.to_le_bytes());
+    output.extend(entry.flags.to_le_bytes());
+    output.extend((entry.name.len() as u16).to_le_bytes());
+    output.extend((entry.base_name.len() as u16).to_le_bytes());
+    output.extend(0u16.to_le_bytes());
+    output.extend((entry.array_dimensions as u16).to_le_bytes());
+    output.extend((entry.sub_items as u16).to_le_bytes());
+    output.extend(entry.name.as_bytes());
+    output.push(0);
+    output.extend(entry.base_name.as_bytes());
+    output.push(0);
+    output.push(0);
+    output.extend(tail);
+    output
+}
+
+/// What a pointer at `offset` holds, to point there
+pub(super) fn address(offset: usize) -> u64 {
+    ADDRESS_BASE + offset as u64
+}
+
+/// Where a pointer which holds `address` points, unless it is null or outside the memory
+fn offset_at(address: u64, memory_length: usize) -> Option<usize> {
+    let offset = address.checked_sub(ADDRESS_BASE)? as usize;
+    (offset < memory_length).then_some(offset)
+}
+
+fn pointee_name(name: &str) -> Option<&str> {
+    name.strip_prefix("POINTER TO ")
+        .or_else(|| name.strip_prefix("REFERENCE TO "))
+        .map(str::trim)
+}
+
+/// `INT` of a subrange such as `INT(0..100)`
+fn subrange_base(name: &str) -> Option<&str> {
+    let (base, bounds) = name.split_once('(')?;
+    bounds
+        .strip_suffix(')')?
+        .contains("..")
+        .then_some(base.trim())
+}
+
+/// TwinCAT only packs `BIT`s into the bytes of structs, and not of unions
+fn check_not_bit(data_type: &str) -> Result<()> {
+    if data_type == "BIT" {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "BIT is only allowed for struct fields",
+        ));
+    }
+    Ok(())
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    let alignment = alignment.clamp(1, MAX_ALIGNMENT);
+    offset.div_ceil(alignment) * alignment
+}
+
+/// The number of characters in `STRING` or `STRING(n)`, if `name` is such a type
+fn string_length(name: &str, string_type: &str) -> Result<Option<usize>> {
+    let remainder = match name.strip_prefix(string_type) {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    if remainder.is_empty() {
+        return Ok(Some(STRING_LENGTH_DEFAULT));
+    }
+    match remainder
+        .strip_prefix('(')
+        .and_then(|r| r.strip_suffix(')'))
+        .map(|n| usize::from_str(n.trim()))
+    {
+        Some(Ok(length)) => Ok(Some(length)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot parse the length of {name}"),
+        )),
+    }
+}
+
+/// Splits `ARRAY [a..b,c..d] OF X` into its outermost ranges and `X`
+fn split_array(name: &str) -> Result<(Vec<RangeInclusive<i32>>, String)> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("Cannot parse {name}"));
+
+    let square_start = name.find('[').ok_or_else(invalid)?;
+    let square_end = name.find(']').ok_or_else(invalid)?;
+    let element = name[square_end..]
+        .split_once(" OF ")
+        .map(|(_, e)| e.trim())
+        .ok_or_else(invalid)?;
+
+    let mut ranges = Vec::new();
+    for dimension in name
+        .get(square_start + 1..square_end)
+        .ok_or_else(invalid)?
+        .split(',')
+    {
+        let (start, end) = dimension.split_once("..").ok_or_else(invalid)?;
+        let start = i32::from_str(start.trim()).map_err(|_| invalid())?;
+        let end = i32::from_str(end.trim()).map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        ranges.push(RangeInclusive::new(start, end));
+    }
+
+    Ok((ranges, element.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn layout() -> Layout {
+        Layout::new(
+            &[(String::from("Colour"), String::from("INT"), Vec::new())],
+            &[(
+                String::from("Pixel"),
+                vec![
+                    Declaration::new("visible", "BOOL"),
+                    Declaration::new("colour", "Colour"),
+                    Declaration::new("brightness", "LREAL"),
+                ],
+            )],
+            &[],
+            &[],
+            &[
+                Declaration::new("screen.enabled", "BOOL"),
+                Declaration::new("screen.pixels", "ARRAY [0..1,1..3] OF Pixel"),
+                Declaration::new("screen.title", "STRING(7)"),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn place_symbols() {
+        let layout = layout();
+
+        let pixel = &layout.types["Pixel"];
+        assert_eq!(pixel.size_bytes, 16);
+        assert_eq!(pixel.alignment, 8);
+
+        let pixels = layout.resolve("screen.pixels", &[]).unwrap();
+        assert_eq!(pixels.offset, 8);
+        assert_eq!(pixels.size_bytes, 96);
+        assert_eq!(pixels.ads_type, 65);
+
+        let title = layout.resolve("screen.title", &[]).unwrap();
+        assert_eq!(title.offset, 104);
+        assert_eq!(title.size_bytes, 8);
+
+        assert_eq!(layout.size_bytes(), 112);
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let layout = layout();
+
+        let colour = layout.resolve("screen.pixels[1,2].colour", &[]).unwrap();
+        assert_eq!(colour.offset, 8 + 4 * 16 + 2);
+        assert_eq!(colour.data_type, "Colour");
+        assert_eq!(colour.ads_type, 2);
+
+        assert_eq!(
+            layout.resolve("SCREEN.Pixels[0,1]", &[]).unwrap().offset,
+            layout.resolve("screen.pixels", &[]).unwrap().offset
+        );
+
+        assert!(layout.resolve("screen", &[]).is_none());
+        assert!(layout.resolve("screen.pixels[2,1]", &[]).is_none());
+        assert!(layout.resolve("screen.pixels[0]", &[]).is_none());
+        assert!(layout.resolve("screen.pixels[0,1].hue", &[]).is_none());
+        assert!(layout.resolve("screen.enabled[0]", &[]).is_none());
+    }
+
+    #[test]
+    fn reject_unknown_types() {
+        assert!(Layout::new(&[], &[], &[], &[], &[Declaration::new("a", "Unknown")]).is_err());
+        assert!(Layout::new(
+            &[],
+            &[(String::from("Loop"), vec![Declaration::new("l", "Loop")])],
+            &[],
+            &[],
+            &[Declaration::new("a", "Loop")]
+        )
+        .is_err());
+        assert!(Layout::new(
+            &[],
+            &[],
+            &[],
+            &[(String::from("Echo"), String::from("Echo"))],
+            &[Declaration::new("a", "Echo")]
+        )
+        .is_err());
+    }
+}
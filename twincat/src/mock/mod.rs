@@ -0,0 +1,605 @@
+//! An in-process ADS server, so that code which takes a `Client` can be tested without a TwinCAT runtime.
+//!
+//! The server is described by its enums, structs, unions, aliases and symbols, much as they are declared in Structured Text.
+//! It answers the symbol and data type uploads, handles, value reads and writes (alone or in
+//! sum commands), notifications, ADS state requests, and the router's list of routes.
+//! With the `tls` feature it can accept only Secure ADS connections.
+//! A `Responder` answers discovery and route requests over UDP.
+//!
+//! ```
+//! use twincat::mock::{Declaration, Server};
+//! use twincat::{Client, Variable};
+//!
+//! let server = Server::builder()
+//!     .with_symbol(Declaration::new("main.counter", "UDINT"))
+//!     .with_value("main.counter", Variable::U32(8))
+//!     .start()
+//!     .unwrap();
+//!
+//! let client = Client::builder()
+//!     .with_tcp_target(server.address())
+//!     .connect()
+//!     .unwrap();
+//!
+//! assert_eq!(client.get_value("main.counter").unwrap(), Variable::U32(8));
+//! ```
+
+use std::io::Result;
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(feature = "tls")]
+use openssl::ssl::{SslContext, SslContextBuilder, SslMethod, SslVerifyMode};
+#[cfg(feature = "tls")]
+use openssl::{pkey::PKey, x509::X509};
+
+use super::routes::Route;
+use super::{State, Variable};
+
+mod device;
+use device::Device;
+mod layout;
+use layout::Layout;
+mod responder;
+pub use responder::{Responder, ResponderBuilder};
+mod session;
+
+pub struct ServerBuilder {
+    enums: Vec<Enum>,
+    structs: Vec<(String, Vec<Declaration>)>,
+    unions: Vec<(String, Vec<Declaration>)>,
+    /// Aliases and the types they stand for
+    aliases: Vec<(String, String)>,
+    symbols: Vec<Declaration>,
+    values: Vec<(String, Variable)>,
+    /// Pointers and what they point to
+    pointers: Vec<(String, String)>,
+    ads_state: State,
+    routes: Vec<Route>,
+    #[cfg(feature = "tls")]
+    tls: Tls,
+}
+
+/// What the server needs to accept Secure ADS connections
+#[cfg(feature = "tls")]
+#[derive(Clone, Default)]
+struct Tls {
+    certificate: Option<(Vec<u8>, Vec<u8>)>,
+    client_ca: Option<Vec<u8>>,
+    psk: Option<(String, Vec<u8>)>,
+}
+
+/// An enum's name, base type and members
+type Enum = (String, String, Vec<(String, i64)>);
+
+/// A symbol or struct field: its name and data type, as it would be declared in Structured Text
+#[derive(Clone, Debug)]
+pub struct Declaration {
+    name: String,
+    data_type: String,
+    persistent: bool,
+}
+
+impl Declaration {
+    /// `data_type` may be a base type (including `STRING(n)` and subranges such as `INT(0..100)`),
+    /// a declared enum, struct, union or alias, an array of any of these, such as `ARRAY [0..3,1..2] OF Food`,
+    /// or a `POINTER TO` or `REFERENCE TO` any of these
+    pub fn new(name: impl Into<String>, data_type: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            data_type: data_type.into(),
+            persistent: false,
+        }
+    }
+
+    pub fn persistent(mut self) -> Self {
+        self.persistent = true;
+        self
+    }
+}
+
+impl ServerBuilder {
+    /// An enum whose members are not described, so that its values read as its base type
+    pub fn with_enum(mut self, name: impl Into<String>, base_type: impl Into<String>) -> Self {
+        self.enums.push((name.into(), base_type.into(), Vec::new()));
+        self
+    }
+
+    /// An enum whose values read as `Variable::Enum`, such as
+    /// `with_enum_members("Food", "INT", &[("Carrot", 0), ("Potato", 1)])`
+    pub fn with_enum_members(
+        mut self,
+        name: impl Into<String>,
+        base_type: impl Into<String>,
+        members: &[(&str, i64)],
+    ) -> Self {
+        let members = members
+            .iter()
+            .map(|(member, value)| (member.to_string(), *value))
+            .collect();
+        self.enums.push((name.into(), base_type.into(), members));
+        self
+    }
+
+    /// Function blocks are declared as structs.
+    /// Fields are laid out in order, each aligned to its own size (up to 8 bytes).
+    pub fn with_struct(mut self, name: impl Into<String>, fields: &[Declaration]) -> Self {
+        self.structs.push((name.into(), fields.to_vec()));
+        self
+    }
+
+    /// Members all start at the first byte, and the union is as large as its largest member
+    pub fn with_union(mut self, name: impl Into<String>, members: &[Declaration]) -> Self {
+        self.unions.push((name.into(), members.to_vec()));
+        self
+    }
+
+    /// Another name for a type, as `TYPE Percent : INT(0..100); END_TYPE` declares.
+    /// The type may be a subrange, whose bounds writes are then checked against.
+    pub fn with_alias(mut self, name: impl Into<String>, data_type: impl Into<String>) -> Self {
+        self.aliases.push((name.into(), data_type.into()));
+        self
+    }
+
+    /// Symbol names include their program or global variable list, such as `main.kitchen`
+    pub fn with_symbol(mut self, symbol: Declaration) -> Self {
+        self.symbols.push(symbol);
+        self
+    }
+
+    /// Sets an initial value; everything else starts as zero
+    pub fn with_value(mut self, value_name: impl Into<String>, value: Variable) -> Self {
+        self.values.push((value_name.into(), value));
+        self
+    }
+
+    /// Points a `POINTER TO` or `REFERENCE TO` at another value, as `ADR` or `REF=` would;
+    /// everything else is null
+    pub fn with_pointer(
+        mut self,
+        pointer_name: impl Into<String>,
+        target_name: impl Into<String>,
+    ) -> Self {
+        self.pointers
+            .push((pointer_name.into(), target_name.into()));
+        self
+    }
+
+    /// The initial ADS state; defaults to `Run`
+    pub fn with_ads_state(mut self, state: State) -> Self {
+        self.ads_state = state;
+        self
+    }
+
+    /// A route for `routes::list_routes` to find, whichever AMS port it is asked for on
+    pub fn with_route(mut self, route: Route) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Accepts only Secure ADS connections, presenting this certificate (PEM)
+    #[cfg(feature = "tls")]
+    pub fn with_tls_certificate(
+        mut self,
+        certificate_pem: impl Into<Vec<u8>>,
+        private_key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.tls.certificate = Some((certificate_pem.into(), private_key_pem.into()));
+        self
+    }
+
+    /// Only accepts Secure ADS clients with a certificate signed by this CA (PEM)
+    #[cfg(feature = "tls")]
+    pub fn with_tls_client_ca(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.tls.client_ca = Some(pem.into());
+        self
+    }
+
+    /// Accepts only Secure ADS connections, from clients which have this pre-shared key
+    #[cfg(feature = "tls")]
+    pub fn with_tls_psk(mut self, identity: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        self.tls.psk = Some((identity.into(), key.into()));
+        self
+    }
+
+    /// Listens on an unused port on localhost
+    pub fn start(&self) -> Result<Server> {
+        let layout = Layout::new(
+            &self.enums,
+            &self.structs,
+            &self.unions,
+            &self.aliases,
+            &self.symbols,
+        )?;
+        let device = Device::new(
+            layout,
+            self.ads_state.to_beckhoff() as u16,
+            self.routes.clone(),
+        )?;
+        for (value_name, value) in &self.values {
+            device.set_value(value_name, value)?;
+        }
+        for (pointer_name, target_name) in &self.pointers {
+            device.set_pointer(pointer_name, target_name)?;
+        }
+        let device = Arc::new(device);
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Mutex::new(Vec::new()));
+
+        let accept_device = device.clone();
+        let accept_stop = stop.clone();
+        let accept_connections = connections.clone();
+        #[cfg(feature = "tls")]
+        let tls_context = self.tls.context()?;
+        let accept_thread = thread::spawn(move || {
+            accept(
+                listener,
+                accept_device,
+                accept_stop,
+                accept_connections,
+                #[cfg(feature = "tls")]
+                tls_context,
+            )
+        });
+
+        Ok(Server {
+            address,
+            device,
+            stop,
+            connections,
+            accept_thread: Some(accept_thread),
+        })
+    }
+}
+
+/// Stops listening, and closes all of its connections, when dropped
+pub struct Server {
+    address: SocketAddr,
+    device: Arc<Device>,
+    stop: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            enums: Vec::new(),
+            structs: Vec::new(),
+            unions: Vec::new(),
+            aliases: Vec::new(),
+            symbols: Vec::new(),
+            values: Vec::new(),
+            pointers: Vec::new(),
+            ads_state: State::Run,
+            routes: Vec::new(),
+            #[cfg(feature = "tls")]
+            tls: Tls::default(),
+        }
+    }
+
+    /// The address to give to `ClientBuilder::with_tcp_target`
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Closes every connection, as the AMS router does when the runtime restarts.
+    /// The server goes on listening.
+    pub fn close_connections(&self) {
+        if let Ok(mut connections) = self.connections.lock() {
+            for connection in connections.drain(..) {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Acts as though the PLC program had been changed online:
+    /// existing handles report `ADSERR_DEVICE_SYMBOLNOTACTIVE`, and the symbol version increases.
+    /// Symbols, data types and values are unchanged.
+    pub fn online_change(&self) -> Result<()> {
+        self.device.online_change()
+    }
+
+    /// Points `pointer_name`, a `POINTER TO` or `REFERENCE TO`, at `target_name`,
+    /// as the PLC program would when it assigns the pointer
+    pub fn set_pointer(&self, pointer_name: &str, target_name: &str) -> Result<()> {
+        self.device.set_pointer(pointer_name, target_name)
+    }
+
+    /// The number of symbol handles which have been created and not yet released
+    pub fn n_handles(&self) -> Result<usize> {
+        self.device.n_handles()
+    }
+
+    /// Answers each request `delay` late, as a busy target would, so that timeouts can be tested.
+    /// Requests on one connection are answered in turn, so each waits for those before it.
+    pub fn set_response_delay(&self, delay: Duration) -> Result<()> {
+        self.device.set_response_delay(delay)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept loop so that it sees the stop flag
+        let _ = TcpStream::connect(self.address);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+
+        self.close_connections();
+    }
+}
+
+fn accept(
+    listener: TcpListener,
+    device: Arc<Device>,
+    stop: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+    #[cfg(feature = "tls")] tls_context: Option<SslContext>,
+) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        let _ = stream.set_nodelay(true);
+
+        match (stream.try_clone(), connections.lock()) {
+            (Ok(clone), Ok(mut connections)) => connections.push(clone),
+            _ => continue,
+        }
+
+        let device = device.clone();
+        #[cfg(feature = "tls")]
+        if let Some(tls_context) = tls_context.clone() {
+            thread::spawn(move || {
+                let ams_net_id = crate::AmsNetId::default();
+                if let Ok((reader, writer)) =
+                    crate::transport::tls::accept(&tls_context, stream, ams_net_id)
+                {
+                    session::serve(device, reader, Box::new(writer));
+                }
+            });
+            continue;
+        }
+        thread::spawn(move || {
+            if let Ok(writer) = stream.try_clone() {
+                session::serve(device, stream, Box::new(writer));
+            }
+        });
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Tls {
+    fn context(&self) -> Result<Option<SslContext>> {
+        if self.certificate.is_none() && self.psk.is_none() {
+            return Ok(None);
+        }
+
+        let mut builder = SslContextBuilder::new(SslMethod::tls_server())?;
+        if let Some((certificate, private_key)) = &self.certificate {
+            let certificate = X509::from_pem(certificate)?;
+            let private_key = PKey::private_key_from_pem(private_key)?;
+            builder.set_certificate(&certificate)?;
+            builder.set_private_key(&private_key)?;
+        }
+        if let Some(client_ca) = &self.client_ca {
+            for certificate in X509::stack_from_pem(client_ca)? {
+                builder.cert_store_mut().add_cert(certificate)?;
+            }
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+        if let Some((identity, key)) = self.psk.clone() {
+            crate::transport::tls::limit_to_psk(&mut builder)?;
+            builder.set_psk_server_callback(move |_, client_identity, key_buffer| {
+                // Unknown identities are given no key, which fails the handshake
+                if client_identity != Some(identity.as_bytes()) || key.len() > key_buffer.len() {
+                    return Ok(0);
+                }
+                key_buffer[..key.len()].copy_from_slice(&key);
+                Ok(key.len())
+            });
+        }
+        Ok(Some(builder.build()))
+    }
+}
+
+/// A server of `symbols`, each a name and a data type, which hold `values`,
+/// for the tests around the crate
+#[cfg(test)]
+pub(crate) fn fixture(symbols: &[(&str, &str)], values: &[(&str, Variable)]) -> ServerBuilder {
+    let builder = symbols
+        .iter()
+        .fold(Server::builder(), |builder, (name, data_type)| {
+            builder.with_symbol(Declaration::new(*name, *data_type))
+        });
+    values.iter().fold(builder, |builder, (name, value)| {
+        builder.with_value(*name, value.clone())
+    })
+}
+
+/// A client of `server`, with the default settings, for the tests around the crate
+#[cfg(test)]
+pub(crate) fn connect(server: &Server) -> crate::Client {
+    crate::Client::builder()
+        .with_tcp_target(server.address())
+        .connect()
+        .unwrap()
+}
+
+/// As `connect`, for an `AsyncClient`
+#[cfg(all(test, feature = "tokio"))]
+pub(crate) async fn connect_async(server: &Server) -> crate::AsyncClient {
+    crate::Client::builder()
+        .with_tcp_target(server.address())
+        .connect_async()
+        .await
+        .unwrap()
+}
+
+/// A struct of `fields`, in order
+#[cfg(test)]
+pub(crate) fn struct_of(fields: &[(&str, Variable)]) -> Variable {
+    Variable::Struct(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{StartIndex, Variable as V};
+
+    fn server() -> Server {
+        Server::builder()
+            .with_enum("Food", "INT")
+            .with_struct(
+                "Fridge",
+                &[
+                    Declaration::new("is_on", "BOOL"),
+                    Declaration::new("shelf", "ARRAY [1..3] OF Food"),
+                    Declaration::new("temperature_oc", "REAL"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.fridge", "Fridge").persistent())
+            .with_symbol(Declaration::new("main.name", "STRING(12)"))
+            .with_symbol(Declaration::new(
+                "main.grid",
+                "ARRAY [0..1] OF ARRAY [0..2,0..1] OF UDINT",
+            ))
+            .with_value("main.name", V::String(String::from("Larder")))
+            .with_value("main.fridge.shelf[2]", V::I16(5))
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn upload_symbols_and_data_types() {
+        let server = server();
+        let client = connect(&server);
+
+        assert_eq!(
+            client.symbols_and_data_types().persistent(),
+            vec![String::from("main.fridge")]
+        );
+        assert!(client.verify_ads_path("main.fridge.shelf[3]").is_ok());
+        assert!(client.verify_ads_path("main.grid[1][2,0]").is_ok());
+        assert!(client.verify_ads_path("main.fridge.door").is_err());
+    }
+
+    #[test]
+    fn get_and_set_values() {
+        let server = server();
+        let client = connect(&server);
+
+        assert_eq!(
+            client.get_value("main.name").unwrap(),
+            V::String(String::from("Larder"))
+        );
+        assert_eq!(
+            client.get_value("main.fridge").unwrap(),
+            V::Struct(vec![
+                (String::from("is_on"), V::Bool(false)),
+                (
+                    String::from("shelf"),
+                    V::Array(StartIndex::Some(1), vec![V::I16(0), V::I16(5), V::I16(0)])
+                ),
+                (String::from("temperature_oc"), V::F32(0.0)),
+            ])
+        );
+
+        client
+            .set_value("main.fridge.temperature_oc", V::F32(4.5))
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.fridge.temperature_oc").unwrap(),
+            V::F32(4.5)
+        );
+
+        client.set_value("main.grid[1][2,0]", V::U32(77)).unwrap();
+        client
+            .set_value_from_str("main.grid[0][1,1]", "12")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.grid").unwrap(),
+            V::Array(
+                StartIndex::Some(0),
+                vec![
+                    V::Array(
+                        StartIndex::Some(0),
+                        vec![
+                            V::Array(StartIndex::Some(0), vec![V::U32(0), V::U32(0)]),
+                            V::Array(StartIndex::Some(0), vec![V::U32(0), V::U32(12)]),
+                            V::Array(StartIndex::Some(0), vec![V::U32(0), V::U32(0)]),
+                        ]
+                    ),
+                    V::Array(
+                        StartIndex::Some(0),
+                        vec![
+                            V::Array(StartIndex::Some(0), vec![V::U32(0), V::U32(0)]),
+                            V::Array(StartIndex::Some(0), vec![V::U32(0), V::U32(0)]),
+                            V::Array(StartIndex::Some(0), vec![V::U32(77), V::U32(0)]),
+                        ]
+                    ),
+                ]
+            )
+        );
+
+        assert!(client
+            .set_value("main.name", V::String(String::from("A name much too long")))
+            .is_err());
+    }
+
+    #[test]
+    fn get_and_set_ads_state() {
+        let server = Server::builder()
+            .with_ads_state(State::Stop)
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        assert_eq!(client.get_ads_state().unwrap(), State::Stop);
+        client.set_ads_state(State::Run).unwrap();
+        assert_eq!(client.get_ads_state().unwrap(), State::Run);
+    }
+
+    #[test]
+    fn reject_unknown_values() {
+        assert!(Server::builder()
+            .with_symbol(Declaration::new("main.count", "UINT"))
+            .with_value("main.total", V::U16(1))
+            .start()
+            .is_err());
+        assert!(Server::builder()
+            .with_symbol(Declaration::new("main.count", "UINT"))
+            .with_value("main.count", V::I16(1))
+            .start()
+            .is_err());
+    }
+
+    #[test]
+    fn close_connections_when_dropped() {
+        let server = server();
+        let client = connect(&server);
+
+        drop(server);
+
+        assert!(client.get_value("main.name").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! A stand-in for the part of a TwinCAT router which answers on UDP port 48899
+
+use std::io::{Error, Result};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::beckhoff;
+use crate::discovery::{OsVersion, TwinCatVersion};
+use crate::routes::Route;
+use crate::udp::{self, tag, Message, Service};
+use crate::AmsNetId;
+
+/// How often the stop flag is checked
+const POLL: Duration = Duration::from_millis(20);
+
+pub struct ResponderBuilder {
+    hostname: String,
+    ams_net_id: AmsNetId,
+    twincat_version: TwinCatVersion,
+    os_version: OsVersion,
+    username: String,
+    password: String,
+}
+
+impl ResponderBuilder {
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    pub fn with_ams_net_id(mut self, ams_net_id: AmsNetId) -> Self {
+        self.ams_net_id = ams_net_id;
+        self
+    }
+
+    pub fn with_twincat_version(mut self, twincat_version: TwinCatVersion) -> Self {
+        self.twincat_version = twincat_version;
+        self
+    }
+
+    pub fn with_os_version(mut self, os_version: OsVersion) -> Self {
+        self.os_version = os_version;
+        self
+    }
+
+    /// Which requests to add a route must give
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = username.into();
+        self.password = password.into();
+        self
+    }
+
+    /// Listens on an unused UDP port on localhost
+    pub fn start(self) -> Result<Responder> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        socket.set_read_timeout(Some(POLL))?;
+        let address = socket.local_addr()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let routes = Arc::new(Mutex::new(Vec::new()));
+        let thread_stop = stop.clone();
+        let thread_routes = routes.clone();
+        let thread = thread::spawn(move || self.serve(socket, thread_stop, thread_routes));
+
+        Ok(Responder {
+            address,
+            routes,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn serve(self, socket: UdpSocket, stop: Arc<AtomicBool>, routes: Arc<Mutex<Vec<Route>>>) {
+        let mut buffer = [0; 2048];
+        while !stop.load(Ordering::SeqCst) {
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let request = match Message::from_bytes(&buffer[..length]) {
+                Ok(r) if !r.is_response => r,
+                _ => continue,
+            };
+            let response = match request.service {
+                Service::Identify => self.identify(&request),
+                Service::AddRoute => self.add_route(&request, &routes),
+            };
+            let _ = socket.send_to(&response.to_bytes(), source);
+        }
+    }
+
+    fn identify(&self, request: &Message) -> Message {
+        let mut twincat_version = vec![self.twincat_version.major, self.twincat_version.minor];
+        twincat_version.extend(self.twincat_version.build.to_le_bytes());
+
+        // OSVERSIONINFO, without the service pack string
+        let mut os_version = Vec::new();
+        for field in [
+            20,
+            self.os_version.major,
+            self.os_version.minor,
+            self.os_version.build,
+            self.os_version.platform,
+        ] {
+            os_version.extend(field.to_le_bytes());
+        }
+
+        Message {
+            invoke_id: request.invoke_id,
+            service: Service::Identify,
+            is_response: true,
+            ams_net_id: self.ams_net_id,
+            ams_port: 10000,
+            tags: vec![
+                (tag::HOSTNAME, udp::string_tag(&self.hostname)),
+                (tag::TWINCAT_VERSION, twincat_version),
+                (tag::OS_VERSION, os_version),
+            ],
+        }
+    }
+
+    /// Adds the route if the credentials match, and answers with the ADS result
+    fn add_route(&self, request: &Message, routes: &Mutex<Vec<Route>>) -> Message {
+        let string = |id| request.tag(id).map(udp::tag_string).unwrap_or_default();
+        let ams_net_id = match request.tag(tag::AMS_NET_ID) {
+            Some(&[a, b, c, d, e, f]) => Some(AmsNetId::new([a, b, c, d, e, f])),
+            _ => None,
+        };
+
+        let status =
+            if string(tag::USERNAME) != self.username || string(tag::PASSWORD) != self.password {
+                beckhoff::ADSERR_DEVICE_ACCESSDENIED
+            } else {
+                match (ams_net_id, routes.lock()) {
+                    (Some(ams_net_id), Ok(mut routes)) => {
+                        let name = string(tag::ROUTE_NAME);
+                        routes.retain(|r| r.name() != name);
+                        routes.push(Route::new(name, ams_net_id, string(tag::HOSTNAME)));
+                        0
+                    }
+                    (None, _) => beckhoff::ADSERR_DEVICE_INVALIDDATA,
+                    (_, Err(_)) => beckhoff::ADSERR_DEVICE_ERROR,
+                }
+            };
+
+        Message {
+            invoke_id: request.invoke_id,
+            service: Service::AddRoute,
+            is_response: true,
+            ams_net_id: self.ams_net_id,
+            ams_port: 10000,
+            tags: vec![(tag::STATUS, status.to_le_bytes().to_vec())],
+        }
+    }
+}
+
+/// Answers discovery and route requests, as a TwinCAT device would, until dropped
+pub struct Responder {
+    address: SocketAddr,
+    routes: Arc<Mutex<Vec<Route>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Responder {
+    /// Defaults to `MOCK` at 127.0.0.1.1.1, running TwinCAT 3.1.4024 on Windows 10,
+    /// with the credentials `Administrator` and `1`
+    pub fn builder() -> ResponderBuilder {
+        ResponderBuilder {
+            hostname: String::from("MOCK"),
+            ams_net_id: AmsNetId::new([127, 0, 0, 1, 1, 1]),
+            twincat_version: TwinCatVersion {
+                major: 3,
+                minor: 1,
+                build: 4024,
+            },
+            os_version: OsVersion {
+                platform: 2,
+                major: 10,
+                minor: 0,
+                build: 19045,
+            },
+            username: String::from("Administrator"),
+            password: String::from("1"),
+        }
+    }
+
+    /// The address to give to `discovery::discover_at` or `routes::add_remote_route_at`
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The routes added so far
+    pub fn routes(&self) -> Result<Vec<Route>> {
+        match self.routes.lock() {
+            Ok(r) => Ok(r.clone()),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
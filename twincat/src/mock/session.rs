@@ -0,0 +1,247 @@
+//! One client connection, and the notifications it has requested
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::device::{self, AdsResult, Device};
+use crate::beckhoff;
+use crate::transport::tcp::{self, AmsHeader, Command};
+
+const STATE_FLAG_ADS_RESPONSE: u16 = 0x0005;
+
+/// How often values are checked for notifications
+const NOTIFICATION_POLL: Duration = Duration::from_millis(5);
+
+/// 100ns intervals from 1601 (FILETIME) to 1970
+const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+
+struct Session {
+    device: Arc<Device>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    /// The addresses of the last request, to send notifications from and to
+    addresses: Mutex<Option<(beckhoff::AmsAddr, beckhoff::AmsAddr)>>,
+    notifications: Mutex<HashMap<u32, Notification>>,
+    next_notification_handle: Mutex<u32>,
+    closed: AtomicBool,
+}
+
+struct Notification {
+    index_group: u32,
+    index_offset: u32,
+    length: usize,
+    on_change: bool,
+    cycle_time: Duration,
+    last_sent: Option<(Instant, Vec<u8>)>,
+}
+
+/// Answers requests until the connection closes
+pub(super) fn serve(device: Arc<Device>, mut reader: impl Read, writer: Box<dyn Write + Send>) {
+    let session = Arc::new(Session {
+        device,
+        writer: Mutex::new(writer),
+        addresses: Mutex::new(None),
+        notifications: Mutex::new(HashMap::new()),
+        next_notification_handle: Mutex::new(1),
+        closed: AtomicBool::new(false),
+    });
+
+    let notifier = session.clone();
+    thread::spawn(move || notifier.notify());
+
+    while let Ok((header, data)) = tcp::read_frame(&mut reader) {
+        if let Ok(mut addresses) = session.addresses.lock() {
+            *addresses = Some((header.source, header.target));
+        }
+
+        let (error_code, response) = match session.respond(header.command_id, &data) {
+            Some(Ok(r)) => {
+                let mut response = 0u32.to_le_bytes().to_vec();
+                response.extend(r);
+                (0, response)
+            }
+            Some(Err(e)) => (0, e.to_le_bytes().to_vec()),
+            None => (
+                beckhoff::ADSERR_DEVICE_SRVNOTSUPP,
+                beckhoff::ADSERR_DEVICE_SRVNOTSUPP.to_le_bytes().to_vec(),
+            ),
+        };
+
+        thread::sleep(session.device.response_delay());
+
+        let response_header = AmsHeader {
+            target: header.source,
+            source: header.target,
+            command_id: header.command_id,
+            state_flags: STATE_FLAG_ADS_RESPONSE,
+            error_code,
+            invoke_id: header.invoke_id,
+        };
+        if !session.send(&response_header, &response) {
+            break;
+        }
+    }
+
+    session.closed.store(true, Ordering::SeqCst);
+}
+
+impl Session {
+    fn respond(&self, command_id: u16, data: &[u8]) -> Option<AdsResult<Vec<u8>>> {
+        match command_id {
+            c if c == Command::AddDeviceNotification as u16 => Some(self.add_notification(data)),
+            c if c == Command::DeleteDeviceNotification as u16 => {
+                Some(self.delete_notification(data))
+            }
+            _ => self.device.respond(command_id, data),
+        }
+    }
+
+    fn add_notification(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let index_group = device::u32_at(data, 0)?;
+        let index_offset = device::u32_at(data, 4)?;
+        let length = device::u32_at(data, 8)? as usize;
+        let on_change = match device::u32_at(data, 12)? as i32 {
+            beckhoff::nAdsTransMode_ADSTRANS_CLIENTCYCLE
+            | beckhoff::nAdsTransMode_ADSTRANS_SERVERCYCLE => false,
+            beckhoff::nAdsTransMode_ADSTRANS_CLIENTONCHA
+            | beckhoff::nAdsTransMode_ADSTRANS_SERVERONCHA => true,
+            _ => return Err(beckhoff::ADSERR_DEVICE_TRANSMODENOTSUPP),
+        };
+        // In units of 100ns
+        let cycle_time = Duration::from_nanos(100 * device::u32_at(data, 20)? as u64);
+
+        // Check that there is something to notify about
+        self.device.read(index_group, index_offset)?;
+
+        let mut next_notification_handle = self
+            .next_notification_handle
+            .lock()
+            .map_err(|_| beckhoff::ADSERR_DEVICE_ERROR)?;
+        let notification_handle = *next_notification_handle;
+        *next_notification_handle += 1;
+
+        self.notifications
+            .lock()
+            .map_err(|_| beckhoff::ADSERR_DEVICE_ERROR)?
+            .insert(
+                notification_handle,
+                Notification {
+                    index_group,
+                    index_offset,
+                    length,
+                    on_change,
+                    cycle_time,
+                    last_sent: None,
+                },
+            );
+
+        Ok(notification_handle.to_le_bytes().to_vec())
+    }
+
+    fn delete_notification(&self, data: &[u8]) -> AdsResult<Vec<u8>> {
+        let notification_handle = device::u32_at(data, 0)?;
+        match self
+            .notifications
+            .lock()
+            .map_err(|_| beckhoff::ADSERR_DEVICE_ERROR)?
+            .remove(&notification_handle)
+        {
+            Some(_) => Ok(Vec::new()),
+            None => Err(beckhoff::ADSERR_DEVICE_NOTIFYHNDINVALID),
+        }
+    }
+
+    /// Sends a device notification whenever one is due, until the connection closes
+    fn notify(&self) {
+        while !self.closed.load(Ordering::SeqCst) {
+            thread::sleep(NOTIFICATION_POLL);
+
+            let samples = match self.notifications.lock() {
+                Ok(mut notifications) => notifications
+                    .iter_mut()
+                    .filter_map(|(handle, notification)| {
+                        notification.due(&self.device).map(|s| (*handle, s))
+                    })
+                    .collect::<Vec<(u32, Vec<u8>)>>(),
+                Err(_) => break,
+            };
+            if samples.is_empty() {
+                continue;
+            }
+
+            let (target, source) = match self.addresses.lock() {
+                Ok(a) => match *a {
+                    Some(a) => a,
+                    None => continue,
+                },
+                Err(_) => break,
+            };
+            let header = AmsHeader {
+                target,
+                source,
+                command_id: Command::DeviceNotification as u16,
+                state_flags: tcp::STATE_FLAG_ADS_COMMAND,
+                error_code: 0,
+                invoke_id: 0,
+            };
+            if !self.send(&header, &device_notification(&samples)) {
+                break;
+            }
+        }
+    }
+
+    /// Returns false once the connection is closed
+    fn send(&self, header: &AmsHeader, data: &[u8]) -> bool {
+        match self.writer.lock() {
+            Ok(mut writer) => tcp::write_frame(&mut *writer, header, data).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl Notification {
+    /// Returns the sample to send, if one is due
+    fn due(&mut self, device: &Device) -> Option<Vec<u8>> {
+        let mut sample = device.read(self.index_group, self.index_offset).ok()?;
+        sample.truncate(self.length);
+
+        let now = Instant::now();
+        let is_due = match &self.last_sent {
+            None => true,
+            Some((sent_at, _)) if now.duration_since(*sent_at) < self.cycle_time => false,
+            Some((_, last_sample)) => !self.on_change || *last_sample != sample,
+        };
+        if !is_due {
+            return None;
+        }
+
+        self.last_sent = Some((now, sample.clone()));
+        Some(sample)
+    }
+}
+
+/// One stamp holding all of `samples`, which are (notification handle, sample)
+fn device_notification(samples: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| UNIX_EPOCH_FILETIME + (d.as_nanos() / 100) as u64)
+        .unwrap_or(UNIX_EPOCH_FILETIME);
+
+    let mut stamp = Vec::new();
+    stamp.extend(timestamp.to_le_bytes());
+    stamp.extend((samples.len() as u32).to_le_bytes());
+    for (notification_handle, sample) in samples {
+        stamp.extend(notification_handle.to_le_bytes());
+        stamp.extend((sample.len() as u32).to_le_bytes());
+        stamp.extend(sample);
+    }
+
+    let mut output = Vec::with_capacity(8 + stamp.len());
+    output.extend(((4 + stamp.len()) as u32).to_le_bytes());
+    output.extend(1u32.to_le_bytes());
+    output.extend(stamp);
+    output
+}
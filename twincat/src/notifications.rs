@@ -1,10 +1,9 @@
 use std::io::{Error, ErrorKind, Result};
-use std::sync::RwLock;
-
-use lazy_static::lazy_static;
+use std::sync::{Arc, RwLock};
 
+use super::beckhoff;
 use super::symbols_and_data_types::SymbolsAndDataTypes;
-use super::{beckhoff, result};
+use super::transport::{AdsTransport, NotificationAttributes};
 use super::{Client, Variable};
 
 pub enum AdsTransmissionMode {
@@ -25,15 +24,19 @@ pub enum Time {
     NanoSeconds(u32),
 }
 
-lazy_static! {
-    static ref REGISTERED_SYMBOLS: RwLock<(Option<SymbolsAndDataTypes>, Vec<RegisteredSymbols>)> =
-        RwLock::new((None, Vec::new()));
+/// The notifications requested by one client and its clones,
+/// so that clients of different targets do not see or cancel each other's.
+/// They are deleted once the last clone is dropped.
+pub(super) struct Registry {
+    transport: Arc<dyn AdsTransport>,
+    registered_symbols: RwLock<Vec<RegisteredSymbol>>,
 }
 
-struct RegisteredSymbols {
+struct RegisteredSymbol {
     name: String,
     value_handle: u32,
-    notification_handle: u32,
+    /// `None` while the notification is being added
+    notification_handle: Option<u32>,
     callback: fn(&str, Variable),
 }
 
@@ -49,112 +52,116 @@ impl Client {
         cycle_time: Option<Time>,
         callback: fn(&str, Variable),
     ) -> Result<u32> {
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
-
-        let mut value_handle = 0_u32;
-        let ptr_value_handle = &mut value_handle as *mut u32 as *mut std::os::raw::c_void;
-
-        let ptr_name = value_name.as_ref() as *const str as *mut std::os::raw::c_void;
-
-        result::process(unsafe {
-            beckhoff::AdsSyncReadWriteReqEx2(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_HNDBYNAME,
-                0,
-                std::mem::size_of::<u32>() as u32,
-                ptr_value_handle,
-                value_name.len() as u32,
-                ptr_name,
-                std::ptr::null_mut(),
-            )
-        })?;
-
-        let (_, value_data_type) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-
-        let mut ads_notification_attribute = beckhoff::AdsNotificationAttrib {
-            cbLength: value_data_type.size_bytes() as u32,
-            nTransMode: ads_transmission_mode.to_beckhoff(),
-            nMaxDelay: time_to_beckhoff(&max_delay)?,
-            __bindgen_anon_1: beckhoff::AdsNotificationAttrib__bindgen_ty_1 {
-                nCycleTime: time_to_beckhoff(&cycle_time)?,
-            },
+        let symbols_and_data_types = self.symbols_and_data_types();
+        let (_, value_data_type) =
+            symbols_and_data_types.get_symbol_and_data_type(value_name.as_ref())?;
+
+        let attributes = NotificationAttributes {
+            length: value_data_type.size_bytes() as u32,
+            transmission_mode: ads_transmission_mode.to_beckhoff(),
+            max_delay: time_to_beckhoff(&max_delay)?,
+            cycle_time: time_to_beckhoff(&cycle_time)?,
         };
-        let ptr_ads_notification_attribute =
-            &mut ads_notification_attribute as *mut beckhoff::AdsNotificationAttrib;
 
-        let mut notification_handle = 0_u32;
-        let ptr_notification_handle = &mut notification_handle as *mut u32;
+        let mut value_handle = [0; std::mem::size_of::<u32>()];
 
-        result::process(unsafe {
-            beckhoff::AdsSyncAddDeviceNotificationReqEx(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_VALBYHND,
-                value_handle,
-                ptr_ads_notification_attribute,
-                Some(callback_wrapper),
+        self.transport().read_write(
+            beckhoff::ADSIGRP_SYM_HNDBYNAME,
+            0,
+            &mut value_handle,
+            value_name.as_bytes(),
+        )?;
+        let value_handle = u32::from_le_bytes(value_handle);
+
+        // The transport holds the callback, so it must not keep the client's state alive
+        let registry = Arc::downgrade(self.notifications());
+        let symbols = Arc::downgrade(self.symbols());
+
+        // Registered before the notification is added, so that the first sample is not dropped,
+        // but without holding the lock while waiting on the target
+        if let Err(e) = self.notifications().write().map(|mut registered_symbols| {
+            registered_symbols.push(RegisteredSymbol {
+                name: value_name,
                 value_handle,
-                ptr_notification_handle,
-            )
-        })?;
+                notification_handle: None,
+                callback,
+            })
+        }) {
+            release_handle(&*self.transport(), value_handle);
+            return Err(e);
+        }
 
-        let mut registered_symbols = match REGISTERED_SYMBOLS.write() {
-            Ok(w) => w,
-            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
-        };
-        registered_symbols.1.push(RegisteredSymbols {
-            name: value_name,
+        let added = self.transport().add_notification(
+            beckhoff::ADSIGRP_SYM_VALBYHND,
             value_handle,
-            notification_handle,
-            callback,
+            &attributes,
+            Arc::new(move |data| {
+                if let (Some(registry), Some(symbols)) = (registry.upgrade(), symbols.upgrade()) {
+                    registry.on_notification(&symbols.current(), value_handle, data);
+                }
+            }),
+        );
+
+        let recorded = self.notifications().write().map(|mut registered_symbols| {
+            let index = registered_symbols
+                .iter()
+                .position(|rs| rs.value_handle == value_handle);
+            match (&added, index) {
+                (Ok(notification_handle), Some(i)) => {
+                    registered_symbols[i].notification_handle = Some(*notification_handle)
+                }
+                (Err(_), Some(i)) => drop(registered_symbols.remove(i)),
+                (_, None) => (),
+            }
         });
-        if registered_symbols.0.is_none() {
-            registered_symbols.0 = Some(self.symbols_and_data_types().clone());
-        }
 
-        Ok(notification_handle)
+        match (added, recorded) {
+            (Ok(notification_handle), Ok(())) => Ok(notification_handle),
+            (Ok(notification_handle), Err(e)) => {
+                let _ = delete_request(&*self.transport(), value_handle, notification_handle);
+                Err(e)
+            }
+            (Err(e), _) => {
+                release_handle(&*self.transport(), value_handle);
+                Err(e)
+            }
+        }
     }
 
     /// Deletes all notification requests for this value
     pub fn delete_notifications_for_value(&self, value_name: impl AsRef<str>) -> Result<()> {
-        let mut registered_symbols = match REGISTERED_SYMBOLS.write() {
-            Ok(rs) => rs,
-            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
-        };
-        let mut found = false;
-        for i in (0..registered_symbols.1.len()).rev() {
-            if registered_symbols.1[i].name.eq(value_name.as_ref()) {
-                self.delete_notification_request_from_handles(
-                    registered_symbols.1[i].value_handle,
-                    registered_symbols.1[i].notification_handle,
-                )?;
-                registered_symbols.1.remove(i);
-                found = true;
-            }
-        }
+        // Not locked while waiting on the target, which may be calling back into the registry
+        let handles = self
+            .notifications()
+            .read()?
+            .iter()
+            .filter(|rs| rs.name == value_name.as_ref())
+            .filter_map(|rs| Some((rs.value_handle, rs.notification_handle?)))
+            .collect::<Vec<_>>();
 
-        if !found {
+        if handles.is_empty() {
             return Err(Error::new(
                 ErrorKind::NotFound,
                 format!("Cannot find Notification for {}", value_name.as_ref()),
             ));
         }
 
+        for (value_handle, notification_handle) in handles {
+            self.delete_notification_request_from_handles(value_handle, notification_handle)?;
+            self.notifications()
+                .write()?
+                .retain(|rs| rs.notification_handle != Some(notification_handle));
+        }
+
         Ok(())
     }
 
     pub fn delete_notification_with_handle(&self, notification_handle: u32) -> Result<()> {
-        let mut registered_symbols = match REGISTERED_SYMBOLS.write() {
-            Ok(rs) => rs,
-            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
-        };
+        let mut registered_symbols = self.notifications().write()?;
         let mut details = None;
-        for i in (0..registered_symbols.1.len()).rev() {
-            if registered_symbols.1[i].notification_handle == notification_handle {
-                details = Some(registered_symbols.1.remove(i));
+        for i in (0..registered_symbols.len()).rev() {
+            if registered_symbols[i].notification_handle == Some(notification_handle) {
+                details = Some(registered_symbols.remove(i));
                 break;
             }
         }
@@ -169,99 +176,118 @@ impl Client {
             }
         };
 
-        self.delete_notification_request_from_handles(
-            details.value_handle,
-            details.notification_handle,
-        )?;
+        self.delete_notification_request_from_handles(details.value_handle, notification_handle)?;
 
         Ok(())
     }
 
-    pub(super) fn drop_notification_requests(&self) -> Result<()> {
-        let mut registered_symbols = match REGISTERED_SYMBOLS.write() {
-            Ok(rs) => rs,
-            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
-        };
-        registered_symbols.0 = None;
-        for i in (0..registered_symbols.1.len()).rev() {
-            self.delete_notification_request_from_handles(
-                registered_symbols.1[i].value_handle,
-                registered_symbols.1[i].notification_handle,
-            )?;
-            registered_symbols.1.remove(i);
-        }
-        Ok(())
-    }
-
     fn delete_notification_request_from_handles(
         &self,
         value_handle: u32,
         notification_handle: u32,
     ) -> Result<()> {
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
+        delete_request(&*self.transport(), value_handle, notification_handle)
+    }
+}
 
-        result::process(unsafe {
-            beckhoff::AdsSyncDelDeviceNotificationReqEx(
-                self.port(),
-                ptr_address,
-                notification_handle,
-            )
-        })?;
-
-        let mut value_handle = value_handle;
-        let ptr_value_handle = &mut value_handle as *mut u32 as *mut std::os::raw::c_void;
-
-        result::process(unsafe {
-            beckhoff::AdsSyncWriteReqEx(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_RELEASEHND,
-                0,
-                std::mem::size_of::<u32>() as u32,
-                ptr_value_handle,
-            )
-        })?;
+fn delete_request(
+    transport: &dyn AdsTransport,
+    value_handle: u32,
+    notification_handle: u32,
+) -> Result<()> {
+    transport.delete_notification(notification_handle)?;
 
-        Ok(())
-    }
+    transport.write(
+        beckhoff::ADSIGRP_SYM_RELEASEHND,
+        0,
+        &value_handle.to_le_bytes(),
+    )?;
+
+    Ok(())
+}
+
+/// For when the notification could not be added; a failure is ignored, as there is already an error
+fn release_handle(transport: &dyn AdsTransport, value_handle: u32) {
+    let _ = transport.write(
+        beckhoff::ADSIGRP_SYM_RELEASEHND,
+        0,
+        &value_handle.to_le_bytes(),
+    );
 }
 
-unsafe extern "C" fn callback_wrapper(
-    _: *mut beckhoff::AmsAddr,
-    ptr_notification: *mut beckhoff::AdsNotificationHeader,
-    value_handle: std::os::raw::c_ulong,
-) {
-    let registered_symbols = match REGISTERED_SYMBOLS.read() {
-        Ok(rs) => rs,
-        Err(_) => return,
-    };
-    let symbols_and_data_types = match &registered_symbols.0 {
-        Some(sdt) => sdt,
-        None => return,
-    };
-    for registered_symbol in registered_symbols.1.iter() {
-        if registered_symbol.value_handle == value_handle {
-            let sample_size = (*ptr_notification).cbSampleSize as usize;
-            let data_slice =
-                std::slice::from_raw_parts((*ptr_notification).data.as_ptr(), sample_size);
-            let data_types = symbols_and_data_types.data_types();
-            let (symbol_info, data_type_info) =
-                match symbols_and_data_types.get_symbol_and_data_type(&registered_symbol.name) {
+impl Registry {
+    pub(super) fn new(transport: Arc<dyn AdsTransport>) -> Self {
+        Self {
+            transport,
+            registered_symbols: RwLock::new(Vec::new()),
+        }
+    }
+
+    fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, Vec<RegisteredSymbol>>> {
+        match self.registered_symbols.read() {
+            Ok(rs) => Ok(rs),
+            Err(e) => Err(Error::other(format!("Read-lock failure!\n{e}"))),
+        }
+    }
+
+    fn write(&self) -> Result<std::sync::RwLockWriteGuard<'_, Vec<RegisteredSymbol>>> {
+        match self.registered_symbols.write() {
+            Ok(rs) => Ok(rs),
+            Err(e) => Err(Error::other(format!("Write-lock failure!\n{e}"))),
+        }
+    }
+
+    /// Called for each sample, with the value handle given at registration
+    fn on_notification(
+        &self,
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_handle: u32,
+        data: &[u8],
+    ) {
+        let registered_symbols = match self.registered_symbols.read() {
+            Ok(rs) => rs,
+            Err(_) => return,
+        };
+        for registered_symbol in registered_symbols.iter() {
+            if registered_symbol.value_handle == value_handle {
+                let data_types = symbols_and_data_types.data_types();
+                let (symbol_info, data_type_info) = match symbols_and_data_types
+                    .get_symbol_and_data_type(&registered_symbol.name)
+                {
                     Ok(sdt) => sdt,
                     Err(_) => return,
                 };
-            let variable =
-                match Variable::from_bytes(data_types, symbol_info, data_type_info, data_slice) {
-                    Ok(v) => v,
-                    Err(_) => return,
-                };
-            (registered_symbol.callback)(&registered_symbol.name, variable);
+                let variable =
+                    match Variable::from_bytes(data_types, symbol_info, data_type_info, data) {
+                        Ok(v) => v,
+                        Err(_) => return,
+                    };
+                (registered_symbol.callback)(&registered_symbol.name, variable);
+            }
+        }
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        let registered_symbols = match self.registered_symbols.get_mut() {
+            Ok(rs) => rs,
+            Err(e) => e.into_inner(),
+        };
+        for registered_symbol in registered_symbols.drain(..) {
+            if let Some(notification_handle) = registered_symbol.notification_handle {
+                let _ = delete_request(
+                    &*self.transport,
+                    registered_symbol.value_handle,
+                    notification_handle,
+                );
+            }
         }
     }
 }
 
 impl AdsTransmissionMode {
-    fn to_beckhoff(&self) -> i32 {
+    pub(super) fn to_beckhoff(&self) -> i32 {
         match self {
             Self::None => beckhoff::nAdsTransMode_ADSTRANS_NOTRANS,
             Self::ClientCycle => beckhoff::nAdsTransMode_ADSTRANS_CLIENTCYCLE,
@@ -272,7 +298,7 @@ impl AdsTransmissionMode {
     }
 }
 
-fn time_to_beckhoff(time: &Option<Time>) -> Result<u32> {
+pub(super) fn time_to_beckhoff(time: &Option<Time>) -> Result<u32> {
     match time {
         None => Ok(0),
         Some(Time::Seconds(s)) => Ok(*s as u32 * 10000000),
@@ -305,3 +331,122 @@ fn time_to_beckhoff(time: &Option<Time>) -> Result<u32> {
         }
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicI16, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::mock::{connect, fixture, Server};
+
+    static LINE_1_SPEED: AtomicI16 = AtomicI16::new(0);
+    static LINE_2_SPEED: AtomicI16 = AtomicI16::new(0);
+    static CLONED_SPEED: AtomicI16 = AtomicI16::new(0);
+
+    fn server() -> Server {
+        fixture(&[("main.speed", "INT")], &[]).start().unwrap()
+    }
+
+    fn request(client: &Client, callback: fn(&str, Variable)) -> u32 {
+        client
+            .request_notifications(
+                String::from("main.speed"),
+                AdsTransmissionMode::OnChange,
+                None,
+                None,
+                callback,
+            )
+            .unwrap()
+    }
+
+    fn wait_for(speed: &AtomicI16, expected: i16) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if speed.load(Ordering::SeqCst) == expected {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn keep_clients_apart() {
+        let (server_1, server_2) = (server(), server());
+        let (line_1, line_2) = (connect(&server_1), connect(&server_2));
+
+        request(&line_1, |_, v| {
+            if let Variable::I16(s) = v {
+                LINE_1_SPEED.store(s, Ordering::SeqCst);
+            }
+        });
+        request(&line_2, |_, v| {
+            if let Variable::I16(s) = v {
+                LINE_2_SPEED.store(s, Ordering::SeqCst);
+            }
+        });
+
+        line_1.set_value("main.speed", Variable::I16(10)).unwrap();
+        line_2.set_value("main.speed", Variable::I16(20)).unwrap();
+        assert!(wait_for(&LINE_1_SPEED, 10));
+        assert!(wait_for(&LINE_2_SPEED, 20));
+
+        // Dropping one client only deletes its own notifications
+        drop(line_1);
+        line_2.set_value("main.speed", Variable::I16(21)).unwrap();
+        assert!(wait_for(&LINE_2_SPEED, 21));
+        line_2.delete_notifications_for_value("main.speed").unwrap();
+        assert!(line_2.delete_notifications_for_value("main.speed").is_err());
+    }
+
+    #[test]
+    fn release_handle_on_failure() {
+        let server = server();
+        let client = connect(&server);
+
+        let request = |ads_transmission_mode, max_delay| {
+            client.request_notifications(
+                String::from("main.speed"),
+                ads_transmission_mode,
+                max_delay,
+                None,
+                |_, _| {},
+            )
+        };
+        assert!(request(
+            AdsTransmissionMode::OnChange,
+            Some(Time::MilliSeconds(500000))
+        )
+        .is_err());
+        // The mock does not support it
+        assert!(request(AdsTransmissionMode::None, None).is_err());
+        assert_eq!(server.n_handles().unwrap(), 0);
+
+        let notification_handle = request(AdsTransmissionMode::OnChange, None).unwrap();
+        assert_eq!(server.n_handles().unwrap(), 1);
+        client
+            .delete_notification_with_handle(notification_handle)
+            .unwrap();
+        assert_eq!(server.n_handles().unwrap(), 0);
+    }
+
+    #[test]
+    fn keep_notifications_until_last_clone() {
+        let server = server();
+        let client = connect(&server);
+
+        let clone = client.clone();
+        request(&clone, |_, v| {
+            if let Variable::I16(s) = v {
+                CLONED_SPEED.store(s, Ordering::SeqCst);
+            }
+        });
+        thread::spawn(move || drop(clone)).join().unwrap();
+
+        client.set_value("main.speed", Variable::I16(7)).unwrap();
+        assert!(wait_for(&CLONED_SPEED, 7));
+    }
+}
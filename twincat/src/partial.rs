@@ -0,0 +1,288 @@
+//! Writes of a `Variable::Struct` which leaves some of the struct's bytes out,
+//! as `ClientBuilder::with_partial_writes` allows
+
+use std::io::{Error, ErrorKind, Result};
+
+use super::client::Client;
+use super::sum;
+
+/// How `Client::set_value` writes a `Variable::Struct` which leaves some of the struct's bytes out:
+/// fields it does not give, or the padding between fields
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PartialWrites {
+    /// Fails with `ErrorKind::Unsupported`; write the fields individually instead
+    #[default]
+    Reject,
+    /// Writes each run of given bytes at its own offset, together in one sum command.
+    /// A `BIT` field cannot be written this way unless every `BIT` it shares a byte with is given.
+    SumWrite,
+    /// Reads the struct, patches the given fields into it, and writes it back.
+    /// This is not atomic: a change the target makes to the other fields in between is lost.
+    ReadModifyWrite,
+}
+
+impl Client {
+    /// `masked` is each byte from the start of the struct, with a mask of the bits to write
+    pub(super) fn set_partial_struct(
+        &self,
+        value_name: &str,
+        size_bytes: usize,
+        masked: &[(u8, u8)],
+    ) -> Result<()> {
+        match self.partial_writes() {
+            PartialWrites::Reject => check(value_name, PartialWrites::Reject, masked),
+            PartialWrites::SumWrite => {
+                let requests = run_requests(value_name, self.symbol_address(value_name)?, masked)?;
+                let requests = requests
+                    .iter()
+                    .map(|(index_group, index_offset, bytes)| {
+                        (*index_group, *index_offset, bytes.as_slice())
+                    })
+                    .collect::<Vec<(u32, u32, &[u8])>>();
+                for result in sum::sum_write(&*self.transport(), &requests)? {
+                    result?;
+                }
+                Ok(())
+            }
+            PartialWrites::ReadModifyWrite => {
+                let mut bytes = self.get_raw_bytes(value_name, size_bytes)?;
+                patch(value_name, &mut bytes, masked)?;
+                self.set_raw_bytes(value_name, bytes)
+            }
+        }
+    }
+}
+
+/// Fails if `partial_writes` cannot write `masked`, before anything is sent
+pub(super) fn check(
+    value_name: &str,
+    partial_writes: PartialWrites,
+    masked: &[(u8, u8)],
+) -> Result<()> {
+    match partial_writes {
+        PartialWrites::Reject => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Cannot write {value_name} as a single struct because the bytes have gaps; write the fields individually, or allow it with ClientBuilder::with_partial_writes"),
+        )),
+        PartialWrites::SumWrite => match masked
+            .iter()
+            .position(|&(_, mask)| mask != 0 && mask != 0xff)
+        {
+            Some(offset) => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{value_name} has BIT fields at byte {offset} which share it with others that are not given; use PartialWrites::ReadModifyWrite"),
+            )),
+            None => Ok(()),
+        },
+        PartialWrites::ReadModifyWrite => Ok(()),
+    }
+}
+
+/// For `PartialWrites::SumWrite`: a write of each run of given bytes, from `address`
+/// (the struct's index group, index offset and size)
+pub(super) fn run_requests(
+    value_name: &str,
+    address: (u32, u32, u32),
+    masked: &[(u8, u8)],
+) -> Result<Vec<(u32, u32, Vec<u8>)>> {
+    let (index_group, index_offset, size) = address;
+    if masked.len() > size as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{value_name} has size {size}, cannot write {} bytes",
+                masked.len()
+            ),
+        ));
+    }
+
+    // Each run of bytes to write, from its start to its end
+    let mut runs = Vec::new();
+    for (offset, &(_, mask)) in masked.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end)) if mask == 0xff && *end == offset => *end += 1,
+            _ if mask == 0xff => runs.push((offset, offset + 1)),
+            _ => (),
+        }
+    }
+
+    Ok(runs
+        .into_iter()
+        .map(|(start, end)| {
+            let bytes = masked[start..end].iter().map(|&(byte, _)| byte).collect();
+            (index_group, index_offset + start as u32, bytes)
+        })
+        .collect())
+}
+
+/// For `PartialWrites::ReadModifyWrite`: patches the given bits of `masked` into `bytes`, as read
+pub(super) fn patch(value_name: &str, bytes: &mut [u8], masked: &[(u8, u8)]) -> Result<()> {
+    if masked.len() > bytes.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{value_name} has size {}, cannot write {} bytes",
+                bytes.len(),
+                masked.len()
+            ),
+        ));
+    }
+    for (byte, &(value, mask)) in bytes.iter_mut().zip(masked) {
+        *byte = (*byte & !mask) | (value & mask);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::{connect, struct_of, Declaration, Server};
+    use crate::Variable as V;
+
+    #[test]
+    fn set_partial_structs() {
+        let server = Server::builder()
+            .with_struct(
+                "Limits",
+                &[
+                    Declaration::new("low", "INT"),
+                    Declaration::new("high", "INT"),
+                ],
+            )
+            .with_struct(
+                "Axis",
+                &[
+                    Declaration::new("ready", "BIT"),
+                    Declaration::new("busy", "BIT"),
+                    Declaration::new("speed", "INT"),
+                    Declaration::new("position", "DINT"),
+                    Declaration::new("limits", "Limits"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.axis", "Axis"))
+            .with_value("main.axis.busy", V::Bool(true))
+            .with_value("main.axis.position", V::I32(100))
+            .with_value("main.axis.limits.low", V::I16(-10))
+            .start()
+            .unwrap();
+        let connect_with = |partial_writes| {
+            Client::builder()
+                .with_tcp_target(server.address())
+                .with_partial_writes(partial_writes)
+                .connect()
+                .unwrap()
+        };
+        let axis = |ready, busy, speed, position, low, high| {
+            struct_of(&[
+                ("ready", V::Bool(ready)),
+                ("busy", V::Bool(busy)),
+                ("speed", V::I16(speed)),
+                ("position", V::I32(position)),
+                (
+                    "limits",
+                    struct_of(&[("low", V::I16(low)), ("high", V::I16(high))]),
+                ),
+            ])
+        };
+
+        assert_eq!(
+            connect(&server)
+                .set_value("main.axis", struct_of(&[("speed", V::I16(7))]))
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+
+        let client = connect_with(PartialWrites::SumWrite);
+        client
+            .set_value(
+                "main.axis",
+                struct_of(&[
+                    ("speed", V::I16(7)),
+                    ("limits", struct_of(&[("high", V::I16(10))])),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.axis").unwrap(),
+            axis(false, true, 7, 100, -10, 10)
+        );
+        // Alone, `ready` would overwrite `busy`
+        assert_eq!(
+            client
+                .set_value("main.axis", struct_of(&[("ready", V::Bool(true))]))
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+        client
+            .set_value(
+                "main.axis",
+                struct_of(&[("ready", V::Bool(true)), ("busy", V::Bool(false))]),
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.axis").unwrap(),
+            axis(true, false, 7, 100, -10, 10)
+        );
+
+        let client = connect_with(PartialWrites::ReadModifyWrite);
+        client
+            .set_value(
+                "main.axis",
+                struct_of(&[
+                    ("busy", V::Bool(true)),
+                    ("limits", struct_of(&[("low", V::I16(-20))])),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.axis").unwrap(),
+            axis(true, true, 7, 100, -20, 10)
+        );
+        assert!(client
+            .set_value("main.axis", struct_of(&[("torque", V::I16(1))]))
+            .is_err());
+    }
+
+    #[test]
+    fn verify_partial_structs() {
+        let server = Server::builder()
+            .with_struct(
+                "Axis",
+                &[
+                    Declaration::new("ready", "BIT"),
+                    Declaration::new("busy", "BIT"),
+                    Declaration::new("speed", "INT"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.axis", "Axis"))
+            .start()
+            .unwrap();
+        let verify = |partial_writes, fields: &[(&str, V)]| {
+            let value = struct_of(fields);
+            Client::builder()
+                .with_tcp_target(server.address())
+                .with_partial_writes(partial_writes)
+                .connect()
+                .unwrap()
+                .verify_ads_path_and_variable_type("main.axis", value)
+                .map_err(|e| e.kind())
+        };
+        let speed = [("speed", V::I16(7))];
+        let ready = [("ready", V::Bool(true))];
+
+        assert_eq!(
+            verify(PartialWrites::Reject, &speed),
+            Err(std::io::ErrorKind::Unsupported)
+        );
+        assert_eq!(verify(PartialWrites::SumWrite, &speed), Ok(()));
+        assert_eq!(
+            verify(PartialWrites::SumWrite, &ready),
+            Err(std::io::ErrorKind::Unsupported)
+        );
+        assert_eq!(verify(PartialWrites::ReadModifyWrite, &ready), Ok(()));
+        assert!(verify(PartialWrites::ReadModifyWrite, &[("torque", V::I16(1))]).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Keeps the symbols and data types up to date after an online change or a download
+
+use std::io::{Error, Result};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::client::Client;
+use super::symbols_and_data_types::{self, SymbolsAndDataTypes};
+#[cfg(feature = "notifications")]
+use super::transport::NotificationAttributes;
+use super::transport::{self, AdsTransport};
+use super::variables::StringEncoding;
+use super::{beckhoff, result};
+
+/// Called with the new symbols and data types, once they have replaced the old ones
+pub type SymbolsChangedCallback = Arc<dyn Fn(&SymbolsAndDataTypes) + Send + Sync>;
+
+/// Shared by all clones of a `Client`
+pub(super) struct SharedSymbols {
+    /// To delete the notification with
+    #[cfg(feature = "notifications")]
+    transport: Arc<dyn AdsTransport>,
+    current: RwLock<Arc<SymbolsAndDataTypes>>,
+    /// The symbol version which `current` was uploaded at
+    version: Arc<AtomicU8>,
+    /// Set when a notification reports a new symbol version,
+    /// or when the target answers again after a restart
+    changed: Arc<AtomicBool>,
+    /// Applied to each upload
+    string_encoding: StringEncoding,
+    callback: Option<SymbolsChangedCallback>,
+    #[cfg(feature = "notifications")]
+    notification_handle: Option<u32>,
+}
+
+impl SharedSymbols {
+    /// If the transport supports notifications, the symbol version is watched by notification.
+    /// Otherwise changes are noticed when the target rejects a handle.
+    /// `changed` may also be set by others, such as `Retrying`.
+    pub(super) fn upload(
+        transport: Arc<dyn AdsTransport>,
+        timeout: Option<Duration>,
+        string_encoding: StringEncoding,
+        callback: Option<SymbolsChangedCallback>,
+        changed: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        let timed = transport::timed(&*transport, timeout);
+        let version = Arc::new(AtomicU8::new(read_version(&*timed)?));
+        let current = RwLock::new(Arc::new(
+            symbols_and_data_types::upload(&*timed)?.with_string_encoding(string_encoding),
+        ));
+        drop(timed);
+        // Anything which happened during the upload is already included
+        changed.store(false, Ordering::SeqCst);
+
+        #[cfg(feature = "notifications")]
+        let notification_handle = watch_version(&*transport, version.clone(), changed.clone());
+
+        Ok(Self {
+            #[cfg(feature = "notifications")]
+            transport,
+            current,
+            version,
+            changed,
+            string_encoding,
+            callback,
+            #[cfg(feature = "notifications")]
+            notification_handle,
+        })
+    }
+
+    pub(super) fn current(&self) -> Arc<SymbolsAndDataTypes> {
+        match self.current.read() {
+            Ok(c) => c.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+
+    /// Uploads again, whatever the symbol version.
+    /// The callback is only called if the symbol version has changed.
+    fn reload(&self, transport: &dyn AdsTransport) -> Result<()> {
+        self.changed.store(false, Ordering::SeqCst);
+
+        let version = read_version(transport)?;
+        let symbols_and_data_types = Arc::new(
+            symbols_and_data_types::upload(transport)?.with_string_encoding(self.string_encoding),
+        );
+        match self.current.write() {
+            Ok(mut c) => *c = symbols_and_data_types.clone(),
+            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
+        }
+        let previous_version = self.version.swap(version, Ordering::SeqCst);
+
+        if previous_version != version {
+            if let Some(callback) = &self.callback {
+                callback(&symbols_and_data_types);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SharedSymbols {
+    fn drop(&mut self) {
+        #[cfg(feature = "notifications")]
+        if let Some(notification_handle) = self.notification_handle {
+            let _ = self.transport.delete_notification(notification_handle);
+        }
+    }
+}
+
+impl Client {
+    /// Uploads the symbols and data types again if the symbol version of the target has changed,
+    /// as it does after an online change or a download.
+    /// Returns whether they were uploaded.
+    ///
+    /// This is done automatically when the target reports a change,
+    /// so is only needed to pick up a change before the next request.
+    pub fn refresh_symbols_and_data_types(&self) -> Result<bool> {
+        if read_version(&*self.transport())? == self.symbols().version.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        self.reload_symbols_and_data_types()?;
+        Ok(true)
+    }
+
+    /// Runs `f` with the current symbols and data types.
+    /// If the target reports that they have changed, they are uploaded again and `f` is run once more.
+    pub(super) fn with_current_symbols<T>(
+        &self,
+        f: impl Fn(&SymbolsAndDataTypes) -> Result<T>,
+    ) -> Result<T> {
+        if self.symbols().changed.load(Ordering::SeqCst) {
+            self.reload_symbols_and_data_types()?;
+        }
+
+        match f(&self.symbols().current()) {
+            Err(e) if symbols_changed(&e) => {
+                self.reload_symbols_and_data_types()?;
+                f(&self.symbols().current())
+            }
+            r => r,
+        }
+    }
+
+    /// Handles from before the change may point to the wrong place, so they are all released
+    fn reload_symbols_and_data_types(&self) -> Result<()> {
+        self.handles().clear();
+        self.symbols().reload(&*self.transport())
+    }
+}
+
+/// Whether `error` means that the symbols have changed since the handle was created
+pub(super) fn symbols_changed(error: &Error) -> bool {
+    matches!(
+        result::code(error),
+        Some(
+            beckhoff::ADSERR_DEVICE_SYMBOLNOTACTIVE | beckhoff::ADSERR_DEVICE_SYMBOLVERSIONINVALID
+        )
+    )
+}
+
+fn read_version(transport: &dyn AdsTransport) -> Result<u8> {
+    let mut version = [0; 1];
+    transport.read(beckhoff::ADSIGRP_SYM_VERSION, 0, &mut version)?;
+    Ok(version[0])
+}
+
+/// Returns the notification handle, or `None` if the transport cannot notify
+#[cfg(feature = "notifications")]
+fn watch_version(
+    transport: &dyn AdsTransport,
+    version: Arc<AtomicU8>,
+    changed: Arc<AtomicBool>,
+) -> Option<u32> {
+    let attributes = NotificationAttributes {
+        length: 1,
+        transmission_mode: beckhoff::nAdsTransMode_ADSTRANS_SERVERONCHA,
+        max_delay: 0,
+        cycle_time: 0,
+    };
+    // The upload cannot be done from within the callback, so it is left to the next request
+    transport
+        .add_notification(
+            beckhoff::ADSIGRP_SYM_VERSION,
+            0,
+            &attributes,
+            Arc::new(move |data| {
+                if data.first() != Some(&version.load(Ordering::SeqCst)) {
+                    changed.store(true, Ordering::SeqCst);
+                }
+            }),
+        )
+        .ok()
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    use crate::mock::{fixture, Server};
+    use crate::Variable;
+
+    fn server() -> Server {
+        fixture(
+            &[("main.pressure", "REAL")],
+            &[("main.pressure", Variable::F32(1.25))],
+        )
+        .start()
+        .unwrap()
+    }
+
+    fn connect(server: &Server, n_changes: &Arc<AtomicUsize>) -> Client {
+        let n_changes = n_changes.clone();
+        Client::builder()
+            .with_tcp_target(server.address())
+            .with_symbols_changed_callback(move |_| {
+                n_changes.fetch_add(1, Ordering::SeqCst);
+            })
+            .connect()
+            .unwrap()
+    }
+
+    #[test]
+    fn refresh_after_online_change() {
+        let server = server();
+        let n_changes = Arc::new(AtomicUsize::new(0));
+        let client = connect(&server, &n_changes);
+
+        assert!(!client.refresh_symbols_and_data_types().unwrap());
+
+        server.online_change().unwrap();
+
+        assert!(client.refresh_symbols_and_data_types().unwrap());
+        assert!(!client.refresh_symbols_and_data_types().unwrap());
+        assert_eq!(n_changes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn refresh_when_handle_is_rejected() {
+        let server = server();
+        let n_changes = Arc::new(AtomicUsize::new(0));
+        let client = connect(&server, &n_changes);
+
+        assert_eq!(
+            client.get_value("main.pressure").unwrap(),
+            Variable::F32(1.25)
+        );
+
+        server.online_change().unwrap();
+
+        assert_eq!(
+            client.get_value("main.pressure").unwrap(),
+            Variable::F32(1.25)
+        );
+        assert_eq!(n_changes.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,4 +1,5 @@
-use std::io::{Error, Result};
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
 
 use super::beckhoff;
 
@@ -152,6 +153,67 @@ pub fn process(code: i32) -> Result<()> {
     }
 }
 
+/// A timeout has `ErrorKind::TimedOut`, so that it can be told apart without knowing the code
 fn error(code: i32, text: &str) -> Result<()> {
-    Err(Error::other(format!("Error code {code:#06x} : {text}")))
+    let kind = match code as u32 {
+        beckhoff::ADSERR_CLIENT_SYNCTIMEOUT => ErrorKind::TimedOut,
+        _ => ErrorKind::Other,
+    };
+    Err(Error::new(
+        kind,
+        AdsError {
+            code: code as u32,
+            text: text.to_string(),
+        },
+    ))
+}
+
+/// The ADS error code, if `error` came from `process`
+pub fn code(error: &Error) -> Option<u32> {
+    error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<AdsError>())
+        .map(|e| e.code)
+}
+
+/// Carried inside the `std::io::Error`s made by `process`, so that the code can be recovered
+#[derive(Debug)]
+struct AdsError {
+    code: u32,
+    text: String,
+}
+
+impl fmt::Display for AdsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error code {:#06x} : {}", self.code as i32, self.text)
+    }
+}
+
+impl std::error::Error for AdsError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recover_code() {
+        let error = process(beckhoff::ADSERR_DEVICE_SYMBOLNOTACTIVE as i32).unwrap_err();
+        assert_eq!(code(&error), Some(beckhoff::ADSERR_DEVICE_SYMBOLNOTACTIVE));
+        assert_eq!(
+            error.to_string(),
+            "Error code 0x0722 : symbol not active -> release handle and try again"
+        );
+
+        assert_eq!(code(&Error::other("Lock failure!")), None);
+    }
+
+    #[test]
+    fn timeout_kind() {
+        let error = process(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT as i32).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert_eq!(code(&error), Some(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT));
+
+        let error = process(beckhoff::ADSERR_DEVICE_SYMBOLNOTFOUND as i32).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Other);
+    }
 }
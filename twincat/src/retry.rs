@@ -0,0 +1,543 @@
+//! Retrying requests which fail for reasons that may pass, such as the runtime restarting
+
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use super::transport::{self, AdsTransport};
+#[cfg(feature = "notifications")]
+use super::transport::{NotificationAttributes, NotificationCallback};
+use super::{beckhoff, result};
+
+/// Router error codes, which TcAdsDll and the AMS router share
+const TARGET_PORT_NOT_FOUND: u32 = 0x06;
+const TARGET_MACHINE_NOT_FOUND: u32 = 0x07;
+const PORT_NOT_CONNECTED: u32 = 0x0d;
+
+/// How a `Client` retries requests which fail for reasons that may pass.
+///
+/// When the port or connection has closed, the transport is opened again before retrying,
+/// and the symbols and data types are uploaded again once the target answers.
+/// Notifications requested before the transport was opened again are lost.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable: Vec<ErrorClass>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorClass {
+    /// The target port or AMS route cannot be found (0x06, 0x07), as while the runtime restarts
+    Unreachable,
+    /// The target did not respond in time (0x745)
+    Timeout,
+    /// The port or TCP connection is closed (0x0d, 0x748, or a socket error)
+    Disconnected,
+}
+
+impl RetryPolicy {
+    /// `max_attempts` includes the first attempt.
+    /// By default every `ErrorClass` is retried, waiting 100ms at first and at most 5s.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retryable: vec![
+                ErrorClass::Unreachable,
+                ErrorClass::Timeout,
+                ErrorClass::Disconnected,
+            ],
+        }
+    }
+
+    /// The wait doubles after each failed attempt, starting at `initial` and never exceeding `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn with_retryable(mut self, error_classes: &[ErrorClass]) -> Self {
+        self.retryable = error_classes.to_vec();
+        self
+    }
+}
+
+impl ErrorClass {
+    /// `None` if the error is not expected to pass, such as an unknown symbol
+    pub fn of(error: &Error) -> Option<Self> {
+        match result::code(error) {
+            Some(TARGET_PORT_NOT_FOUND | TARGET_MACHINE_NOT_FOUND) => Some(Self::Unreachable),
+            Some(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT) => Some(Self::Timeout),
+            Some(PORT_NOT_CONNECTED | beckhoff::ADSERR_CLIENT_PORTNOTOPEN) => {
+                Some(Self::Disconnected)
+            }
+            Some(_) => None,
+            None => match error.kind() {
+                ErrorKind::TimedOut => Some(Self::Timeout),
+                ErrorKind::NotConnected
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof => Some(Self::Disconnected),
+                _ => None,
+            },
+        }
+    }
+}
+
+pub(super) type OpenTransport = Box<dyn Fn() -> Result<Box<dyn AdsTransport>> + Send + Sync>;
+
+/// Wraps the transport of a `Client` which has a `RetryPolicy`
+pub(super) struct Retrying {
+    policy: RetryPolicy,
+    inner: RwLock<Arc<dyn AdsTransport>>,
+    /// `None` if the transport was given to `ClientBuilder::with_transport`
+    open: Option<OpenTransport>,
+    /// Set once the target answers again after being unreachable or disconnected,
+    /// so that the symbols and data types are uploaded again
+    recovered: Arc<AtomicBool>,
+}
+
+impl Retrying {
+    pub(super) fn new(
+        policy: RetryPolicy,
+        inner: Arc<dyn AdsTransport>,
+        open: Option<OpenTransport>,
+        recovered: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            policy,
+            inner: RwLock::new(inner),
+            open,
+            recovered,
+        }
+    }
+
+    fn timed(&self, timeout: Option<Duration>) -> Timed<'_> {
+        Timed {
+            retrying: self,
+            timeout,
+        }
+    }
+
+    /// Each attempt waits at most `timeout` for its response
+    fn retry<T>(
+        &self,
+        timeout: Option<Duration>,
+        resend: Resend,
+        mut f: impl FnMut(&dyn AdsTransport) -> Result<T>,
+    ) -> Result<T> {
+        let mut backoff = self.policy.initial_backoff;
+        let mut interrupted = false;
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let current = self.current();
+            let error = match f(&*transport::timed(&*current, timeout)) {
+                Ok(t) => {
+                    if interrupted {
+                        self.recovered.store(true, Ordering::SeqCst);
+                    }
+                    return Ok(t);
+                }
+                Err(e) => e,
+            };
+
+            let error_class = match ErrorClass::of(&error) {
+                Some(c) if self.policy.retryable.contains(&c) => c,
+                _ => return Err(error),
+            };
+            // The target may have created the handle before the response was lost
+            if error_class == ErrorClass::Timeout && resend == Resend::CreatesHandles {
+                return Err(error);
+            }
+            if attempt >= self.policy.max_attempts {
+                return Err(error);
+            }
+
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+
+            match error_class {
+                ErrorClass::Timeout => (),
+                ErrorClass::Unreachable => interrupted = true,
+                ErrorClass::Disconnected => {
+                    interrupted = true;
+                    // If this fails, the next attempt fails too, and so counts against the policy
+                    let _ = self.reopen();
+                }
+            }
+
+            // After a restart the handle may belong to another symbol, so it is not used again.
+            // `Client::with_current_symbols` takes this error to mean that the symbols have changed,
+            // so uploads them again and creates new handles.
+            if interrupted && resend == Resend::ByHandle {
+                self.recovered.store(true, Ordering::SeqCst);
+                return Err(
+                    result::process(beckhoff::ADSERR_DEVICE_SYMBOLNOTACTIVE as i32)
+                        .err()
+                        .unwrap_or(error),
+                );
+            }
+        }
+    }
+
+    fn current(&self) -> Arc<dyn AdsTransport> {
+        match self.inner.read() {
+            Ok(i) => i.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+
+    fn reopen(&self) -> Result<()> {
+        let open = match &self.open {
+            Some(o) => o,
+            None => return Ok(()),
+        };
+        let transport = open()?;
+        match self.inner.write() {
+            Ok(mut i) => *i = Arc::from(transport),
+            Err(e) => return Err(Error::other(format!("Write-lock failure!\n{e}"))),
+        }
+        Ok(())
+    }
+}
+
+/// What sending a request again could do besides repeat it
+#[derive(Clone, Copy, PartialEq)]
+enum Resend {
+    Safe,
+    /// Goes by symbol handle
+    ByHandle,
+    /// Creates symbol handles
+    CreatesHandles,
+}
+
+impl Resend {
+    fn of(index_group: u32, index_offset: u32, write_data: &[u8]) -> Self {
+        // The index offset of a sum command is its number of sub-commands,
+        // whose headers each start with the sub-command's index group
+        let header_length = match index_group {
+            beckhoff::ADSIGRP_SUMUP_READ | beckhoff::ADSIGRP_SUMUP_WRITE => Some(12),
+            beckhoff::ADSIGRP_SUMUP_READWRITE => Some(16),
+            _ => None,
+        };
+        let index_groups = match header_length {
+            Some(length) => write_data
+                .chunks_exact(length)
+                .take(index_offset as usize)
+                .map(|header| u32::from_le_bytes([header[0], header[1], header[2], header[3]]))
+                .collect(),
+            None => vec![index_group],
+        };
+
+        if index_groups.contains(&beckhoff::ADSIGRP_SYM_HNDBYNAME) {
+            Self::CreatesHandles
+        } else if index_groups.iter().any(|g| {
+            matches!(
+                *g,
+                beckhoff::ADSIGRP_SYM_VALBYHND | beckhoff::ADSIGRP_SYM_RELEASEHND
+            )
+        }) {
+            Self::ByHandle
+        } else {
+            Self::Safe
+        }
+    }
+}
+
+/// A view of `Retrying` whose attempts wait for their own timeout
+struct Timed<'a> {
+    retrying: &'a Retrying,
+    timeout: Option<Duration>,
+}
+
+impl AdsTransport for Timed<'_> {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        let resend = Resend::of(index_group, index_offset, &[]);
+        self.retrying.retry(self.timeout, resend, |t| {
+            t.read(index_group, index_offset, buffer)
+        })
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        let resend = Resend::of(index_group, index_offset, data);
+        self.retrying.retry(self.timeout, resend, |t| {
+            t.write(index_group, index_offset, data)
+        })
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        let resend = Resend::of(index_group, index_offset, write_data);
+        self.retrying.retry(self.timeout, resend, |t| {
+            t.read_write(index_group, index_offset, read_buffer, write_data)
+        })
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        self.retrying
+            .retry(self.timeout, Resend::Safe, |t| t.read_state())
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        self.retrying.retry(self.timeout, Resend::Safe, |t| {
+            t.write_control(ads_state, device_state, data)
+        })
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        Box::new(self.retrying.timed(Some(timeout)))
+    }
+}
+
+impl AdsTransport for Retrying {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        self.timed(None).read(index_group, index_offset, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        self.timed(None).write(index_group, index_offset, data)
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        self.timed(None)
+            .read_write(index_group, index_offset, read_buffer, write_data)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        self.timed(None).read_state()
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        self.timed(None)
+            .write_control(ads_state, device_state, data)
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        Box::new(self.timed(Some(timeout)))
+    }
+
+    /// Not retried, so that a notification is never requested twice
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        self.current()
+            .add_notification(index_group, index_offset, attributes, callback)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        self.current().delete_notification(notification_handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::AtomicU32;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::{Client, Variable};
+
+    /// Fails with `code` until `n_failures` requests have failed
+    struct Flaky {
+        code: u32,
+        n_failures: u32,
+        n_requests: AtomicU32,
+    }
+
+    impl AdsTransport for Flaky {
+        fn read(&self, _: u32, _: u32, buffer: &mut [u8]) -> Result<usize> {
+            if self.n_requests.fetch_add(1, Ordering::SeqCst) < self.n_failures {
+                result::process(self.code as i32)?;
+            }
+            buffer.fill(1);
+            Ok(buffer.len())
+        }
+
+        fn write(&self, _: u32, _: u32, _: &[u8]) -> Result<()> {
+            Err(Error::other("Flaky only reads"))
+        }
+
+        fn read_write(&self, _: u32, _: u32, read_buffer: &mut [u8], _: &[u8]) -> Result<usize> {
+            self.read(0, 0, read_buffer)
+        }
+
+        fn read_state(&self) -> Result<(u16, u16)> {
+            Err(Error::other("Flaky only reads"))
+        }
+
+        fn write_control(&self, _: u16, _: u16, _: &[u8]) -> Result<()> {
+            Err(Error::other("Flaky only reads"))
+        }
+    }
+
+    fn retrying(code: u32, n_failures: u32, policy: RetryPolicy) -> (Retrying, Arc<AtomicBool>) {
+        let recovered = Arc::new(AtomicBool::new(false));
+        let flaky = Flaky {
+            code,
+            n_failures,
+            n_requests: AtomicU32::new(0),
+        };
+        let policy = policy.with_backoff(Duration::from_millis(1), Duration::from_millis(2));
+        (
+            Retrying::new(policy, Arc::new(flaky), None, recovered.clone()),
+            recovered,
+        )
+    }
+
+    #[test]
+    fn classify_errors() {
+        let class_of = |code: u32| ErrorClass::of(&result::process(code as i32).unwrap_err());
+        assert_eq!(class_of(0x06), Some(ErrorClass::Unreachable));
+        assert_eq!(class_of(0x07), Some(ErrorClass::Unreachable));
+        assert_eq!(class_of(0x0d), Some(ErrorClass::Disconnected));
+        assert_eq!(class_of(0x745), Some(ErrorClass::Timeout));
+        assert_eq!(class_of(beckhoff::ADSERR_DEVICE_SYMBOLNOTFOUND), None);
+
+        assert_eq!(
+            ErrorClass::of(&Error::from(ErrorKind::ConnectionReset)),
+            Some(ErrorClass::Disconnected)
+        );
+        assert_eq!(ErrorClass::of(&Error::from(ErrorKind::InvalidInput)), None);
+    }
+
+    #[test]
+    fn retry_until_success() {
+        let (transport, recovered) = retrying(TARGET_PORT_NOT_FOUND, 2, RetryPolicy::new(3));
+        assert_eq!(transport.read(0, 0, &mut [0; 2]).unwrap(), 2);
+        assert!(recovered.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn give_up_after_max_attempts() {
+        let (transport, _) = retrying(TARGET_PORT_NOT_FOUND, 3, RetryPolicy::new(3));
+        assert!(transport.read(0, 0, &mut [0; 2]).is_err());
+        assert_eq!(transport.read(0, 0, &mut [0; 2]).unwrap(), 2);
+    }
+
+    #[test]
+    fn only_retry_retryable_errors() {
+        let (transport, _) = retrying(
+            beckhoff::ADSERR_CLIENT_SYNCTIMEOUT,
+            1,
+            RetryPolicy::new(3).with_retryable(&[ErrorClass::Unreachable]),
+        );
+        assert!(transport.read(0, 0, &mut [0; 2]).is_err());
+
+        let (transport, recovered) =
+            retrying(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT, 1, RetryPolicy::new(3));
+        assert!(transport.read(0, 0, &mut [0; 2]).is_ok());
+        assert!(!recovered.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn do_not_reuse_handles_after_restart() {
+        let (transport, recovered) = retrying(TARGET_PORT_NOT_FOUND, 1, RetryPolicy::new(3));
+        let error = transport
+            .read(beckhoff::ADSIGRP_SYM_VALBYHND, 7, &mut [0; 2])
+            .unwrap_err();
+        assert!(crate::refresh::symbols_changed(&error));
+        assert!(recovered.load(Ordering::SeqCst));
+
+        // Anything else is retried
+        let (transport, _) = retrying(TARGET_PORT_NOT_FOUND, 1, RetryPolicy::new(3));
+        assert!(transport
+            .read(beckhoff::ADSIGRP_SYM_VERSION, 0, &mut [0; 1])
+            .is_ok());
+    }
+
+    #[test]
+    fn do_not_create_handles_again_after_timeout() {
+        let (transport, _) = retrying(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT, 1, RetryPolicy::new(3));
+        let mut handle = [0; 4];
+        assert_eq!(
+            transport
+                .read_write(
+                    beckhoff::ADSIGRP_SYM_HNDBYNAME,
+                    0,
+                    &mut handle,
+                    b"main.mode"
+                )
+                .unwrap_err()
+                .kind(),
+            ErrorKind::TimedOut
+        );
+
+        // Nor inside a sum command
+        let (transport, _) = retrying(beckhoff::ADSERR_CLIENT_SYNCTIMEOUT, 1, RetryPolicy::new(3));
+        let mut header = beckhoff::ADSIGRP_SYM_HNDBYNAME.to_le_bytes().to_vec();
+        header.extend([0; 12]);
+        assert!(transport
+            .read_write(beckhoff::ADSIGRP_SUMUP_READWRITE, 1, &mut [0; 12], &header)
+            .is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reopen_closed_connection() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.mode", "USINT"))
+            .with_value("main.mode", Variable::U8(2))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .with_retry_policy(
+                RetryPolicy::new(3)
+                    .with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+            )
+            .connect()
+            .unwrap();
+        assert_eq!(client.get_value("main.mode").unwrap(), Variable::U8(2));
+
+        server.close_connections();
+
+        assert_eq!(client.get_value("main.mode").unwrap(), Variable::U8(2));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn fail_without_retry_policy() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.mode", "USINT"))
+            .start()
+            .unwrap();
+        let client = Client::builder()
+            .with_tcp_target(server.address())
+            .connect()
+            .unwrap();
+
+        server.close_connections();
+
+        assert!(client.get_value("main.mode").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! AMS routes, without which a router refuses ADS requests from another machine.
+//!
+//! A remote router is asked to add a route to this machine over UDP port 48899,
+//! with the username and password of the remote machine.
+//! The routes a router already has are listed and removed over ADS, through its system service.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use twincat::routes::{self, RouteRequest};
+//! use twincat::Client;
+//!
+//! let request = RouteRequest::new("192.168.0.5.1.1".parse().unwrap(), "192.168.0.5")
+//!     .with_credentials("Administrator", "1");
+//! routes::add_remote_route("192.168.0.20".parse().unwrap(), &request, Duration::from_secs(2))
+//!     .unwrap();
+//!
+//! for route in routes::list_routes(&Client::builder()).unwrap() {
+//!     println!("{} {} {}", route.name(), route.ams_net_id(), route.address());
+//! }
+//! ```
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use super::transport::AdsTransport;
+use super::udp::{self, tag, Message, Service};
+use super::{beckhoff, result, AmsNetId, ClientBuilder};
+
+/// The AMS port of the TwinCAT system service, which manages the router's routes
+const SYSTEM_SERVICE_PORT: u16 = 10000;
+/// Index groups of the system service
+pub(crate) const INDEX_GROUP_DELETE_REMOTE: u32 = 802;
+pub(crate) const INDEX_GROUP_ENUMERATE_REMOTE: u32 = 803;
+
+/// Room enough for any one route
+const ROUTE_ENTRY_LENGTH: usize = 0x800;
+/// The header of a route entry, before the address and the name
+const ROUTE_ENTRY_HEADER_LENGTH: usize = 20;
+#[cfg(feature = "mock")]
+const TRANSPORT_TCP: u32 = 1;
+
+/// A route from a router to another machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    name: String,
+    ams_net_id: AmsNetId,
+    address: String,
+}
+
+/// What `add_remote_route` asks a remote router to add: a route back to this machine
+#[derive(Clone, Debug)]
+pub struct RouteRequest {
+    route: Route,
+    username: String,
+    password: String,
+}
+
+impl Route {
+    /// `address` is the IP address or hostname of the machine the route leads to
+    pub fn new(name: impl Into<String>, ams_net_id: AmsNetId, address: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ams_net_id,
+            address: address.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ams_net_id(&self) -> AmsNetId {
+        self.ams_net_id
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// As the system service enumerates it: the AMS Net ID, 2 reserved bytes, the transport type,
+    /// the lengths of the address and the name, then the address and the name (each NUL-terminated)
+    #[cfg(feature = "mock")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let address = udp::string_tag(&self.address);
+        let name = udp::string_tag(&self.name);
+
+        let mut bytes = self.ams_net_id.bytes().to_vec();
+        bytes.extend([0, 0]);
+        bytes.extend(TRANSPORT_TCP.to_le_bytes());
+        bytes.extend((address.len() as u32).to_le_bytes());
+        bytes.extend((name.len() as u32).to_le_bytes());
+        bytes.extend(address);
+        bytes.extend(name);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let address_length = udp::u32_at(bytes, 12)? as usize;
+        let name_length = udp::u32_at(bytes, 16)? as usize;
+        let address_end = ROUTE_ENTRY_HEADER_LENGTH + address_length;
+        let (address, name) = match (
+            bytes.get(ROUTE_ENTRY_HEADER_LENGTH..address_end),
+            bytes.get(address_end..address_end + name_length),
+        ) {
+            (Some(a), Some(n)) => (a, n),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Route entry of {} bytes is truncated", bytes.len()),
+                ))
+            }
+        };
+
+        let mut ams_net_id = [0; 6];
+        ams_net_id.copy_from_slice(&bytes[..6]);
+
+        Ok(Self {
+            name: udp::tag_string(name),
+            ams_net_id: AmsNetId::new(ams_net_id),
+            address: udp::tag_string(address),
+        })
+    }
+}
+
+impl RouteRequest {
+    /// `ams_net_id` and `address` are those of this machine, as the remote machine should reach it.
+    /// The route is named after `address`, and the credentials default to TwinCAT's
+    /// `Administrator` and `1`.
+    pub fn new(ams_net_id: AmsNetId, address: impl Into<String>) -> Self {
+        let address = address.into();
+        Self {
+            route: Route::new(address.clone(), ams_net_id, address),
+            username: String::from("Administrator"),
+            password: String::from("1"),
+        }
+    }
+
+    pub fn with_route_name(mut self, name: impl Into<String>) -> Self {
+        self.route.name = name.into();
+        self
+    }
+
+    /// Of a user of the remote machine
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = username.into();
+        self.password = password.into();
+        self
+    }
+
+    pub fn route(&self) -> &Route {
+        &self.route
+    }
+}
+
+/// Asks the router at `target` to add a route to this machine, and waits up to `timeout` for its answer
+pub fn add_remote_route(target: IpAddr, request: &RouteRequest, timeout: Duration) -> Result<()> {
+    add_remote_route_at(SocketAddr::new(target, udp::PORT), request, timeout)
+}
+
+/// As `add_remote_route`, for a router which does not listen on the usual port
+pub fn add_remote_route_at(
+    target: SocketAddr,
+    request: &RouteRequest,
+    timeout: Duration,
+) -> Result<()> {
+    let message = Message {
+        invoke_id: 0,
+        service: Service::AddRoute,
+        is_response: false,
+        ams_net_id: request.route.ams_net_id,
+        ams_port: SYSTEM_SERVICE_PORT,
+        tags: vec![
+            (tag::ROUTE_NAME, udp::string_tag(&request.route.name)),
+            (tag::AMS_NET_ID, request.route.ams_net_id.bytes().to_vec()),
+            (tag::USERNAME, udp::string_tag(&request.username)),
+            (tag::PASSWORD, udp::string_tag(&request.password)),
+            (tag::HOSTNAME, udp::string_tag(&request.route.address)),
+        ],
+    };
+
+    let response = udp::exchange(target, &message, timeout)?;
+    match response.tag(tag::STATUS) {
+        Some(status) => result::process(udp::u32_at(status, 0)? as i32),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{target} did not say whether the route was added"),
+        )),
+    }
+}
+
+/// The routes of the router which `router` would connect to, such as `Client::builder()`
+/// for the local router.
+/// Its AMS port is replaced by that of the system service.
+pub fn list_routes(router: &ClientBuilder) -> Result<Vec<Route>> {
+    let system_service = open_system_service(router)?;
+
+    let mut routes = Vec::new();
+    for index in 0.. {
+        let mut entry = vec![0; ROUTE_ENTRY_LENGTH];
+        match system_service.read(INDEX_GROUP_ENUMERATE_REMOTE, index, &mut entry) {
+            Ok(n_read) => routes.push(Route::from_bytes(&entry[..n_read])?),
+            // Past the last route
+            Err(e) if result::code(&e) == Some(beckhoff::ADSERR_DEVICE_NOTFOUND) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(routes)
+}
+
+/// Removes the route called `name` from the router which `router` would connect to
+pub fn remove_route(router: &ClientBuilder, name: &str) -> Result<()> {
+    open_system_service(router)?.write(INDEX_GROUP_DELETE_REMOTE, 0, &udp::string_tag(name))
+}
+
+/// The system service has no symbols to upload, so only the transport is opened
+fn open_system_service(router: &ClientBuilder) -> Result<Box<dyn AdsTransport>> {
+    router
+        .clone()
+        .with_ams_port(SYSTEM_SERVICE_PORT)
+        .open_transport()
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use crate::mock::{Responder, Server};
+    use crate::Client;
+
+    #[test]
+    fn add_route_on_remote() {
+        let responder = Responder::builder()
+            .with_credentials("Administrator", "secret")
+            .start()
+            .unwrap();
+        let request = RouteRequest::new(AmsNetId::new([10, 0, 0, 3, 1, 1]), "10.0.0.3")
+            .with_route_name("commissioning")
+            .with_credentials("Administrator", "secret");
+
+        add_remote_route_at(responder.address(), &request, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(
+            responder.routes().unwrap(),
+            vec![Route::new(
+                "commissioning",
+                AmsNetId::new([10, 0, 0, 3, 1, 1]),
+                "10.0.0.3"
+            )]
+        );
+    }
+
+    #[test]
+    fn refuse_wrong_password() {
+        let responder = Responder::builder()
+            .with_credentials("Administrator", "secret")
+            .start()
+            .unwrap();
+        let request = RouteRequest::new(AmsNetId::new([10, 0, 0, 3, 1, 1]), "10.0.0.3");
+
+        let error =
+            add_remote_route_at(responder.address(), &request, Duration::from_secs(1)).unwrap_err();
+
+        assert_eq!(
+            result::code(&error),
+            Some(beckhoff::ADSERR_DEVICE_ACCESSDENIED)
+        );
+        assert!(responder.routes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn time_out_without_answer() {
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let request = RouteRequest::new(AmsNetId::new([10, 0, 0, 3, 1, 1]), "10.0.0.3");
+
+        let error = add_remote_route_at(
+            socket.local_addr().unwrap(),
+            &request,
+            Duration::from_millis(50),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn list_and_remove_routes() {
+        let server = Server::builder()
+            .with_route(Route::new(
+                "engineering",
+                AmsNetId::new([192, 168, 0, 5, 1, 1]),
+                "192.168.0.5",
+            ))
+            .with_route(Route::new(
+                "hmi",
+                AmsNetId::new([192, 168, 0, 6, 1, 1]),
+                "hmi-panel",
+            ))
+            .start()
+            .unwrap();
+        let router = Client::builder().with_tcp_target(server.address());
+
+        let routes = list_routes(&router).unwrap();
+        assert_eq!(
+            routes.iter().map(|r| r.name()).collect::<Vec<_>>(),
+            vec!["engineering", "hmi"]
+        );
+        assert_eq!(routes[1].address(), "hmi-panel");
+        assert_eq!(routes[1].ams_net_id().to_string(), "192.168.0.6.1.1");
+
+        remove_route(&router, "engineering").unwrap();
+        assert_eq!(list_routes(&router).unwrap().len(), 1);
+        assert!(remove_route(&router, "engineering").is_err());
+    }
+}
@@ -1,69 +1,67 @@
 use std::io::Result;
 
+use super::beckhoff;
 use super::client::Client;
+use super::symbols_and_data_types::{Bits, SymbolsAndDataTypes};
 use super::variables::Variable;
-use super::{beckhoff, result};
 
 impl Client {
     pub fn get_value(&self, value_name: impl AsRef<str>) -> Result<Variable> {
-        let data_types = self.symbols_and_data_types().data_types();
-        let (symbol_info, data_type_info) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-        let bytes = self.get_raw_bytes(value_name.as_ref(), data_type_info.size_bytes())?;
-        Variable::from_bytes(data_types, symbol_info, data_type_info, &bytes)
+        self.with_current_symbols(|symbols_and_data_types| {
+            self.check_dereferences(symbols_and_data_types, value_name.as_ref())?;
+            self.get_value_inner(symbols_and_data_types, value_name.as_ref(), &mut Vec::new())
+        })
     }
 
-    fn get_raw_bytes(&self, value_name: &str, symbol_size_bytes: usize) -> Result<Vec<u8>> {
-        const SIZE_U32: u32 = std::mem::size_of::<u32>() as u32;
-
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
-
-        let mut handle = 0;
-        let ptr_handle = &mut handle as *mut u32 as *mut std::os::raw::c_void;
-
-        let ptr_name = value_name as *const str as *mut std::os::raw::c_void;
-
-        result::process(unsafe {
-            beckhoff::AdsSyncReadWriteReqEx2(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_HNDBYNAME,
-                0,
-                SIZE_U32,
-                ptr_handle,
-                value_name.len() as u32,
-                ptr_name,
-                std::ptr::null_mut(),
-            )
-        })?;
+    /// `addresses` are those of the references followed so far
+    pub(super) fn get_value_inner(
+        &self,
+        symbols_and_data_types: &SymbolsAndDataTypes,
+        value_name: &str,
+        addresses: &mut Vec<u64>,
+    ) -> Result<Variable> {
+        let data_types = symbols_and_data_types.data_types();
+        let (symbol_info, data_type_info) =
+            symbols_and_data_types.get_symbol_and_data_type(value_name)?;
+        let bytes = match symbol_info.bits() {
+            Some(bits) => self.get_bits(value_name, bits, symbol_info.offset())?,
+            None => self.get_raw_bytes(value_name, data_type_info.size_bytes())?,
+        };
+        let mut value = Variable::from_bytes(data_types, symbol_info, data_type_info, &bytes)?;
+        if self.dereferencing() {
+            self.dereference_members(
+                symbols_and_data_types,
+                value_name,
+                data_type_info,
+                &mut value,
+                addresses,
+            )?;
+        }
+        Ok(value)
+    }
 
-        let mut buffer = vec![0; symbol_size_bytes];
-        let ptr_buffer = buffer.as_mut_ptr() as *mut std::os::raw::c_void;
+    pub(super) fn get_raw_bytes(
+        &self,
+        value_name: &str,
+        symbol_size_bytes: usize,
+    ) -> Result<Vec<u8>> {
+        let transport = self.transport();
+        self.handles()
+            .with_handle(&*transport, value_name, |handle| {
+                let mut buffer = vec![0; symbol_size_bytes];
 
-        result::process(unsafe {
-            beckhoff::AdsSyncReadReqEx2(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_VALBYHND,
-                handle,
-                symbol_size_bytes as u32,
-                ptr_buffer,
-                std::ptr::null_mut(),
-            )
-        })?;
+                transport.read(beckhoff::ADSIGRP_SYM_VALBYHND, handle, &mut buffer)?;
 
-        result::process(unsafe {
-            beckhoff::AdsSyncWriteReqEx(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_RELEASEHND,
-                0,
-                SIZE_U32,
-                ptr_handle,
-            )
-        })?;
+                Ok(buffer)
+            })
+    }
 
-        Ok(buffer)
+    /// Of a `BIT` struct member, from the byte it shares with others
+    fn get_bits(&self, value_name: &str, bits: Bits, byte_offset: usize) -> Result<Vec<u8>> {
+        let (index_group, index_offset) = self.bits_address(value_name, byte_offset)?;
+        let mut byte = [0];
+        self.transport()
+            .read(index_group, index_offset, &mut byte)?;
+        Ok(vec![bits.get(byte[0])])
     }
 }
@@ -2,8 +2,8 @@ use std::io::{Error, ErrorKind, Result};
 
 use strum_macros::EnumIter;
 
+use super::beckhoff;
 use super::client::Client;
-use super::{beckhoff, result};
 
 #[derive(Debug, EnumIter, PartialEq)]
 pub enum State {
@@ -29,7 +29,7 @@ pub enum State {
 }
 
 impl State {
-    fn from_beckhoff(state: i32) -> Result<Self> {
+    pub(super) fn from_beckhoff(state: i32) -> Result<Self> {
         match state {
             beckhoff::nAdsState_ADSSTATE_INVALID => Ok(State::Invalid),
             beckhoff::nAdsState_ADSSTATE_IDLE => Ok(State::Idle),
@@ -57,7 +57,7 @@ impl State {
         }
     }
 
-    fn to_beckhoff(&self) -> i32 {
+    pub(super) fn to_beckhoff(&self) -> i32 {
         match self {
             State::Invalid => beckhoff::nAdsState_ADSSTATE_INVALID,
             State::Idle => beckhoff::nAdsState_ADSSTATE_IDLE,
@@ -84,22 +84,7 @@ impl State {
 
 impl Client {
     pub fn get_ads_state(&self) -> Result<State> {
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
-
-        let mut ads_state = 0u16;
-        let ptr_ads_state = &mut ads_state as *mut u16;
-
-        let mut device_state = 0u16;
-        let ptr_device_state = &mut device_state as *mut u16;
-
-        result::process(unsafe {
-            beckhoff::AdsSyncReadStateReqEx(
-                self.port(),
-                ptr_address,
-                ptr_ads_state,
-                ptr_device_state,
-            )
-        })?;
+        let (ads_state, _device_state) = self.transport().read_state()?;
 
         State::from_beckhoff(ads_state as i32)
     }
@@ -110,20 +95,9 @@ impl Client {
             return Ok(());
         }
 
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
-
         let u16_state = state.to_beckhoff() as u16;
 
-        result::process(unsafe {
-            beckhoff::AdsSyncWriteControlReqEx(
-                self.port(),
-                ptr_address,
-                u16_state,
-                0,
-                0,
-                std::ptr::null_mut(),
-            )
-        })?;
+        self.transport().write_control(u16_state, 0, &[])?;
 
         Ok(())
     }
@@ -0,0 +1,422 @@
+//! ADS sum commands, which bundle many requests into one round trip
+
+use std::io::{Error, ErrorKind, Result};
+
+use super::client::Client;
+use super::dereference;
+use super::transport::AdsTransport;
+use super::variables::Variable;
+use super::{beckhoff, result};
+
+/// The most requests that a target accepts in one sum command
+pub(super) const MAX_SUB_COMMANDS: usize = 500;
+
+impl Client {
+    /// Reads every value in three round trips (creating handles, reading, releasing handles).
+    /// Returns the result of each value, in the order given.
+    pub fn get_values(&self, value_names: &[impl AsRef<str>]) -> Result<Vec<Result<Variable>>> {
+        self.with_current_symbols(|symbols_and_data_types| {
+            let data_types = symbols_and_data_types.data_types();
+
+            let found = value_names
+                .iter()
+                .map(|value_name| {
+                    dereference::check_not_dereferenced(value_name.as_ref())?;
+                    let (symbol, data_type) =
+                        symbols_and_data_types.get_symbol_and_data_type(value_name.as_ref())?;
+                    symbol.check_not_bits()?;
+                    Ok((value_name.as_ref(), symbol, data_type))
+                })
+                .collect();
+
+            let with_handles = on_successes(found, |found| {
+                let value_names = found.iter().map(|f| f.0).collect::<Vec<&str>>();
+                let handles = self.create_handles(&value_names)?;
+                Ok(found
+                    .into_iter()
+                    .zip(handles)
+                    .map(|(f, handle)| handle.map(|h| (f, h)))
+                    .collect())
+            })?;
+            let handles = handles_of(&with_handles);
+
+            let values = on_successes(with_handles, |with_handles| {
+                let requests = with_handles
+                    .iter()
+                    .map(|((_, _, data_type), handle)| {
+                        (
+                            beckhoff::ADSIGRP_SYM_VALBYHND,
+                            *handle,
+                            data_type.size_bytes(),
+                        )
+                    })
+                    .collect::<Vec<(u32, u32, usize)>>();
+                let bytes = sum_read(&*self.transport(), &requests)?;
+                Ok(with_handles
+                    .into_iter()
+                    .zip(bytes)
+                    .map(|(((_, symbol, data_type), _), bytes)| {
+                        bytes.and_then(|b| Variable::from_bytes(data_types, symbol, data_type, &b))
+                    })
+                    .collect())
+            });
+
+            let released = self.release_handles(&handles);
+            let values = values?;
+            released?;
+
+            Ok(values)
+        })
+    }
+
+    /// Writes every value in three round trips (creating handles, writing, releasing handles).
+    /// Returns the result of each value, in the order given.
+    pub fn set_values(&self, values: &[(impl AsRef<str>, Variable)]) -> Result<Vec<Result<()>>> {
+        self.with_current_symbols(|symbols_and_data_types| {
+            let data_types = symbols_and_data_types.data_types();
+
+            let encoded = values
+                .iter()
+                .map(|(value_name, value)| {
+                    let value_name = value_name.as_ref();
+                    dereference::check_not_dereferenced(value_name)?;
+                    let (symbol, data_type) =
+                        symbols_and_data_types.get_symbol_and_data_type(value_name)?;
+                    symbol.check_not_bits()?;
+                    let bytes = value.to_bytes(data_types, symbol, data_type)?;
+                    if bytes.len() > data_type.size_bytes() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!(
+                                "{value_name} has size {}, cannot write {} bytes",
+                                data_type.size_bytes(),
+                                bytes.len()
+                            ),
+                        ));
+                    }
+                    Ok((value_name, bytes))
+                })
+                .collect();
+
+            let with_handles = on_successes(encoded, |encoded| {
+                let value_names = encoded.iter().map(|e| e.0).collect::<Vec<&str>>();
+                let handles = self.create_handles(&value_names)?;
+                Ok(encoded
+                    .into_iter()
+                    .zip(handles)
+                    .map(|(e, handle)| handle.map(|h| (e, h)))
+                    .collect())
+            })?;
+            let handles = handles_of(&with_handles);
+
+            let written = on_successes(with_handles, |with_handles| {
+                let requests = with_handles
+                    .iter()
+                    .map(|((_, bytes), handle)| {
+                        (beckhoff::ADSIGRP_SYM_VALBYHND, *handle, bytes.as_slice())
+                    })
+                    .collect::<Vec<(u32, u32, &[u8])>>();
+                sum_write(&*self.transport(), &requests)
+            });
+
+            let released = self.release_handles(&handles);
+            let written = written?;
+            released?;
+
+            Ok(written)
+        })
+    }
+
+    fn create_handles(&self, value_names: &[&str]) -> Result<Vec<Result<u32>>> {
+        let requests = value_names
+            .iter()
+            .map(|value_name| {
+                (
+                    beckhoff::ADSIGRP_SYM_HNDBYNAME,
+                    0,
+                    std::mem::size_of::<u32>(),
+                    value_name.as_bytes(),
+                )
+            })
+            .collect::<Vec<(u32, u32, usize, &[u8])>>();
+
+        Ok(sum_read_write(&*self.transport(), &requests)?
+            .into_iter()
+            .map(|handle| handle.and_then(|h| u32_at(&h, 0)))
+            .collect())
+    }
+
+    fn release_handles(&self, handles: &[u32]) -> Result<()> {
+        let handles = handles
+            .iter()
+            .map(|handle| handle.to_le_bytes())
+            .collect::<Vec<[u8; 4]>>();
+        let requests = handles
+            .iter()
+            .map(|handle| (beckhoff::ADSIGRP_SYM_RELEASEHND, 0, handle.as_slice()))
+            .collect::<Vec<(u32, u32, &[u8])>>();
+
+        for released in sum_write(&*self.transport(), &requests)? {
+            released?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs `f` on the successful items only, and puts its results back in their places
+fn on_successes<T, U>(
+    items: Vec<Result<T>>,
+    f: impl FnOnce(Vec<T>) -> Result<Vec<Result<U>>>,
+) -> Result<Vec<Result<U>>> {
+    let mut successes = Vec::new();
+    let mut failures = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Ok(i) => {
+                successes.push(i);
+                failures.push(None);
+            }
+            Err(e) => failures.push(Some(e)),
+        }
+    }
+
+    let mut results = f(successes)?.into_iter();
+
+    Ok(failures
+        .into_iter()
+        .map(|failure| match failure {
+            Some(e) => Err(e),
+            None => results
+                .next()
+                .unwrap_or_else(|| Err(Error::other("Sum command returned too few results"))),
+        })
+        .collect())
+}
+
+fn handles_of<T>(with_handles: &[Result<(T, u32)>]) -> Vec<u32> {
+    with_handles
+        .iter()
+        .filter_map(|w| w.as_ref().ok().map(|(_, handle)| *handle))
+        .collect()
+}
+
+/// Each request is (index group, index offset, length).
+/// Returns the data read by each request.
+fn sum_read(
+    transport: &dyn AdsTransport,
+    requests: &[(u32, u32, usize)],
+) -> Result<Vec<Result<Vec<u8>>>> {
+    let mut output = Vec::with_capacity(requests.len());
+
+    for chunk in requests.chunks(MAX_SUB_COMMANDS) {
+        let mut write_data = Vec::with_capacity(12 * chunk.len());
+        for (index_group, index_offset, length) in chunk {
+            write_data.extend(index_group.to_le_bytes());
+            write_data.extend(index_offset.to_le_bytes());
+            write_data.extend((*length as u32).to_le_bytes());
+        }
+
+        // The result of each request, followed by the data of each request
+        let mut data_index = 4 * chunk.len();
+        let mut buffer = vec![0; data_index + chunk.iter().map(|r| r.2).sum::<usize>()];
+        transport.read_write(
+            beckhoff::ADSIGRP_SUMUP_READ,
+            chunk.len() as u32,
+            &mut buffer,
+            &write_data,
+        )?;
+
+        for (i, (_, _, length)) in chunk.iter().enumerate() {
+            let data = buffer[data_index..data_index + length].to_vec();
+            output.push(result_at(&buffer, 4 * i)?.map(|_| data));
+            data_index += length;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Each request is (index group, index offset, data)
+pub(super) fn sum_write(
+    transport: &dyn AdsTransport,
+    requests: &[(u32, u32, &[u8])],
+) -> Result<Vec<Result<()>>> {
+    let mut output = Vec::with_capacity(requests.len());
+
+    for chunk in requests.chunks(MAX_SUB_COMMANDS) {
+        // The result of each request
+        let mut buffer = vec![0; 4 * chunk.len()];
+        transport.read_write(
+            beckhoff::ADSIGRP_SUMUP_WRITE,
+            chunk.len() as u32,
+            &mut buffer,
+            &sum_write_data(chunk),
+        )?;
+        output.extend(sum_write_results(&buffer, chunk.len())?);
+    }
+
+    Ok(output)
+}
+
+/// The write data of a sum write of at most `MAX_SUB_COMMANDS` requests
+pub(super) fn sum_write_data(requests: &[(u32, u32, &[u8])]) -> Vec<u8> {
+    let mut write_data = Vec::new();
+    for (index_group, index_offset, data) in requests {
+        write_data.extend(index_group.to_le_bytes());
+        write_data.extend(index_offset.to_le_bytes());
+        write_data.extend((data.len() as u32).to_le_bytes());
+    }
+    for (_, _, data) in requests {
+        write_data.extend(*data);
+    }
+    write_data
+}
+
+/// The result of each of `n_requests` from the response to a sum write
+pub(super) fn sum_write_results(response: &[u8], n_requests: usize) -> Result<Vec<Result<()>>> {
+    (0..n_requests)
+        .map(|i| result_at(response, 4 * i))
+        .collect()
+}
+
+/// Each request is (index group, index offset, read length, write data).
+/// Returns the data read by each request.
+fn sum_read_write(
+    transport: &dyn AdsTransport,
+    requests: &[(u32, u32, usize, &[u8])],
+) -> Result<Vec<Result<Vec<u8>>>> {
+    let mut output = Vec::with_capacity(requests.len());
+
+    for chunk in requests.chunks(MAX_SUB_COMMANDS) {
+        let mut write_data = Vec::new();
+        for (index_group, index_offset, read_length, data) in chunk {
+            write_data.extend(index_group.to_le_bytes());
+            write_data.extend(index_offset.to_le_bytes());
+            write_data.extend((*read_length as u32).to_le_bytes());
+            write_data.extend((data.len() as u32).to_le_bytes());
+        }
+        for (_, _, _, data) in chunk {
+            write_data.extend(*data);
+        }
+
+        // The result and length of each request, followed by the data of each request
+        let mut data_index = 8 * chunk.len();
+        let mut buffer = vec![0; data_index + chunk.iter().map(|r| r.2).sum::<usize>()];
+        transport.read_write(
+            beckhoff::ADSIGRP_SUMUP_READWRITE,
+            chunk.len() as u32,
+            &mut buffer,
+            &write_data,
+        )?;
+
+        for i in 0..chunk.len() {
+            let length = u32_at(&buffer, 8 * i + 4)? as usize;
+            let data = match buffer.get(data_index..data_index + length) {
+                Some(d) => d.to_vec(),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Sum command result of {length} bytes overruns the response"),
+                    ))
+                }
+            };
+            output.push(result_at(&buffer, 8 * i)?.map(|_| data));
+            data_index += length;
+        }
+    }
+
+    Ok(output)
+}
+
+/// The outer result fails if the response is too short; the inner result is the ADS result code
+fn result_at(bytes: &[u8], index: usize) -> Result<Result<()>> {
+    Ok(result::process(u32_at(bytes, index)? as i32))
+}
+
+fn u32_at(bytes: &[u8], index: usize) -> Result<u32> {
+    match bytes.get(index..index + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Expected at least {} bytes, got {}", index + 4, bytes.len()),
+        )),
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use crate::mock::{connect, fixture, Server};
+    use crate::StartIndex;
+
+    fn server() -> Server {
+        fixture(
+            &[
+                ("main.count", "UINT"),
+                ("main.ratios", "ARRAY [0..1] OF REAL"),
+                ("main.label", "STRING(8)"),
+            ],
+            &[
+                ("main.count", Variable::U16(12)),
+                ("main.label", Variable::String(String::from("Tank"))),
+            ],
+        )
+        .start()
+        .unwrap()
+    }
+
+    #[test]
+    fn get_and_set_many_values() {
+        let server = server();
+        let client = connect(&server);
+
+        let written = client
+            .set_values(&[
+                ("main.ratios[1]", Variable::F32(0.25)),
+                ("main.missing", Variable::F32(1.0)),
+                ("main.count", Variable::U16(40)),
+                ("main.count", Variable::I8(1)),
+            ])
+            .unwrap();
+        assert_eq!(written.len(), 4);
+        assert!(written[0].is_ok());
+        assert!(written[1].is_err());
+        assert!(written[2].is_ok());
+        assert!(written[3].is_err());
+
+        let values = client
+            .get_values(&["main.label", "main.missing", "main.count", "main.ratios"])
+            .unwrap();
+        assert_eq!(values.len(), 4);
+        assert_eq!(
+            values[0].as_ref().unwrap(),
+            &Variable::String(String::from("Tank"))
+        );
+        assert!(values[1].is_err());
+        assert_eq!(values[2].as_ref().unwrap(), &Variable::U16(40));
+        assert_eq!(
+            values[3].as_ref().unwrap(),
+            &Variable::Array(
+                StartIndex::Some(0),
+                vec![Variable::F32(0.0), Variable::F32(0.25)]
+            )
+        );
+    }
+
+    #[test]
+    fn split_into_sum_commands_of_limited_size() {
+        let server = server();
+        let client = connect(&server);
+
+        let value_names = vec!["main.count"; MAX_SUB_COMMANDS * 2 + 1];
+        let values = client.get_values(&value_names).unwrap();
+        assert_eq!(values.len(), value_names.len());
+        for value in values {
+            assert_eq!(value.unwrap(), Variable::U16(12));
+        }
+
+        assert!(client.get_values(&[] as &[&str]).unwrap().is_empty());
+    }
+}
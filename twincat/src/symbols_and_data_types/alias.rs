@@ -0,0 +1,142 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Longer alias chains are taken to be cycles
+pub(super) const MAX_DEPTH: usize = 32;
+
+/// Splits a subrange such as `INT(0..100)` or `INT (0..100)` into its type and bounds.
+/// Other names, including `STRING(80)`, have no bounds.
+pub(super) fn split_subrange(name: &str) -> (&str, Option<RangeInclusive<i64>>) {
+    let bounds = name
+        .find('(')
+        .and_then(|open| Some((open, name[open + 1..].strip_suffix(')')?)))
+        .and_then(|(open, inner)| {
+            let (start, end) = inner.split_once("..")?;
+            let start = i64::from_str(start.trim()).ok()?;
+            let end = i64::from_str(end.trim()).ok()?;
+            Some((open, RangeInclusive::new(start, end)))
+        });
+
+    match bounds {
+        Some((open, range)) => (name[..open].trim(), Some(range)),
+        None => (name, None),
+    }
+}
+
+/// A subrange of a subrange is bounded by both
+pub(super) fn intersect(
+    outer: Option<RangeInclusive<i64>>,
+    inner: RangeInclusive<i64>,
+) -> RangeInclusive<i64> {
+    match outer {
+        Some(outer) => RangeInclusive::new(
+            *outer.start().max(inner.start()),
+            *outer.end().min(inner.end()),
+        ),
+        None => inner,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use std::time::Duration;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{connect, Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::{StartIndex, Variable as V};
+
+    #[test]
+    fn parse_subranges() {
+        assert_eq!(split_subrange("INT(0..100)"), ("INT", Some(0..=100)));
+        assert_eq!(split_subrange("DINT (-5 .. 5)"), ("DINT", Some(-5..=5)));
+        assert_eq!(split_subrange("STRING(80)"), ("STRING(80)", None));
+        assert_eq!(split_subrange("Percent"), ("Percent", None));
+        assert_eq!(split_subrange("INT(a..b)"), ("INT(a..b)", None));
+
+        assert_eq!(intersect(None, 0..=100), 0..=100);
+        assert_eq!(intersect(Some(0..=100), 50..=150), 50..=100);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_and_set_aliases() {
+        let server = Server::builder()
+            .with_struct(
+                "Limits",
+                &[
+                    Declaration::new("low", "INT"),
+                    Declaration::new("high", "INT"),
+                ],
+            )
+            .with_alias("Bounds", "Limits")
+            .with_alias("Percent", "INT(0..100)")
+            .with_alias("Level", "Percent")
+            .with_alias("Wait", "TIME")
+            .with_symbol(Declaration::new("main.bounds", "Bounds"))
+            .with_symbol(Declaration::new("main.level", "Level"))
+            .with_symbol(Declaration::new("main.levels", "ARRAY [0..1] OF Percent"))
+            .with_symbol(Declaration::new("main.offset", "SINT(-5..5)"))
+            .with_symbol(Declaration::new("main.wait", "Wait"))
+            .with_value("main.level", V::I16(50))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        // Aliases read as the types they stand for
+        assert_eq!(
+            client.get_value("main.bounds").unwrap(),
+            V::Struct(vec![
+                (String::from("low"), V::I16(0)),
+                (String::from("high"), V::I16(0)),
+            ])
+        );
+        client
+            .set_value("main.wait", V::Time(Duration::from_millis(1500)))
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.wait").unwrap(),
+            V::Time(Duration::from_millis(1500))
+        );
+        assert_eq!(client.get_value("main.level").unwrap(), V::I16(50));
+
+        client.set_value("main.level", V::I16(100)).unwrap();
+        client.set_value_from_str("main.levels[1]", "0").unwrap();
+        client.set_value_from_str("main.offset", "-5").unwrap();
+        assert_eq!(client.get_value("main.level").unwrap(), V::I16(100));
+        assert_eq!(client.get_value("main.offset").unwrap(), V::I8(-5));
+
+        // Subranges, and aliases of them, hold only the values within their bounds
+        for (value_name, value) in [
+            ("main.level", V::I16(101)),
+            (
+                "main.levels",
+                V::Array(StartIndex::Some(0), vec![V::I16(1), V::I16(-1)]),
+            ),
+            ("main.offset", V::I8(6)),
+        ] {
+            assert_eq!(
+                client
+                    .verify_ads_path_and_variable_type(value_name, value.clone())
+                    .unwrap_err()
+                    .kind(),
+                std::io::ErrorKind::InvalidInput
+            );
+            assert_eq!(
+                client.set_value(value_name, value).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidInput
+            );
+        }
+        assert_eq!(
+            client
+                .set_value_from_str("main.levels[0]", "-1")
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        assert_eq!(client.get_value("main.level").unwrap(), V::I16(100));
+    }
+}
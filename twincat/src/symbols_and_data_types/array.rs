@@ -41,10 +41,17 @@ pub(super) fn get_base_name(data_type: &str, n_array_accessings: Option<u8>) ->
             }
             remainder
         }
-        None => match data_type.rfind(" OF ") {
-            Some(start) => data_type[start + 4..].trim(),
-            None => data_type,
-        },
+        // Not `rfind(" OF ")`, which would reach into `ARRAY [0..1] OF POINTER TO ARRAY [..] OF X`
+        None => {
+            let mut remainder = data_type;
+            while remainder.starts_with("ARRAY") {
+                match remainder.find(" OF ") {
+                    Some(i_of) => remainder = remainder[i_of + 4..].trim(),
+                    None => break,
+                }
+            }
+            remainder
+        }
     };
 
     Ok(base_name)
@@ -50,11 +50,12 @@ impl SymbolsAndDataTypes {
             if (filter.filter)(symbol.1) {
                 output.push(symbol.0.to_string());
             } else {
-                let data_type = if let Some(dt) = self.data_types.0.get(&symbol.1.data_type_name) {
-                    dt
-                } else {
-                    continue;
-                };
+                let data_type =
+                    if let Some(dt) = self.data_types.data_types.get(&symbol.1.data_type_name) {
+                        dt
+                    } else {
+                        continue;
+                    };
                 let symbols = self.recurse(filter, data_type);
                 for s0 in symbols {
                     output.extend(s0.to_strings(symbol.0.to_string()));
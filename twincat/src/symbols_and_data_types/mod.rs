@@ -2,10 +2,14 @@ use std::collections::HashMap;
 use std::io::{Error, ErrorKind, Result};
 use std::ops::RangeInclusive;
 
-use super::{beckhoff, result};
+use super::beckhoff;
+use super::transport::AdsTransport;
+use super::variables::{self, StringEncoding};
 
+mod alias;
 mod array;
 mod filters;
+mod pointer;
 
 #[derive(Clone, Debug, Default)]
 pub struct SymbolsAndDataTypes {
@@ -17,7 +21,11 @@ pub struct SymbolsAndDataTypes {
 pub struct Symbols(HashMap<String, Symbol>);
 
 #[derive(Clone, Debug, Default)]
-pub struct DataTypes(HashMap<String, DataType>);
+pub struct DataTypes {
+    data_types: HashMap<String, DataType>,
+    /// How the target stores `STRING`s
+    string_encoding: StringEncoding,
+}
 
 #[derive(Clone, Debug)]
 pub struct Symbol {
@@ -28,6 +36,31 @@ pub struct Symbol {
     group: Group,
     persistent: bool,
     _comment: Option<String>,
+    bits: Option<Bits>,
+}
+
+/// Where a `BIT` struct member lies within the byte at its offset, which it shares with others
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Bits {
+    pub(super) offset: u8,
+    pub(super) size: u8,
+}
+
+impl Bits {
+    /// The value of these bits in `byte`, shifted down
+    pub(super) fn get(self, byte: u8) -> u8 {
+        (byte >> self.offset) & self.mask()
+    }
+
+    /// `byte` with these bits replaced by `value`, and the others kept
+    pub(super) fn set(self, byte: u8, value: u8) -> u8 {
+        let mask = self.mask() << self.offset;
+        (byte & !mask) | ((value << self.offset) & mask)
+    }
+
+    fn mask(self) -> u8 {
+        (0xff_u16 >> (8 - self.size)) as u8
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -46,6 +79,15 @@ pub struct DataType {
     _comment: Option<String>,
     array_ranges: Vec<RangeInclusive<i32>>,
     fields: Vec<Symbol>,
+    /// The members of an enum, with their values
+    enum_members: Vec<(String, i64)>,
+    data_type_id: u8,
+    /// What a `POINTER TO` or `REFERENCE TO` points to, as a symbol at its address
+    pointee: Option<Box<Symbol>>,
+    /// The type which an alias, such as `TYPE Percent : INT(0..100)`, stands for, as declared
+    alias_of: Option<String>,
+    /// A `UNION`, as its type flags mark it, rather than a struct
+    is_union: bool,
 }
 
 impl SymbolsAndDataTypes {
@@ -55,14 +97,16 @@ impl SymbolsAndDataTypes {
     ) -> Result<(&Symbol, &DataType)> {
         let symbol = self.get_symbol(value_name)?;
 
-        let n_array_accessings = array::count_accessors(value_name);
+        // Accessors before a `^` index the pointer, which `get_symbol` has already dereferenced
+        let after_dereference = value_name.rsplit_once('^').map_or(value_name, |(_, a)| a);
+        let n_array_accessings = array::count_accessors(after_dereference);
         let data_type_info = if n_array_accessings > 0 {
             self.data_types
                 .symbol_get_base_type(symbol, Some(n_array_accessings))?
         } else {
-            match self.data_types.0.get(&symbol.data_type_name) {
-                Some(dti) => dti,
-                None => {
+            match self.data_types.get(&symbol.data_type_name) {
+                Ok(dti) => dti,
+                Err(_) => {
                     return Err(Error::new(
                         ErrorKind::NotFound,
                         format!("Cannot find data type info for\n{symbol:?}"),
@@ -83,9 +127,9 @@ impl SymbolsAndDataTypes {
                     format!("Cannot find {value_name} (it seems to be empty)"),
                 ))
             }
-            [one] => one.to_string(),
+            [one] => pointer::trim_dereferences(one).0,
             [one, two, ..] => {
-                let two_base = array::trim_accessors(two);
+                let two_base = array::trim_accessors(&pointer::trim_dereferences(two).0);
                 format!("{one}.{two_base}")
             }
         };
@@ -99,9 +143,12 @@ impl SymbolsAndDataTypes {
                 ))
             }
         };
+        let n_dereferences = pointer::trim_dereferences(tokens[tokens.len().min(2) - 1]).1;
+        symbol_entry = self.dereference(value_name, symbol_entry, n_dereferences)?;
 
         for token in &tokens[2..] {
-            let token_base = array::trim_accessors(token);
+            let (token_dereferenced, n_dereferences) = pointer::trim_dereferences(token);
+            let token_base = array::trim_accessors(&token_dereferenced);
             let parent_data_type = self.data_types.symbol_get_base_type(symbol_entry, None)?;
             let mut found = false;
             for field in &parent_data_type.fields {
@@ -117,28 +164,107 @@ impl SymbolsAndDataTypes {
                     format!("Cannot find {token} in {parent_data_type:?}"),
                 ));
             }
+            symbol_entry = self.dereference(value_name, symbol_entry, n_dereferences)?;
         }
 
         Ok(symbol_entry)
     }
 
+    /// What `symbol` points to, following `n_dereferences` pointers
+    fn dereference<'a>(
+        &'a self,
+        value_name: &str,
+        mut symbol: &'a Symbol,
+        n_dereferences: usize,
+    ) -> Result<&'a Symbol> {
+        for _ in 0..n_dereferences {
+            let data_type = self.data_types.symbol_get_base_type(symbol, None)?;
+            symbol = match &data_type.pointee {
+                Some(p) => p,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{value_name} dereferences {}, which is {} rather than a POINTER or REFERENCE",
+                            symbol.name, data_type.name
+                        ),
+                    ))
+                }
+            };
+        }
+        Ok(symbol)
+    }
+
     pub fn symbols(&self) -> &Symbols {
         &self.symbols
     }
     pub(super) fn data_types(&self) -> &DataTypes {
         &self.data_types
     }
+
+    /// Defaults to Windows-1252, as TwinCAT does
+    pub(super) fn with_string_encoding(mut self, string_encoding: StringEncoding) -> Self {
+        self.data_types.string_encoding = string_encoding;
+        self
+    }
 }
 
 impl DataTypes {
+    /// Follows aliases, and aliases of aliases, to the type they stand for
     pub(super) fn get(&self, name: &str) -> Result<&DataType> {
-        match self.0.get(name) {
-            Some(dt) => Ok(dt),
-            None => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Cannot find {name} in data types"),
-            )),
+        let mut data_type = match self.data_types.get(alias::split_subrange(name).0) {
+            Some(dt) => dt,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Cannot find {name} in data types"),
+                ))
+            }
+        };
+
+        for _ in 0..alias::MAX_DEPTH {
+            match self.alias_target(data_type) {
+                Some(target) => data_type = target,
+                None => return Ok(data_type),
+            }
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{name} is an alias of itself"),
+        ))
+    }
+
+    /// The bounds of a subrange such as `INT(0..100)`, or of those which an alias leads to.
+    /// For an array, those of its elements.
+    pub(super) fn symbol_subrange(&self, symbol: &Symbol) -> Option<RangeInclusive<i64>> {
+        let mut range = None;
+        let mut name = data_type_get_base_name(&symbol.data_type_name, None).ok()?;
+        for _ in 0..alias::MAX_DEPTH {
+            let (base_name, bounds) = alias::split_subrange(name);
+            if let Some(bounds) = bounds {
+                range = Some(alias::intersect(range, bounds));
+            }
+            let alias_of = self
+                .data_types
+                .get(base_name)
+                .filter(|data_type| self.alias_target(data_type).is_some())
+                .and_then(|data_type| data_type.alias_of.as_deref());
+            match alias_of {
+                Some(alias_of) => name = alias_of,
+                None => break,
+            }
         }
+        range
+    }
+
+    /// Only an alias of a type which was uploaded too is followed
+    fn alias_target(&self, data_type: &DataType) -> Option<&DataType> {
+        let alias_of = data_type.alias_of.as_deref()?;
+        self.data_types.get(alias::split_subrange(alias_of).0)
+    }
+
+    pub(super) fn string_encoding(&self) -> StringEncoding {
+        self.string_encoding
     }
 
     pub(super) fn data_type_get_base_type(&self, data_type: &DataType) -> Result<&DataType> {
@@ -146,7 +272,7 @@ impl DataTypes {
         self.get(base_type)
     }
 
-    fn symbol_get_base_type(
+    pub(super) fn symbol_get_base_type(
         &self,
         symbol: &Symbol,
         n_array_accessings: Option<u8>,
@@ -156,134 +282,154 @@ impl DataTypes {
     }
 }
 
+/// A `POINTER TO` or `REFERENCE TO` is its own data type; only `^` leads to what it points to
 fn data_type_get_base_name(data_type: &str, n_array_accessings: Option<u8>) -> Result<&str> {
-    let base_name = array::get_base_name(data_type, n_array_accessings)?;
+    array::get_base_name(data_type, n_array_accessings)
+}
 
-    let base_name = match base_name.rfind(" TO ") {
-        Some(start) => base_name[start + 4..].trim(),
-        None => base_name,
-    };
+pub(super) fn upload(transport: &dyn AdsTransport) -> Result<SymbolsAndDataTypes> {
+    let mut upload_info_bytes = [0; UPLOAD_INFO_LENGTH];
+    transport.read(beckhoff::ADSIGRP_SYM_UPLOADINFO2, 0, &mut upload_info_bytes)?;
+    let upload_info = UploadInfo::from_bytes(&upload_info_bytes)?;
 
-    Ok(base_name)
-}
+    let mut symbol_bytes: Box<[u8]> = vec![0; upload_info.symbol_size].into_boxed_slice();
+    transport.read(beckhoff::ADSIGRP_SYM_UPLOAD, 0, &mut symbol_bytes)?;
 
-pub(super) fn upload(
-    ams_address: &beckhoff::AmsAddr,
-    ads_port: i32,
-) -> Result<SymbolsAndDataTypes> {
-    let mut symbols = SymbolsAndDataTypes::default();
-
-    let mut address = *ams_address;
-    let ptr_address = &mut address as *mut beckhoff::AmsAddr;
-
-    let mut upload_info = beckhoff::AdsSymbolUploadInfo2::default();
-    let ptr_upload_info =
-        &mut upload_info as *mut beckhoff::AdsSymbolUploadInfo2 as *mut std::os::raw::c_void;
-
-    result::process(unsafe {
-        beckhoff::AdsSyncReadReqEx2(
-            ads_port,
-            ptr_address,
-            beckhoff::ADSIGRP_SYM_UPLOADINFO2,
-            0,
-            std::mem::size_of::<beckhoff::AdsSymbolUploadInfo2>() as u32,
-            ptr_upload_info,
-            std::ptr::null_mut(),
-        )
-    })?;
-
-    symbols.symbols = upload_symbols(
-        ams_address,
-        ads_port,
-        upload_info.nSymSize,
-        upload_info.nSymbols,
-    )?;
-
-    symbols.data_types = upload_data_types(
-        ams_address,
-        ads_port,
-        upload_info.nDatatypeSize,
-        upload_info.nDatatypes,
-    )?;
-
-    Ok(symbols)
+    let mut data_type_bytes: Box<[u8]> = vec![0; upload_info.data_type_size].into_boxed_slice();
+    transport.read(beckhoff::ADSIGRP_SYM_DT_UPLOAD, 0, &mut data_type_bytes)?;
+
+    upload_info.parse(&symbol_bytes, &data_type_bytes)
 }
 
-fn upload_symbols(
-    ams_address: &beckhoff::AmsAddr,
-    ads_port: i32,
-    size: u32,
-    n: u32,
-) -> Result<Symbols> {
-    let mut output = Symbols(HashMap::new());
-
-    let mut address = *ams_address;
-    let ptr_address = &mut address as *mut beckhoff::AmsAddr;
-
-    let mut buffer: Box<[u8]> = vec![0; size as usize].into_boxed_slice();
-    let ptr_buffer = buffer.as_mut_ptr() as *mut std::os::raw::c_void;
-
-    result::process(unsafe {
-        beckhoff::AdsSyncReadReqEx2(
-            ads_port,
-            ptr_address,
-            beckhoff::ADSIGRP_SYM_UPLOAD,
-            0,
-            size,
-            ptr_buffer,
-            std::ptr::null_mut(),
-        )
-    })?;
+/// The length of the `ADSIGRP_SYM_UPLOADINFO2` read which starts an upload
+pub(super) const UPLOAD_INFO_LENGTH: usize = std::mem::size_of::<beckhoff::AdsSymbolUploadInfo2>();
 
-    let mut offset = 0;
-    for _ in 0..n {
-        let (symbol, n_bytes) = Symbol::from_bytes(&buffer[offset..])?;
-        output.0.insert(symbol.name.clone(), symbol);
+/// How many symbols and data types an upload holds, and in how many bytes,
+/// shared by `Client` and `AsyncClient`, which each send the reads in their own way
+pub(super) struct UploadInfo {
+    n_symbols: u32,
+    pub(super) symbol_size: usize,
+    n_data_types: u32,
+    pub(super) data_type_size: usize,
+}
 
-        offset += n_bytes;
+impl UploadInfo {
+    pub(super) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < UPLOAD_INFO_LENGTH {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Expected upload info of {UPLOAD_INFO_LENGTH} bytes, got {}",
+                    bytes.len()
+                ),
+            ));
+        }
+        let upload_info: &beckhoff::AdsSymbolUploadInfo2 = unsafe { &*bytes.as_ptr().cast() };
+
+        Ok(Self {
+            n_symbols: upload_info.nSymbols,
+            symbol_size: upload_info.nSymSize as usize,
+            n_data_types: upload_info.nDatatypes,
+            data_type_size: upload_info.nDatatypeSize as usize,
+        })
     }
 
-    Ok(output)
+    /// Parses the results of the `ADSIGRP_SYM_UPLOAD` and `ADSIGRP_SYM_DT_UPLOAD` reads
+    pub(super) fn parse(
+        &self,
+        symbol_bytes: &[u8],
+        data_type_bytes: &[u8],
+    ) -> Result<SymbolsAndDataTypes> {
+        SymbolsAndDataTypes::from_bytes(
+            symbol_bytes,
+            self.n_symbols,
+            data_type_bytes,
+            self.n_data_types,
+        )
+    }
 }
 
-fn upload_data_types(
-    ams_address: &beckhoff::AmsAddr,
-    ads_port: i32,
-    size: u32,
-    n: u32,
-) -> Result<DataTypes> {
-    let mut output = DataTypes(HashMap::new());
-
-    let mut address = *ams_address;
-    let ptr_address = &mut address as *mut beckhoff::AmsAddr;
-
-    let mut buffer: Box<[u8]> = vec![0; size as usize].into_boxed_slice();
-    let ptr_buffer = buffer.as_mut_ptr() as *mut std::os::raw::c_void;
-
-    result::process(unsafe {
-        beckhoff::AdsSyncReadReqEx2(
-            ads_port,
-            ptr_address,
-            beckhoff::ADSIGRP_SYM_DT_UPLOAD,
-            0,
-            size,
-            ptr_buffer,
-            std::ptr::null_mut(),
-        )
-    })?;
+impl SymbolsAndDataTypes {
+    /// Parses the results of `ADSIGRP_SYM_UPLOAD` and `ADSIGRP_SYM_DT_UPLOAD` requests
+    pub(super) fn from_bytes(
+        symbol_bytes: &[u8],
+        n_symbols: u32,
+        data_type_bytes: &[u8],
+        n_data_types: u32,
+    ) -> Result<Self> {
+        Ok(Self {
+            symbols: Symbols::from_bytes(symbol_bytes, n_symbols)?,
+            data_types: DataTypes::from_bytes(data_type_bytes, n_data_types)?,
+        })
+    }
+}
+
+impl Symbols {
+    fn from_bytes(bytes: &[u8], n: u32) -> Result<Self> {
+        let mut output = Symbols(HashMap::new());
 
-    let mut offset = 0;
-    for _ in 0..n {
-        let (data_type_info, n_bytes) = DataType::from_bytes(&buffer[offset..])?;
-        output.0.insert(data_type_info.name.clone(), data_type_info);
+        let mut offset = 0;
+        for _ in 0..n {
+            let (symbol, n_bytes) = Symbol::from_bytes(&bytes[offset..])?;
+            output.0.insert(symbol.name.clone(), symbol);
+
+            offset += n_bytes;
+        }
 
-        offset += n_bytes;
+        Ok(output)
     }
+}
+
+impl DataTypes {
+    fn from_bytes(bytes: &[u8], n: u32) -> Result<Self> {
+        let mut output = DataTypes::default();
+
+        let mut offset = 0;
+        for _ in 0..n {
+            let (data_type_info, n_bytes) = DataType::from_bytes(&bytes[offset..])?;
+            output
+                .data_types
+                .insert(data_type_info.name.clone(), data_type_info);
 
-    Ok(output)
+            offset += n_bytes;
+        }
+
+        // Pointers name what they point to, which may come later in the upload
+        let pointees = output
+            .data_types
+            .values()
+            .filter_map(|data_type| {
+                let pointee = output
+                    .data_types
+                    .get(pointer::pointee_name(&data_type.name)?)?;
+                Some((data_type.name.clone(), Symbol::pointee(pointee)))
+            })
+            .collect::<Vec<(String, Symbol)>>();
+        for (name, pointee) in pointees {
+            if let Some(data_type) = output.data_types.get_mut(&name) {
+                data_type.pointee = Some(Box::new(pointee));
+            }
+        }
+
+        Ok(output)
+    }
 }
 
 impl Symbol {
+    /// What a pointer points to, which has no name or offset of its own
+    fn pointee(data_type: &DataType) -> Self {
+        Self {
+            name: String::from("^"),
+            data_type_id: data_type.data_type_id,
+            data_type_name: data_type.name.clone(),
+            offset: 0,
+            group: Group::StructField,
+            persistent: false,
+            _comment: None,
+            bits: None,
+        }
+    }
+
     fn from_bytes(bytes: &[u8]) -> Result<(Self, usize)> {
         const DETAILS_LENGTH: usize = std::mem::size_of::<beckhoff::AdsSymbolEntry>();
 
@@ -310,6 +456,7 @@ impl Symbol {
                 persistent: entry.flags & beckhoff::ADSSYMBOLFLAG_PERSISTENT
                     == beckhoff::ADSSYMBOLFLAG_PERSISTENT,
                 _comment: comment,
+                bits: None,
             },
             entry.entryLength as usize,
         ))
@@ -330,17 +477,40 @@ impl Symbol {
 
         let data_type_name = bytes_get_string(&bytes[data_type_name_start..data_type_name_end])?;
         let comment = bytes_get_comment(&bytes[comment_start..comment_end])?;
+        let name = bytes_get_string(&bytes[name_start..name_end])?;
+
+        // The offset and size of bit values are in bits
+        let (offset, bits) = if entry.flags & beckhoff::ADSDATATYPEFLAG_BITVALUES != 0 {
+            let bits = Bits {
+                offset: (entry.offs % 8) as u8,
+                size: entry.size as u8,
+            };
+            if entry.size == 0 || bits.offset as u32 + entry.size > 8 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{name} has {} bits at bit offset {}, which do not fit in one byte",
+                        { entry.size },
+                        { entry.offs }
+                    ),
+                ));
+            }
+            (entry.offs as usize / 8, Some(bits))
+        } else {
+            (entry.offs as usize, None)
+        };
 
         Ok((
             Self {
-                name: bytes_get_string(&bytes[name_start..name_end])?,
+                name,
                 data_type_id: entry.dataType as u8,
                 data_type_name,
-                offset: entry.offs as usize,
+                offset,
                 group: Group::StructField,
                 persistent: (entry.flags >> 8) & beckhoff::ADSSYMBOLFLAG_PERSISTENT
                     == beckhoff::ADSSYMBOLFLAG_PERSISTENT,
                 _comment: comment,
+                bits,
             },
             entry.entryLength as usize,
         ))
@@ -358,6 +528,29 @@ impl Symbol {
     pub(super) fn offset(&self) -> usize {
         self.offset
     }
+    /// For `BIT` struct members; `offset` is then that of the byte which holds them
+    pub(super) fn bits(&self) -> Option<Bits> {
+        self.bits
+    }
+
+    pub(super) fn is_reference(&self) -> bool {
+        pointer::is_reference(&self.data_type_name)
+    }
+
+    /// Handles address whole bytes, so a `BIT` struct member can only be accessed
+    /// through the byte it shares with others
+    pub(super) fn check_not_bits(&self) -> Result<()> {
+        match self.bits {
+            Some(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "{} is a BIT which shares its byte with others; use Client::get_value and Client::set_value",
+                    self.name
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Group {
@@ -394,6 +587,7 @@ impl DataType {
         let field_info_start = comment_end + 1 + (entry.arrayDim as usize * ARRAY_INFO_LENGTH);
 
         let name = bytes_get_string(&bytes[name_start..name_end])?;
+        let type_name = bytes_get_string(&bytes[type_start..type_end])?;
 
         let comment = bytes_get_comment(&bytes[comment_start..comment_end])?;
 
@@ -407,6 +601,21 @@ impl DataType {
             field_this_start += length;
         }
 
+        let enum_members = if entry.flags & beckhoff::ADSDATATYPEFLAG_ENUMINFOS != 0 {
+            let enum_info_start = skip_optional_infos(bytes, field_this_start, entry)?;
+            enum_infos_from_bytes(bytes, enum_info_start, entry)?
+        } else {
+            Vec::new()
+        };
+
+        // Arrays, pointers and enums also name another type, but are not aliases of it
+        let is_alias = fields.is_empty()
+            && enum_members.is_empty()
+            && array_ranges.is_empty()
+            && pointer::pointee_name(&name).is_none()
+            && !type_name.is_empty()
+            && type_name != name;
+
         Ok((
             Self {
                 name,
@@ -414,6 +623,11 @@ impl DataType {
                 _comment: comment,
                 array_ranges,
                 fields,
+                enum_members,
+                data_type_id: entry.dataType as u8,
+                pointee: None,
+                alias_of: is_alias.then_some(type_name),
+                is_union: entry.flags & beckhoff::ADSDATATYPEFLAG_UNION != 0,
             },
             entry.entryLength as usize,
         ))
@@ -428,6 +642,133 @@ impl DataType {
     pub(super) fn fields(&self) -> &[Symbol] {
         &self.fields
     }
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+    pub(super) fn is_enum(&self) -> bool {
+        !self.enum_members.is_empty()
+    }
+    pub(super) fn is_union(&self) -> bool {
+        self.is_union
+    }
+    /// Members are matched regardless of case, as TwinCAT does
+    pub(super) fn enum_value(&self, member: &str) -> Option<i64> {
+        self.enum_members
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(member))
+            .map(|(_, value)| *value)
+    }
+    /// The first member with this value
+    pub(super) fn enum_member(&self, value: i64) -> Option<&str> {
+        self.enum_members
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| name.as_str())
+    }
+    pub(super) fn enum_members(&self) -> &[(String, i64)] {
+        &self.enum_members
+    }
+}
+
+/// Steps over the type GUID, copy mask, method infos and attributes, whichever are present,
+/// to where the enum infos start
+fn skip_optional_infos(
+    bytes: &[u8],
+    mut offset: usize,
+    entry: &beckhoff::AdsDatatypeEntry,
+) -> Result<usize> {
+    const TYPE_GUID_LENGTH: usize = 16;
+
+    if entry.flags & beckhoff::ADSDATATYPEFLAG_TYPEGUID != 0 {
+        offset += TYPE_GUID_LENGTH;
+    }
+    if entry.flags & beckhoff::ADSDATATYPEFLAG_COPYMASK != 0 {
+        offset += entry.size as usize;
+    }
+    if entry.flags & beckhoff::ADSDATATYPEFLAG_METHODINFOS != 0 {
+        let n_methods = u16_at(bytes, offset)?;
+        offset += 2;
+        for _ in 0..n_methods {
+            // Each method info starts with its own length
+            offset += u32_at(bytes, offset)? as usize;
+        }
+    }
+    if entry.flags & beckhoff::ADSDATATYPEFLAG_ATTRIBUTES != 0 {
+        let n_attributes = u16_at(bytes, offset)?;
+        offset += 2;
+        for _ in 0..n_attributes {
+            let name_length = byte_at(bytes, offset)? as usize;
+            let value_length = byte_at(bytes, offset + 1)? as usize;
+            offset += 2 + name_length + 1 + value_length + 1;
+        }
+    }
+
+    Ok(offset)
+}
+
+/// The number of members, then for each its name length, its NUL-terminated name,
+/// and its value (as many bytes as the enum)
+fn enum_infos_from_bytes(
+    bytes: &[u8],
+    mut offset: usize,
+    entry: &beckhoff::AdsDatatypeEntry,
+) -> Result<Vec<(String, i64)>> {
+    let size_bytes = entry.size as usize;
+    let n_members = u16_at(bytes, offset)?;
+    offset += 2;
+
+    let mut members = Vec::with_capacity(n_members as usize);
+    for _ in 0..n_members {
+        let name_length = byte_at(bytes, offset)? as usize;
+        let name_start = offset + 1;
+        let value_start = name_start + name_length + 1;
+        let value_end = value_start + size_bytes;
+        let (name, value) = match (
+            bytes.get(name_start..name_start + name_length),
+            bytes.get(value_start..value_end),
+        ) {
+            (Some(n), Some(v)) => (n, v),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Enum info at {offset} is truncated"),
+                ))
+            }
+        };
+        members.push((
+            bytes_get_string(name)?,
+            variables::bytes_to_integer(value, entry.dataType as u8)?,
+        ));
+        offset = value_end;
+    }
+
+    Ok(members)
+}
+
+fn byte_at(bytes: &[u8], index: usize) -> Result<u8> {
+    match bytes.get(index) {
+        Some(b) => Ok(*b),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Data type upload is truncated at {index}"),
+        )),
+    }
+}
+
+fn u16_at(bytes: &[u8], index: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes([
+        byte_at(bytes, index)?,
+        byte_at(bytes, index + 1)?,
+    ]))
+}
+
+fn u32_at(bytes: &[u8], index: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes([
+        byte_at(bytes, index)?,
+        byte_at(bytes, index + 1)?,
+        byte_at(bytes, index + 2)?,
+        byte_at(bytes, index + 3)?,
+    ]))
 }
 
 fn bytes_get_comment(bytes: &[u8]) -> Result<Option<String>> {
@@ -449,3 +790,167 @@ fn bytes_get_string(bytes: &[u8]) -> Result<String> {
         ))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{connect, Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::Variable as V;
+
+    /// An `INT` enum called `Food`, with a type GUID and an attribute before its enum infos
+    fn food_entry() -> Vec<u8> {
+        const DETAILS_LENGTH: usize = std::mem::size_of::<beckhoff::AdsDatatypeEntry>();
+
+        let mut tail = vec![0xab; 16];
+        tail.extend(1u16.to_le_bytes());
+        tail.extend([14, 3]);
+        tail.extend(b"qualified_only\0yes\0");
+        tail.extend(2u16.to_le_bytes());
+        tail.extend([6]);
+        tail.extend(b"Carrot\0");
+        tail.extend(0i16.to_le_bytes());
+        tail.extend([4]);
+        tail.extend(b"Leek\0");
+        tail.extend((-5i16).to_le_bytes());
+
+        let flags = beckhoff::ADSDATATYPEFLAG_DATATYPE
+            | beckhoff::ADSDATATYPEFLAG_TYPEGUID
+            | beckhoff::ADSDATATYPEFLAG_ATTRIBUTES
+            | beckhoff::ADSDATATYPEFLAG_ENUMINFOS;
+        let entry_length = DETAILS_LENGTH + "Food".len() + "INT".len() + 3 + tail.len();
+
+        let mut bytes = Vec::new();
+        for field in [entry_length as u32, 1, 0, 0, 2, 0, 2, flags] {
+            bytes.extend(field.to_le_bytes());
+        }
+        for field in [4u16, 3, 0, 0, 0] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(b"Food\0INT\0\0");
+        bytes.extend(tail);
+        bytes
+    }
+
+    #[test]
+    fn parse_enum_infos() {
+        let bytes = food_entry();
+        let (data_type, length) = DataType::from_bytes(&bytes).unwrap();
+
+        assert_eq!(length, bytes.len());
+        assert_eq!(
+            data_type.enum_members(),
+            &[(String::from("Carrot"), 0), (String::from("Leek"), -5)]
+        );
+        assert_eq!(data_type.enum_value("leek"), Some(-5));
+        assert_eq!(data_type.enum_member(0), Some("Carrot"));
+        assert_eq!(data_type.enum_member(1), None);
+    }
+
+    #[test]
+    fn get_and_set_bits() {
+        let bits = Bits { offset: 2, size: 3 };
+        assert_eq!(bits.get(0b1001_0100), 0b101);
+        assert_eq!(bits.set(0b1111_1111, 0b010), 0b1110_1011);
+        assert_eq!(bits.set(0, 0b1111), 0b0001_1100);
+
+        let bit = Bits { offset: 7, size: 1 };
+        assert_eq!(bit.get(0x80), 1);
+        assert_eq!(bit.set(0x81, 0), 0x01);
+    }
+
+    #[test]
+    fn reject_truncated_enum_infos() {
+        let bytes = food_entry();
+        assert_eq!(
+            DataType::from_bytes(&bytes[..bytes.len() - 3])
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_and_set_bit_members() {
+        let status = |ready, busy, error, code| {
+            V::Struct(vec![
+                (String::from("ready"), V::Bool(ready)),
+                (String::from("busy"), V::Bool(busy)),
+                (String::from("error"), V::Bool(error)),
+                (String::from("code"), V::I8(code)),
+            ])
+        };
+        let server = Server::builder()
+            .with_struct(
+                "Status",
+                &[
+                    Declaration::new("ready", "BIT"),
+                    Declaration::new("busy", "BIT"),
+                    Declaration::new("error", "BIT"),
+                    Declaration::new("code", "SINT"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.status", "Status"))
+            .with_value("main.status.busy", V::Bool(true))
+            .with_value("main.status.code", V::I8(-3))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        let symbols_and_data_types = client.symbols_and_data_types();
+        let fields = symbols_and_data_types
+            .data_types()
+            .get("Status")
+            .unwrap()
+            .fields();
+        assert_eq!(
+            fields
+                .iter()
+                .map(|f| (f.offset(), f.bits().map(|b| b.offset)))
+                .collect::<Vec<_>>(),
+            vec![(0, Some(0)), (0, Some(1)), (0, Some(2)), (1, None)]
+        );
+
+        assert_eq!(
+            client.get_value("main.status").unwrap(),
+            status(false, true, false, -3)
+        );
+        assert_eq!(client.get_value("main.status.busy").unwrap(), V::Bool(true));
+
+        // The other bits of the byte are kept
+        client
+            .set_value("main.status.error", V::Bool(true))
+            .unwrap();
+        client
+            .set_value_from_str("main.status.busy", "false")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.status").unwrap(),
+            status(false, false, true, -3)
+        );
+
+        client
+            .set_value("main.status", status(true, true, false, 12))
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.status.ready").unwrap(),
+            V::Bool(true)
+        );
+        assert_eq!(
+            client.get_value("main.status").unwrap(),
+            status(true, true, false, 12)
+        );
+
+        // Handles address whole bytes
+        assert_eq!(
+            client.get_values(&["main.status.ready"]).unwrap()[0]
+                .as_ref()
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+    }
+}
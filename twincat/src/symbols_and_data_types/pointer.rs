@@ -0,0 +1,45 @@
+/// What a `POINTER TO` or `REFERENCE TO` points to
+pub(super) fn pointee_name(data_type: &str) -> Option<&str> {
+    data_type
+        .strip_prefix("POINTER TO ")
+        .or_else(|| data_type.strip_prefix("REFERENCE TO "))
+        .map(str::trim)
+}
+
+pub(super) fn is_reference(data_type: &str) -> bool {
+    data_type.starts_with("REFERENCE TO ")
+}
+
+/// Splits `ptr^` or `ptrs[1]^^` into the name without `^`, and how many times it is dereferenced
+pub(super) fn trim_dereferences(token: &str) -> (String, usize) {
+    let n_dereferences = token.matches('^').count();
+    (token.replace('^', ""), n_dereferences)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_pointers() {
+        assert_eq!(pointee_name("POINTER TO ST_Config"), Some("ST_Config"));
+        assert_eq!(pointee_name("REFERENCE TO INT"), Some("INT"));
+        assert_eq!(
+            pointee_name("POINTER TO POINTER TO INT"),
+            Some("POINTER TO INT")
+        );
+        assert_eq!(pointee_name("ARRAY [0..1] OF POINTER TO INT"), None);
+        assert!(is_reference("REFERENCE TO INT"));
+        assert!(!is_reference("POINTER TO INT"));
+
+        assert_eq!(trim_dereferences("config"), (String::from("config"), 0));
+        assert_eq!(
+            trim_dereferences("configs[1]^^"),
+            (String::from("configs[1]"), 2)
+        );
+        assert_eq!(
+            trim_dereferences("limits^[2]"),
+            (String::from("limits[2]"), 1)
+        );
+    }
+}
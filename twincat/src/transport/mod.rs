@@ -0,0 +1,351 @@
+use std::io::Result;
+#[cfg(feature = "notifications")]
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(windows)]
+mod tc_ads_dll;
+#[cfg(windows)]
+pub(super) use tc_ads_dll::TcAdsDll;
+#[cfg(feature = "tcp")]
+pub(super) mod tcp;
+#[cfg(feature = "tcp")]
+pub(super) use tcp::Tcp;
+#[cfg(feature = "tls")]
+pub(super) mod tls;
+
+#[cfg(not(any(windows, feature = "tcp")))]
+compile_error!("TcAdsDll is only available on Windows; enable the `tcp` feature to use the native AMS/TCP transport");
+
+/// The route a `Client` takes to its target.
+/// Every ADS request goes through here, so `Client` does not care which implementation is in use.
+///
+/// TcAdsDll (on Windows), AMS/TCP (with the `tcp` feature) and Secure ADS (with the `tls` feature)
+/// are built in.
+/// Others, such as a proxy which records requests or a fake target, can be given to
+/// `ClientBuilder::with_transport`.
+/// The transport is closed when it is dropped.
+pub trait AdsTransport: Send + Sync {
+    /// Returns the number of bytes read
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize>;
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()>;
+
+    /// Returns the number of bytes read
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize>;
+
+    /// Returns the ADS state and the device state
+    fn read_state(&self) -> Result<(u16, u16)>;
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()>;
+
+    /// Requests made through the returned view wait at most `timeout` for their response,
+    /// then fail with `ErrorKind::TimedOut`.
+    /// By default the timeout is ignored.
+    fn with_timeout(&self, _timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        Box::new(self)
+    }
+
+    /// `callback` is called with each sample until the notification is deleted.
+    /// Returns the notification handle.
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        _index_group: u32,
+        _index_offset: u32,
+        _attributes: &NotificationAttributes,
+        _callback: NotificationCallback,
+    ) -> Result<u32> {
+        Err(notifications_unsupported())
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, _notification_handle: u32) -> Result<()> {
+        Err(notifications_unsupported())
+    }
+}
+
+#[cfg(feature = "notifications")]
+pub struct NotificationAttributes {
+    /// The number of bytes in each sample
+    pub length: u32,
+    /// One of the `nAdsTransMode` values
+    pub transmission_mode: i32,
+    /// In units of 100ns
+    pub max_delay: u32,
+    /// In units of 100ns
+    pub cycle_time: u32,
+}
+
+#[cfg(feature = "notifications")]
+pub type NotificationCallback = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+/// `transport`, or a view of it bounded by `timeout` if there is one
+pub(super) fn timed(
+    transport: &dyn AdsTransport,
+    timeout: Option<Duration>,
+) -> Box<dyn AdsTransport + '_> {
+    match timeout {
+        Some(t) => transport.with_timeout(t),
+        None => Box::new(transport),
+    }
+}
+
+impl<T: AdsTransport + ?Sized> AdsTransport for Box<T> {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        (**self).read(index_group, index_offset, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        (**self).write(index_group, index_offset, data)
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        (**self).read_write(index_group, index_offset, read_buffer, write_data)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        (**self).read_state()
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        (**self).write_control(ads_state, device_state, data)
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        (**self).with_timeout(timeout)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        (**self).add_notification(index_group, index_offset, attributes, callback)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        (**self).delete_notification(notification_handle)
+    }
+}
+
+impl<T: AdsTransport + ?Sized> AdsTransport for &T {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        (**self).read(index_group, index_offset, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        (**self).write(index_group, index_offset, data)
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        (**self).read_write(index_group, index_offset, read_buffer, write_data)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        (**self).read_state()
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        (**self).write_control(ads_state, device_state, data)
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        (**self).with_timeout(timeout)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        (**self).add_notification(index_group, index_offset, attributes, callback)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        (**self).delete_notification(notification_handle)
+    }
+}
+
+impl<T: AdsTransport + ?Sized> AdsTransport for Arc<T> {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        (**self).read(index_group, index_offset, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        (**self).write(index_group, index_offset, data)
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        (**self).read_write(index_group, index_offset, read_buffer, write_data)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        (**self).read_state()
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        (**self).write_control(ads_state, device_state, data)
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        (**self).with_timeout(timeout)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        (**self).add_notification(index_group, index_offset, attributes, callback)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        (**self).delete_notification(notification_handle)
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn notifications_unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "This transport does not support notifications",
+    )
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::mock::{Declaration, Server};
+    use crate::{Client, Variable};
+
+    /// Counts the requests that pass through it
+    struct Counter {
+        inner: Box<dyn AdsTransport>,
+        n_requests: Arc<AtomicUsize>,
+    }
+
+    impl AdsTransport for Counter {
+        fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            self.inner.read(index_group, index_offset, buffer)
+        }
+
+        fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            self.inner.write(index_group, index_offset, data)
+        }
+
+        fn read_write(
+            &self,
+            index_group: u32,
+            index_offset: u32,
+            read_buffer: &mut [u8],
+            write_data: &[u8],
+        ) -> Result<usize> {
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            self.inner
+                .read_write(index_group, index_offset, read_buffer, write_data)
+        }
+
+        fn read_state(&self) -> Result<(u16, u16)> {
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            self.inner.read_state()
+        }
+
+        fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+            self.n_requests.fetch_add(1, Ordering::Relaxed);
+            self.inner.write_control(ads_state, device_state, data)
+        }
+    }
+
+    #[test]
+    fn wrap_transport() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.speed", "LREAL"))
+            .with_value("main.speed", Variable::F64(3.5))
+            .start()
+            .unwrap();
+
+        let builder = Client::builder().with_tcp_target(server.address());
+        let n_requests = Arc::new(AtomicUsize::new(0));
+        let counter = Counter {
+            inner: builder.open_transport().unwrap(),
+            n_requests: n_requests.clone(),
+        };
+        let client = builder.with_transport(counter).connect().unwrap();
+
+        // Symbol version, upload info, symbols and data types
+        assert_eq!(n_requests.load(Ordering::Relaxed), 4);
+
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::F64(3.5));
+        // Create handle, read
+        assert_eq!(n_requests.load(Ordering::Relaxed), 6);
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::F64(3.5));
+        // Read, with the handle kept from last time
+        assert_eq!(n_requests.load(Ordering::Relaxed), 7);
+
+        #[cfg(feature = "notifications")]
+        assert_eq!(
+            client
+                .request_notifications(
+                    String::from("main.speed"),
+                    crate::AdsTransmissionMode::OnChange,
+                    None,
+                    None,
+                    |_, _| {},
+                )
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Unsupported
+        );
+
+        let n_requests_before_drop = n_requests.load(Ordering::Relaxed);
+        drop(client);
+        // Release handle
+        assert_eq!(
+            n_requests.load(Ordering::Relaxed),
+            n_requests_before_drop + 1
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "notifications")]
+use lazy_static::lazy_static;
+
+use super::AdsTransport;
+#[cfg(feature = "notifications")]
+use super::{NotificationAttributes, NotificationCallback};
+use crate::{beckhoff, result};
+
+/// The timeout belongs to the port, so it is set once, when the port is opened,
+/// and requests on the port do not wait for each other.
+/// Requests with another timeout go through a port of their own, which is kept open
+/// as long as this one, so that the handles made through it stay valid.
+pub(crate) struct TcAdsDll {
+    port: i32,
+    address: beckhoff::AmsAddr,
+    /// The port's timeout, in ms
+    timeout_ms: i32,
+    /// The ports opened for other timeouts, by timeout in ms
+    timed_ports: Mutex<HashMap<i32, Arc<TcAdsDll>>>,
+    /// Only the transport `open` returns holds it; the ports opened for other timeouts do not
+    _legacy_port: Option<LegacyPort>,
+}
+
+/// The port `AdsPortOpen` opens, which is closed when this is dropped,
+/// after the transport's own port
+struct LegacyPort;
+
+/// A port for another timeout which could not be opened; its requests fail with the reason why
+struct Unopened {
+    kind: ErrorKind,
+    message: String,
+}
+
+/// TcAdsDll passes a `u32` user value to the callback; this maps it back to the notification's callback
+#[cfg(feature = "notifications")]
+#[derive(Default)]
+struct Callbacks {
+    next_user: u32,
+    by_user: HashMap<u32, NotificationCallback>,
+    /// By (port, notification handle)
+    users: HashMap<(i32, u32), u32>,
+}
+
+#[cfg(feature = "notifications")]
+lazy_static! {
+    static ref CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks::default());
+}
+
+impl TcAdsDll {
+    /// Without a `timeout`, the port keeps the TcAdsDll default (5 seconds)
+    pub(crate) fn open(address: beckhoff::AmsAddr, timeout: Option<Duration>) -> Result<Self> {
+        let legacy_port = LegacyPort::open();
+        let mut transport = Self::open_port(address, timeout)?;
+        transport._legacy_port = Some(legacy_port);
+        Ok(transport)
+    }
+
+    pub(crate) fn local_address() -> beckhoff::AmsAddr {
+        let mut ams_address = beckhoff::AmsAddr::default();
+        unsafe { beckhoff::AdsGetLocalAddress(&mut ams_address) };
+        ams_address
+    }
+
+    fn open_port(address: beckhoff::AmsAddr, timeout: Option<Duration>) -> Result<Self> {
+        // Closes the port again if anything below fails
+        let mut transport = Self {
+            port: unsafe { beckhoff::AdsPortOpenEx() },
+            address,
+            timeout_ms: 0,
+            timed_ports: Mutex::new(HashMap::new()),
+            _legacy_port: None,
+        };
+
+        match timeout {
+            Some(timeout) => {
+                transport.timeout_ms = to_ms(timeout);
+                result::process(unsafe {
+                    beckhoff::AdsSyncSetTimeoutEx(transport.port, transport.timeout_ms)
+                })?;
+            }
+            None => result::process(unsafe {
+                beckhoff::AdsSyncGetTimeoutEx(transport.port, &mut transport.timeout_ms)
+            })?,
+        }
+
+        Ok(transport)
+    }
+
+    fn timed_port(&self, timeout_ms: i32) -> Result<Arc<TcAdsDll>> {
+        let mut timed_ports = match self.timed_ports.lock() {
+            Ok(t) => t,
+            Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+        };
+        if let Some(transport) = timed_ports.get(&timeout_ms) {
+            return Ok(transport.clone());
+        }
+        let transport = Arc::new(Self::open_port(
+            self.address,
+            Some(Duration::from_millis(timeout_ms as u64)),
+        )?);
+        timed_ports.insert(timeout_ms, transport.clone());
+        Ok(transport)
+    }
+}
+
+impl Drop for TcAdsDll {
+    fn drop(&mut self) {
+        unsafe { beckhoff::AdsPortCloseEx(self.port) };
+    }
+}
+
+impl LegacyPort {
+    fn open() -> Self {
+        unsafe { beckhoff::AdsPortOpen() };
+        Self
+    }
+}
+
+impl Drop for LegacyPort {
+    fn drop(&mut self) {
+        unsafe { beckhoff::AdsPortClose() };
+    }
+}
+
+impl Unopened {
+    fn error(&self) -> Error {
+        Error::new(self.kind, self.message.clone())
+    }
+}
+
+impl AdsTransport for Unopened {
+    fn read(&self, _: u32, _: u32, _: &mut [u8]) -> Result<usize> {
+        Err(self.error())
+    }
+
+    fn write(&self, _: u32, _: u32, _: &[u8]) -> Result<()> {
+        Err(self.error())
+    }
+
+    fn read_write(&self, _: u32, _: u32, _: &mut [u8], _: &[u8]) -> Result<usize> {
+        Err(self.error())
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        Err(self.error())
+    }
+
+    fn write_control(&self, _: u16, _: u16, _: &[u8]) -> Result<()> {
+        Err(self.error())
+    }
+}
+
+impl AdsTransport for TcAdsDll {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        let mut address = self.address;
+        let mut n_bytes_read = 0;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncReadReqEx2(
+                self.port,
+                &mut address,
+                index_group,
+                index_offset,
+                buffer.len() as u32,
+                buffer.as_mut_ptr() as *mut c_void,
+                &mut n_bytes_read,
+            )
+        })?;
+
+        Ok(n_bytes_read as usize)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        let mut address = self.address;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncWriteReqEx(
+                self.port,
+                &mut address,
+                index_group,
+                index_offset,
+                data.len() as u32,
+                data.as_ptr() as *mut c_void,
+            )
+        })
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        let mut address = self.address;
+        let mut n_bytes_read = 0;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncReadWriteReqEx2(
+                self.port,
+                &mut address,
+                index_group,
+                index_offset,
+                read_buffer.len() as u32,
+                read_buffer.as_mut_ptr() as *mut c_void,
+                write_data.len() as u32,
+                write_data.as_ptr() as *mut c_void,
+                &mut n_bytes_read,
+            )
+        })?;
+
+        Ok(n_bytes_read as usize)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        let mut address = self.address;
+        let mut ads_state = 0u16;
+        let mut device_state = 0u16;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncReadStateReqEx(
+                self.port,
+                &mut address,
+                &mut ads_state,
+                &mut device_state,
+            )
+        })?;
+
+        Ok((ads_state, device_state))
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        let mut address = self.address;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncWriteControlReqEx(
+                self.port,
+                &mut address,
+                ads_state,
+                device_state,
+                data.len() as u32,
+                data.as_ptr() as *mut c_void,
+            )
+        })
+    }
+
+    /// Through the port kept for `timeout`, opened by the first request which needs it,
+    /// unless this port already has `timeout`
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        let timeout_ms = to_ms(timeout);
+        if timeout_ms == self.timeout_ms {
+            return Box::new(self);
+        }
+        match self.timed_port(timeout_ms) {
+            Ok(transport) => Box::new(transport),
+            Err(e) => Box::new(Unopened {
+                kind: e.kind(),
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        let mut address = self.address;
+
+        // Register the callback first, so that the first sample is not dropped
+        let user = {
+            let mut callbacks = lock_callbacks()?;
+            let user = callbacks.next_user;
+            callbacks.next_user = callbacks.next_user.wrapping_add(1);
+            callbacks.by_user.insert(user, callback);
+            user
+        };
+
+        let mut ads_notification_attribute = beckhoff::AdsNotificationAttrib {
+            cbLength: attributes.length,
+            nTransMode: attributes.transmission_mode,
+            nMaxDelay: attributes.max_delay,
+            __bindgen_anon_1: beckhoff::AdsNotificationAttrib__bindgen_ty_1 {
+                nCycleTime: attributes.cycle_time,
+            },
+        };
+
+        let mut notification_handle = 0;
+
+        let added = result::process(unsafe {
+            beckhoff::AdsSyncAddDeviceNotificationReqEx(
+                self.port,
+                &mut address,
+                index_group,
+                index_offset,
+                &mut ads_notification_attribute,
+                Some(callback_wrapper),
+                user,
+                &mut notification_handle,
+            )
+        });
+
+        let mut callbacks = lock_callbacks()?;
+        match added {
+            Ok(()) => {
+                callbacks
+                    .users
+                    .insert((self.port, notification_handle), user);
+                Ok(notification_handle)
+            }
+            Err(e) => {
+                callbacks.by_user.remove(&user);
+                Err(e)
+            }
+        }
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        let mut address = self.address;
+
+        result::process(unsafe {
+            beckhoff::AdsSyncDelDeviceNotificationReqEx(
+                self.port,
+                &mut address,
+                notification_handle,
+            )
+        })?;
+
+        let mut callbacks = lock_callbacks()?;
+        if let Some(user) = callbacks.users.remove(&(self.port, notification_handle)) {
+            callbacks.by_user.remove(&user);
+        }
+
+        Ok(())
+    }
+}
+
+fn to_ms(timeout: Duration) -> i32 {
+    timeout.as_millis().clamp(1, i32::MAX as u128) as i32
+}
+
+#[cfg(feature = "notifications")]
+fn lock_callbacks() -> Result<std::sync::MutexGuard<'static, Callbacks>> {
+    match CALLBACKS.lock() {
+        Ok(c) => Ok(c),
+        Err(e) => Err(std::io::Error::other(format!("Lock failure!\n{e}"))),
+    }
+}
+
+#[cfg(feature = "notifications")]
+unsafe extern "C" fn callback_wrapper(
+    _: *mut beckhoff::AmsAddr,
+    ptr_notification: *mut beckhoff::AdsNotificationHeader,
+    user: std::os::raw::c_ulong,
+) {
+    let sample_size = (*ptr_notification).cbSampleSize as usize;
+    let data_slice = std::slice::from_raw_parts((*ptr_notification).data.as_ptr(), sample_size);
+    let callback = match CALLBACKS.lock() {
+        Ok(c) => c.by_user.get(&user).cloned(),
+        Err(_) => return,
+    };
+    if let Some(callback) = callback {
+        callback(data_slice);
+    }
+}
@@ -0,0 +1,742 @@
+//! A native implementation of AMS/TCP, for talking to a target without TcAdsDll
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "tls")]
+use super::tls::{self, TlsConfig};
+use super::AdsTransport;
+#[cfg(feature = "notifications")]
+use super::{NotificationAttributes, NotificationCallback};
+use crate::{beckhoff, result, AmsNetId};
+
+pub(crate) const AMS_TCP_HEADER_LENGTH: usize = 6;
+pub(crate) const AMS_HEADER_LENGTH: usize = 32;
+/// The longest AMS packet accepted, well above any an AMS router passes on,
+/// so that a broken length in a header does not make us allocate gigabytes
+pub(crate) const MAX_AMS_PACKET_LENGTH: usize = 64 * 1024 * 1024;
+
+pub(crate) const STATE_FLAG_ADS_COMMAND: u16 = 0x0004;
+
+/// The AMS port this end of the connection claims to be
+const LOCAL_AMS_PORT: u16 = 32905;
+/// How long to wait for a connection or a response, unless the client is given another timeout
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(feature = "notifications"), allow(dead_code))]
+pub(crate) enum Command {
+    Read = 2,
+    Write = 3,
+    ReadState = 4,
+    WriteControl = 5,
+    AddDeviceNotification = 6,
+    DeleteDeviceNotification = 7,
+    DeviceNotification = 8,
+    ReadWrite = 9,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AmsHeader {
+    pub(crate) target: beckhoff::AmsAddr,
+    pub(crate) source: beckhoff::AmsAddr,
+    pub(crate) command_id: u16,
+    pub(crate) state_flags: u16,
+    pub(crate) error_code: u32,
+    pub(crate) invoke_id: u32,
+}
+
+type Response = (u32, Vec<u8>);
+
+/// Requests awaiting a response, by invoke ID.
+/// `None` once the connection has closed.
+type Pending = Arc<Mutex<Option<HashMap<u32, Sender<Response>>>>>;
+
+#[cfg(feature = "notifications")]
+type NotificationCallbacks = Arc<Mutex<HashMap<u32, NotificationCallback>>>;
+
+pub(crate) struct Tcp {
+    target: beckhoff::AmsAddr,
+    source: beckhoff::AmsAddr,
+    /// Shut down when dropped, which ends the receiving thread
+    socket: TcpStream,
+    writer: Mutex<Box<dyn Write + Send>>,
+    invoke_id: AtomicU32,
+    pending: Pending,
+    timeout: Duration,
+    /// The callback of each notification, by notification handle
+    #[cfg(feature = "notifications")]
+    notification_callbacks: NotificationCallbacks,
+}
+
+impl Tcp {
+    /// See `local_address` for the default `local_net_id`.
+    /// `timeout` bounds the connection and, unless overridden, each request.
+    pub(crate) fn connect(
+        socket_address: SocketAddr,
+        target: beckhoff::AmsAddr,
+        local_net_id: Option<AmsNetId>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let socket = open(socket_address, timeout)?;
+        let source = local_address(socket.local_addr()?, local_net_id)?;
+        let reader = socket.try_clone()?;
+        let writer = socket.try_clone()?;
+        Ok(Self::start(
+            socket,
+            Box::new(reader),
+            Box::new(writer),
+            target,
+            source,
+            timeout,
+        ))
+    }
+
+    /// As `connect`, over Secure ADS
+    #[cfg(feature = "tls")]
+    pub(crate) fn connect_tls(
+        socket_address: SocketAddr,
+        target: beckhoff::AmsAddr,
+        local_net_id: Option<AmsNetId>,
+        tls_config: &TlsConfig,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let socket = open(socket_address, timeout)?;
+        let source = local_address(socket.local_addr()?, local_net_id)?;
+
+        socket.set_read_timeout(Some(timeout))?;
+        let (reader, writer) = tls::connect(
+            tls_config,
+            socket.try_clone()?,
+            AmsNetId::new(source.netId.b),
+        )?;
+        socket.set_read_timeout(None)?;
+
+        Ok(Self::start(
+            socket,
+            Box::new(reader),
+            Box::new(writer),
+            target,
+            source,
+            timeout,
+        ))
+    }
+
+    /// Starts receiving from `reader` on its own thread
+    fn start(
+        socket: TcpStream,
+        reader: Box<dyn Read + Send>,
+        writer: Box<dyn Write + Send>,
+        target: beckhoff::AmsAddr,
+        source: beckhoff::AmsAddr,
+        timeout: Duration,
+    ) -> Self {
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+
+        #[cfg(feature = "notifications")]
+        let notification_callbacks = Arc::new(Mutex::new(HashMap::new()));
+        #[cfg(feature = "notifications")]
+        let notifications = spawn_notification_dispatcher(notification_callbacks.clone());
+
+        let reader_pending = pending.clone();
+        thread::spawn(move || {
+            receive(
+                reader,
+                reader_pending,
+                #[cfg(feature = "notifications")]
+                notifications,
+            )
+        });
+
+        Self {
+            target,
+            source,
+            socket,
+            writer: Mutex::new(writer),
+            invoke_id: AtomicU32::new(1),
+            pending,
+            timeout,
+            #[cfg(feature = "notifications")]
+            notification_callbacks,
+        }
+    }
+
+    fn timed(&self, timeout: Duration) -> Timed<'_> {
+        Timed { tcp: self, timeout }
+    }
+
+    /// Sends one request and waits up to `timeout` for its response.
+    /// Returns the response data after the ADS result code.
+    fn request(&self, command: Command, data: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let invoke_id = self.invoke_id.fetch_add(1, Ordering::Relaxed);
+
+        let (sender, receiver) = mpsc::channel();
+        match self.pending.lock() {
+            Ok(mut pending) => match pending.as_mut() {
+                Some(p) => {
+                    p.insert(invoke_id, sender);
+                }
+                None => return Err(connection_closed()),
+            },
+            Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+
+        let header = AmsHeader {
+            target: self.target,
+            source: self.source,
+            command_id: command as u16,
+            state_flags: STATE_FLAG_ADS_COMMAND,
+            error_code: 0,
+            invoke_id,
+        };
+        let sent = match self.writer.lock() {
+            Ok(mut writer) => write_frame(&mut *writer, &header, data),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        };
+        if let Err(e) = sent {
+            self.forget(invoke_id);
+            return Err(e);
+        }
+
+        let (error_code, response) = match receiver.recv_timeout(timeout) {
+            Ok(r) => r,
+            Err(RecvTimeoutError::Timeout) => {
+                self.forget(invoke_id);
+                (beckhoff::ADSERR_CLIENT_SYNCTIMEOUT, Vec::new())
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(connection_closed()),
+        };
+        result::process(error_code as i32)?;
+
+        // Every response except to a device notification starts with the ADS result code
+        result::process(u32_at(&response, 0)? as i32)?;
+        Ok(response[4..].to_vec())
+    }
+
+    fn forget(&self, invoke_id: u32) {
+        if let Ok(mut pending) = self.pending.lock() {
+            if let Some(p) = pending.as_mut() {
+                p.remove(&invoke_id);
+            }
+        }
+    }
+}
+
+/// A view of `Tcp` whose requests wait for their own timeout
+struct Timed<'a> {
+    tcp: &'a Tcp,
+    timeout: Duration,
+}
+
+impl Timed<'_> {
+    fn request(&self, command: Command, data: &[u8]) -> Result<Vec<u8>> {
+        self.tcp.request(command, data, self.timeout)
+    }
+}
+
+impl AdsTransport for Timed<'_> {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        let request = read_request(index_group, index_offset, buffer.len());
+        let response = self.request(Command::Read, &request)?;
+        copy_read_data(&response, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        let request = write_request(index_group, index_offset, data);
+        self.request(Command::Write, &request)?;
+        Ok(())
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        let request = read_write_request(index_group, index_offset, read_buffer.len(), write_data);
+        let response = self.request(Command::ReadWrite, &request)?;
+        copy_read_data(&response, read_buffer)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        let response = self.request(Command::ReadState, &[])?;
+        Ok((u16_at(&response, 0)?, u16_at(&response, 2)?))
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        let request = write_control_request(ads_state, device_state, data);
+        self.request(Command::WriteControl, &request)?;
+        Ok(())
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        Box::new(self.tcp.timed(timeout))
+    }
+}
+
+impl AdsTransport for Tcp {
+    fn read(&self, index_group: u32, index_offset: u32, buffer: &mut [u8]) -> Result<usize> {
+        self.timed(self.timeout)
+            .read(index_group, index_offset, buffer)
+    }
+
+    fn write(&self, index_group: u32, index_offset: u32, data: &[u8]) -> Result<()> {
+        self.timed(self.timeout)
+            .write(index_group, index_offset, data)
+    }
+
+    fn read_write(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        read_buffer: &mut [u8],
+        write_data: &[u8],
+    ) -> Result<usize> {
+        self.timed(self.timeout)
+            .read_write(index_group, index_offset, read_buffer, write_data)
+    }
+
+    fn read_state(&self) -> Result<(u16, u16)> {
+        self.timed(self.timeout).read_state()
+    }
+
+    fn write_control(&self, ads_state: u16, device_state: u16, data: &[u8]) -> Result<()> {
+        self.timed(self.timeout)
+            .write_control(ads_state, device_state, data)
+    }
+
+    fn with_timeout(&self, timeout: Duration) -> Box<dyn AdsTransport + '_> {
+        Box::new(self.timed(timeout))
+    }
+
+    #[cfg(feature = "notifications")]
+    fn add_notification(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        attributes: &NotificationAttributes,
+        callback: NotificationCallback,
+    ) -> Result<u32> {
+        let request = add_notification_request(index_group, index_offset, attributes);
+
+        // Hold the lock until the handle is recorded, so that the first sample is not dropped
+        let mut notification_callbacks = match self.notification_callbacks.lock() {
+            Ok(nc) => nc,
+            Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+        };
+        let response = self.request(Command::AddDeviceNotification, &request, self.timeout)?;
+        let notification_handle = u32_at(&response, 0)?;
+        notification_callbacks.insert(notification_handle, callback);
+
+        Ok(notification_handle)
+    }
+
+    #[cfg(feature = "notifications")]
+    fn delete_notification(&self, notification_handle: u32) -> Result<()> {
+        self.request(
+            Command::DeleteDeviceNotification,
+            &notification_handle.to_le_bytes(),
+            self.timeout,
+        )?;
+
+        if let Ok(mut notification_callbacks) = self.notification_callbacks.lock() {
+            notification_callbacks.remove(&notification_handle);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Tcp {
+    fn drop(&mut self) {
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+}
+
+fn open(socket_address: SocketAddr, timeout: Duration) -> Result<TcpStream> {
+    let socket = TcpStream::connect_timeout(&socket_address, timeout)?;
+    socket.set_nodelay(true)?;
+    Ok(socket)
+}
+
+fn receive(
+    mut reader: Box<dyn Read + Send>,
+    pending: Pending,
+    #[cfg(feature = "notifications")] notifications: Sender<Vec<u8>>,
+) {
+    while let Ok((header, data)) = read_frame(&mut reader) {
+        if header.command_id == Command::DeviceNotification as u16 {
+            #[cfg(feature = "notifications")]
+            let _ = notifications.send(data);
+            continue;
+        }
+
+        let sender = match pending.lock() {
+            Ok(mut p) => p.as_mut().and_then(|p| p.remove(&header.invoke_id)),
+            Err(_) => break,
+        };
+        if let Some(sender) = sender {
+            let _ = sender.send((header.error_code, data));
+        }
+    }
+
+    if let Ok(mut p) = pending.lock() {
+        *p = None;
+    }
+}
+
+/// Callbacks are run on their own thread so that a slow callback cannot hold up responses
+#[cfg(feature = "notifications")]
+fn spawn_notification_dispatcher(notification_callbacks: NotificationCallbacks) -> Sender<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        for data in receiver {
+            let samples = match notification_samples(&data) {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            for (notification_handle, sample) in samples {
+                let callback = match notification_callbacks.lock() {
+                    Ok(nc) => nc.get(&notification_handle).cloned(),
+                    Err(_) => return,
+                };
+                if let Some(callback) = callback {
+                    callback(sample);
+                }
+            }
+        }
+    });
+    sender
+}
+
+/// Returns each (notification handle, sample) in a device notification
+#[cfg(feature = "notifications")]
+pub(crate) fn notification_samples(data: &[u8]) -> Result<Vec<(u32, &[u8])>> {
+    let mut output = Vec::new();
+
+    let n_stamps = u32_at(data, 4)?;
+    let mut index = 8;
+    for _ in 0..n_stamps {
+        // Skip the timestamp
+        index += 8;
+        let n_samples = u32_at(data, index)?;
+        index += 4;
+        for _ in 0..n_samples {
+            let notification_handle = u32_at(data, index)?;
+            let size = u32_at(data, index + 4)? as usize;
+            index += 8;
+            match data.get(index..index + size) {
+                Some(sample) => output.push((notification_handle, sample)),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Notification sample of {size} bytes overruns {data:?}"),
+                    ))
+                }
+            }
+            index += size;
+        }
+    }
+
+    Ok(output)
+}
+
+/// If `local_net_id` is not given, it is derived from the local IP address (`a.b.c.d.1.1`)
+pub(crate) fn local_address(
+    local: SocketAddr,
+    local_net_id: Option<AmsNetId>,
+) -> Result<beckhoff::AmsAddr> {
+    let local_net_id = match (local_net_id, local) {
+        (Some(net_id), _) => net_id.bytes(),
+        (None, SocketAddr::V4(local)) => {
+            let [a, b, c, d] = local.ip().octets();
+            [a, b, c, d, 1, 1]
+        }
+        (None, SocketAddr::V6(local)) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Cannot derive an AMS Net ID from {local}; please provide the local AMS Net ID"
+                ),
+            ))
+        }
+    };
+    Ok(beckhoff::AmsAddr {
+        netId: beckhoff::AmsNetId_ { b: local_net_id },
+        port: LOCAL_AMS_PORT,
+    })
+}
+
+pub(crate) fn read_request(index_group: u32, index_offset: u32, length: usize) -> Vec<u8> {
+    let mut request = Vec::with_capacity(12);
+    request.extend(index_group.to_le_bytes());
+    request.extend(index_offset.to_le_bytes());
+    request.extend((length as u32).to_le_bytes());
+    request
+}
+
+pub(crate) fn write_request(index_group: u32, index_offset: u32, data: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(12 + data.len());
+    request.extend(index_group.to_le_bytes());
+    request.extend(index_offset.to_le_bytes());
+    request.extend((data.len() as u32).to_le_bytes());
+    request.extend(data);
+    request
+}
+
+pub(crate) fn read_write_request(
+    index_group: u32,
+    index_offset: u32,
+    read_length: usize,
+    write_data: &[u8],
+) -> Vec<u8> {
+    let mut request = Vec::with_capacity(16 + write_data.len());
+    request.extend(index_group.to_le_bytes());
+    request.extend(index_offset.to_le_bytes());
+    request.extend((read_length as u32).to_le_bytes());
+    request.extend((write_data.len() as u32).to_le_bytes());
+    request.extend(write_data);
+    request
+}
+
+pub(crate) fn write_control_request(ads_state: u16, device_state: u16, data: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(8 + data.len());
+    request.extend(ads_state.to_le_bytes());
+    request.extend(device_state.to_le_bytes());
+    request.extend((data.len() as u32).to_le_bytes());
+    request.extend(data);
+    request
+}
+
+#[cfg(feature = "notifications")]
+pub(crate) fn add_notification_request(
+    index_group: u32,
+    index_offset: u32,
+    attributes: &NotificationAttributes,
+) -> Vec<u8> {
+    let mut request = Vec::with_capacity(40);
+    request.extend(index_group.to_le_bytes());
+    request.extend(index_offset.to_le_bytes());
+    request.extend(attributes.length.to_le_bytes());
+    request.extend((attributes.transmission_mode as u32).to_le_bytes());
+    request.extend(attributes.max_delay.to_le_bytes());
+    request.extend(attributes.cycle_time.to_le_bytes());
+    request.extend([0; 16]);
+    request
+}
+
+/// Copies the data of a read response (length, data) into `buffer`, returning the length
+pub(crate) fn copy_read_data(response: &[u8], buffer: &mut [u8]) -> Result<usize> {
+    let length = u32_at(response, 0)? as usize;
+    let data = match response.get(4..4 + length) {
+        Some(d) => d,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Response claims {length} bytes but holds {}",
+                    response.len() - 4
+                ),
+            ))
+        }
+    };
+    if length > buffer.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Received {length} bytes, expected at most {}", buffer.len()),
+        ));
+    }
+    buffer[..length].copy_from_slice(data);
+    Ok(length)
+}
+
+pub(crate) fn write_frame(stream: &mut impl Write, header: &AmsHeader, data: &[u8]) -> Result<()> {
+    let mut frame = Vec::with_capacity(AMS_TCP_HEADER_LENGTH + AMS_HEADER_LENGTH + data.len());
+    frame.extend([0, 0]);
+    frame.extend(((AMS_HEADER_LENGTH + data.len()) as u32).to_le_bytes());
+    frame.extend(header.target.netId.b);
+    frame.extend(header.target.port.to_le_bytes());
+    frame.extend(header.source.netId.b);
+    frame.extend(header.source.port.to_le_bytes());
+    frame.extend(header.command_id.to_le_bytes());
+    frame.extend(header.state_flags.to_le_bytes());
+    frame.extend((data.len() as u32).to_le_bytes());
+    frame.extend(header.error_code.to_le_bytes());
+    frame.extend(header.invoke_id.to_le_bytes());
+    frame.extend(data);
+
+    stream.write_all(&frame)
+}
+
+/// The length of the AMS packet which follows `tcp_header`, checked before it is read
+pub(crate) fn packet_length(tcp_header: &[u8]) -> Result<usize> {
+    let length = u32_at(tcp_header, 2)? as usize;
+    if length < AMS_HEADER_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("AMS/TCP packet of length {length} cannot hold an AMS header"),
+        ));
+    }
+    if length > MAX_AMS_PACKET_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("AMS/TCP packet of length {length} is longer than the {MAX_AMS_PACKET_LENGTH} bytes allowed"),
+        ));
+    }
+    Ok(length)
+}
+
+pub(crate) fn read_frame(stream: &mut impl Read) -> Result<(AmsHeader, Vec<u8>)> {
+    let mut tcp_header = [0; AMS_TCP_HEADER_LENGTH];
+    stream.read_exact(&mut tcp_header)?;
+    let length = packet_length(&tcp_header)?;
+
+    let mut packet = vec![0; length];
+    stream.read_exact(&mut packet)?;
+
+    let header = AmsHeader {
+        target: ams_address_at(&packet, 0)?,
+        source: ams_address_at(&packet, 8)?,
+        command_id: u16_at(&packet, 16)?,
+        state_flags: u16_at(&packet, 18)?,
+        error_code: u32_at(&packet, 24)?,
+        invoke_id: u32_at(&packet, 28)?,
+    };
+
+    let data_length = u32_at(&packet, 20)? as usize;
+    if AMS_HEADER_LENGTH + data_length != length {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "AMS header claims {data_length} bytes of data but packet holds {}",
+                length - AMS_HEADER_LENGTH
+            ),
+        ));
+    }
+    packet.drain(..AMS_HEADER_LENGTH);
+
+    Ok((header, packet))
+}
+
+fn ams_address_at(bytes: &[u8], index: usize) -> Result<beckhoff::AmsAddr> {
+    let mut net_id = [0; 6];
+    match bytes.get(index..index + 6) {
+        Some(b) => net_id.copy_from_slice(b),
+        None => return Err(too_short(bytes, index + 6)),
+    }
+    Ok(beckhoff::AmsAddr {
+        netId: beckhoff::AmsNetId_ { b: net_id },
+        port: u16_at(bytes, index + 6)?,
+    })
+}
+
+pub(crate) fn u16_at(bytes: &[u8], index: usize) -> Result<u16> {
+    match bytes.get(index..index + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(too_short(bytes, index + 2)),
+    }
+}
+
+pub(crate) fn u32_at(bytes: &[u8], index: usize) -> Result<u32> {
+    match bytes.get(index..index + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(too_short(bytes, index + 4)),
+    }
+}
+
+fn too_short(bytes: &[u8], length: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Expected at least {length} bytes, got {}", bytes.len()),
+    )
+}
+
+pub(crate) fn connection_closed() -> Error {
+    Error::new(ErrorKind::NotConnected, "The AMS/TCP connection is closed")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_to_frame() {
+        let header = AmsHeader {
+            target: beckhoff::AmsAddr {
+                netId: beckhoff::AmsNetId_ {
+                    b: [5, 21, 69, 109, 1, 1],
+                },
+                port: 851,
+            },
+            source: beckhoff::AmsAddr {
+                netId: beckhoff::AmsNetId_ {
+                    b: [192, 168, 0, 4, 1, 1],
+                },
+                port: LOCAL_AMS_PORT,
+            },
+            command_id: Command::ReadWrite as u16,
+            state_flags: STATE_FLAG_ADS_COMMAND,
+            error_code: 0,
+            invoke_id: 87,
+        };
+
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &header, &[1, 2, 3]).unwrap();
+        assert_eq!(frame.len(), AMS_TCP_HEADER_LENGTH + AMS_HEADER_LENGTH + 3);
+        assert_eq!(&frame[..6], &[0, 0, 35, 0, 0, 0]);
+
+        let (header_out, data) = read_frame(&mut frame.as_slice()).unwrap();
+        assert_eq!(header_out.target.netId.b, [5, 21, 69, 109, 1, 1]);
+        assert_eq!({ header_out.target.port }, 851);
+        assert_eq!(header_out.source.netId.b, [192, 168, 0, 4, 1, 1]);
+        assert_eq!(header_out.command_id, Command::ReadWrite as u16);
+        assert_eq!(header_out.invoke_id, 87);
+        assert_eq!(data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_frame() {
+        assert!(read_frame(&mut [0, 0, 32, 0, 0, 0, 1, 2].as_slice()).is_err());
+        assert!(read_frame(&mut [0, 0, 4, 0, 0, 0, 1, 2, 3, 4].as_slice()).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_frame() {
+        assert_eq!(
+            read_frame(&mut [0, 0, 0xff, 0xff, 0xff, 0xff].as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "notifications")]
+    fn split_notification_samples() {
+        let mut data = Vec::new();
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u64.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend(7u32.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend([3, 0]);
+        data.extend(8u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend([1]);
+
+        assert_eq!(
+            notification_samples(&data).unwrap(),
+            vec![(7, [3, 0].as_slice()), (8, [1].as_slice())]
+        );
+
+        assert!(notification_samples(&data[..data.len() - 1]).is_err());
+    }
+}
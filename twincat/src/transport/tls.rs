@@ -0,0 +1,562 @@
+//! Secure ADS: AMS/TCP inside TLS, usually on port 8016.
+//!
+//! Once the handshake is done, each end sends a connect info block, after which
+//! AMS/TCP frames flow as over a plain connection.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use openssl::error::ErrorStack;
+use openssl::pkey::PKey;
+#[cfg(feature = "mock")]
+use openssl::ssl::SslContext;
+use openssl::ssl::{
+    ErrorCode, Ssl, SslContextBuilder, SslMethod, SslStream, SslVerifyMode, SslVersion,
+};
+use openssl::x509::X509;
+
+use crate::{result, AmsNetId};
+
+/// The port on which TwinCAT accepts Secure ADS connections
+pub const SECURE_ADS_PORT: u16 = 8016;
+
+/// The connect info block: its length, flags, version, an error code (in the response),
+/// the AMS Net ID of the sender, then reserved bytes
+const CONNECT_INFO_LENGTH: usize = 64;
+const CONNECT_INFO_VERSION: u8 = 1;
+const CONNECT_INFO_FLAG_RESPONSE: u16 = 0x0001;
+
+/// How to secure a connection to `ClientBuilder::with_tcp_target`: which certificate authorities
+/// to trust, and how to identify this machine (by a client certificate or a pre-shared key).
+///
+/// Without a CA certificate or a pre-shared key the target cannot be verified, so the handshake fails.
+/// The target's certificate must carry the address connected to, unless told otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    ca_certificates: Vec<Vec<u8>>,
+    client_certificate: Option<(Vec<u8>, Vec<u8>)>,
+    psk: Option<(String, Vec<u8>)>,
+    server_name: Option<String>,
+    any_server_name: bool,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// PEM, which may hold several certificates.
+    /// The target's certificate must be signed by one of them.
+    pub fn with_ca_certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.ca_certificates.push(pem.into());
+        self
+    }
+
+    /// PEM, for targets which only accept clients whose certificate they trust
+    pub fn with_client_certificate(
+        mut self,
+        certificate_pem: impl Into<Vec<u8>>,
+        private_key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.client_certificate = Some((certificate_pem.into(), private_key_pem.into()));
+        self
+    }
+
+    /// Identifies this machine by a key shared with the target, instead of by certificates.
+    /// Limits the connection to TLS 1.2, whose PSK cipher suites TwinCAT uses.
+    pub fn with_psk(mut self, identity: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        self.psk = Some((identity.into(), key.into()));
+        self
+    }
+
+    /// The name the target's certificate must carry, instead of the IP address connected to
+    pub fn with_server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Accepts the target's certificate whatever name it carries, as long as it is signed by a trusted CA.
+    /// Then any target holding such a certificate can pose as this one.
+    pub fn with_any_server_name(mut self) -> Self {
+        self.any_server_name = true;
+        self
+    }
+
+    /// `host` is the address connected to
+    fn ssl(&self, host: IpAddr) -> Result<Ssl> {
+        let mut builder = SslContextBuilder::new(SslMethod::tls_client()).map_err(invalid)?;
+        builder
+            .set_min_proto_version(Some(SslVersion::TLS1_2))
+            .map_err(invalid)?;
+
+        for pem in &self.ca_certificates {
+            let certificates = X509::stack_from_pem(pem).map_err(invalid)?;
+            if certificates.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "CA certificate PEM holds no certificate",
+                ));
+            }
+            for certificate in certificates {
+                builder
+                    .cert_store_mut()
+                    .add_cert(certificate)
+                    .map_err(invalid)?;
+            }
+        }
+        // The PSK cipher suites send no certificate, in which case the key verifies the target
+        builder.set_verify(SslVerifyMode::PEER);
+
+        if let Some((certificate, private_key)) = &self.client_certificate {
+            let certificate = X509::from_pem(certificate).map_err(invalid)?;
+            let private_key = PKey::private_key_from_pem(private_key).map_err(invalid)?;
+            builder.set_certificate(&certificate).map_err(invalid)?;
+            builder.set_private_key(&private_key).map_err(invalid)?;
+            builder.check_private_key().map_err(invalid)?;
+        }
+
+        if let Some((identity, key)) = self.psk.clone() {
+            limit_to_psk(&mut builder)?;
+            builder.set_psk_client_callback(move |_, _, identity_buffer, key_buffer| {
+                // The identity is NUL-terminated
+                if identity.len() >= identity_buffer.len() || key.len() > key_buffer.len() {
+                    return Err(ErrorStack::get());
+                }
+                identity_buffer[..identity.len()].copy_from_slice(identity.as_bytes());
+                identity_buffer[identity.len()] = 0;
+                key_buffer[..key.len()].copy_from_slice(&key);
+                Ok(key.len())
+            });
+        }
+
+        let mut ssl = Ssl::new(&builder.build()).map_err(invalid)?;
+        if let Some(name) = &self.server_name {
+            ssl.set_hostname(name).map_err(invalid)?;
+        }
+        match (&self.server_name, self.any_server_name) {
+            (_, true) => (),
+            (Some(name), false) => ssl.param_mut().set_host(name).map_err(invalid)?,
+            (None, false) => ssl.param_mut().set_ip(host).map_err(invalid)?,
+        }
+        Ok(ssl)
+    }
+}
+
+pub(crate) fn limit_to_psk(builder: &mut SslContextBuilder) -> Result<()> {
+    builder
+        .set_max_proto_version(Some(SslVersion::TLS1_2))
+        .map_err(invalid)?;
+    builder.set_cipher_list("PSK").map_err(invalid)
+}
+
+/// The plaintext halves of a secured connection, for the receiving thread and for senders
+pub(crate) type Halves = (TlsReader, TlsWriter);
+
+/// Secures `socket`, which should have a read timeout to bound the handshake
+pub(crate) fn connect(
+    config: &TlsConfig,
+    socket: TcpStream,
+    local_net_id: AmsNetId,
+) -> Result<Halves> {
+    let host = socket.peer_addr()?.ip();
+    let wire = Wire::new(socket.try_clone()?);
+    let mut stream = match config.ssl(host)?.connect(wire) {
+        Ok(s) => s,
+        Err(e) => return Err(Error::other(format!("Secure ADS handshake failed!\n{e}"))),
+    };
+
+    stream.write_all(&connect_info(0, local_net_id, 0))?;
+    let mut response = [0; CONNECT_INFO_LENGTH];
+    stream.read_exact(&mut response)?;
+    if u16::from_le_bytes([response[2], response[3]]) & CONNECT_INFO_FLAG_RESPONSE == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Secure ADS target did not answer the connect info",
+        ));
+    }
+    result::process(response[5] as i32)?;
+
+    Ok(split(stream, socket))
+}
+
+/// The target's side of `connect`, answering the connect info with `ams_net_id`
+#[cfg(feature = "mock")]
+pub(crate) fn accept(
+    context: &SslContext,
+    socket: TcpStream,
+    ams_net_id: AmsNetId,
+) -> Result<Halves> {
+    let wire = Wire::new(socket.try_clone()?);
+    let mut stream = match Ssl::new(context).map_err(invalid)?.accept(wire) {
+        Ok(s) => s,
+        Err(e) => return Err(Error::other(format!("Secure ADS handshake failed!\n{e}"))),
+    };
+
+    let mut request = [0; CONNECT_INFO_LENGTH];
+    stream.read_exact(&mut request)?;
+    stream.write_all(&connect_info(CONNECT_INFO_FLAG_RESPONSE, ams_net_id, 0))?;
+
+    Ok(split(stream, socket))
+}
+
+fn connect_info(flags: u16, ams_net_id: AmsNetId, error: u8) -> Vec<u8> {
+    let mut info = Vec::with_capacity(CONNECT_INFO_LENGTH);
+    info.extend((CONNECT_INFO_LENGTH as u16).to_le_bytes());
+    info.extend(flags.to_le_bytes());
+    info.push(CONNECT_INFO_VERSION);
+    info.push(error);
+    info.extend(ams_net_id.bytes());
+    info.resize(CONNECT_INFO_LENGTH, 0);
+    info
+}
+
+fn split(mut stream: SslStream<Wire>, socket: TcpStream) -> Halves {
+    stream.get_mut().handshaking = false;
+    let stream = Arc::new(Mutex::new(stream));
+    (
+        TlsReader {
+            socket,
+            stream: stream.clone(),
+            plaintext: Vec::new(),
+        },
+        TlsWriter(stream),
+    )
+}
+
+/// What OpenSSL reads and writes.
+/// After the handshake it only reads what `TlsReader` has received, so that the lock
+/// on the stream is not held while waiting for data, and senders are not held up.
+#[derive(Debug)]
+struct Wire {
+    socket: TcpStream,
+    received: Vec<u8>,
+    handshaking: bool,
+}
+
+impl Wire {
+    fn new(socket: TcpStream) -> Self {
+        Self {
+            socket,
+            received: Vec::new(),
+            handshaking: true,
+        }
+    }
+}
+
+impl Read for Wire {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if self.received.is_empty() {
+            if self.handshaking {
+                return self.socket.read(buffer);
+            }
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = buffer.len().min(self.received.len());
+        buffer[..n].copy_from_slice(&self.received[..n]);
+        self.received.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for Wire {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        self.socket.write(data)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.socket.flush()
+    }
+}
+
+pub(crate) struct TlsReader {
+    socket: TcpStream,
+    stream: Arc<Mutex<SslStream<Wire>>>,
+    plaintext: Vec<u8>,
+}
+
+impl TlsReader {
+    /// Decrypts whatever has been received. Returns `false` once the target has closed the connection.
+    fn decrypt(&mut self) -> Result<bool> {
+        let mut stream = match self.stream.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+        };
+        let mut chunk = [0; 4096];
+        loop {
+            match stream.ssl_read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.plaintext.extend(&chunk[..n]),
+                Err(e) if e.code() == ErrorCode::WANT_READ => return Ok(true),
+                Err(e) if e.code() == ErrorCode::ZERO_RETURN => return Ok(false),
+                Err(e) => return Err(Error::other(e)),
+            }
+        }
+    }
+}
+
+impl Read for TlsReader {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        loop {
+            let open = self.decrypt()?;
+            if !self.plaintext.is_empty() {
+                let n = buffer.len().min(self.plaintext.len());
+                buffer[..n].copy_from_slice(&self.plaintext[..n]);
+                self.plaintext.drain(..n);
+                return Ok(n);
+            }
+            if !open {
+                return Ok(0);
+            }
+
+            let mut encrypted = [0; 4096];
+            let n = self.socket.read(&mut encrypted)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            match self.stream.lock() {
+                Ok(mut s) => s.get_mut().received.extend(&encrypted[..n]),
+                Err(e) => return Err(Error::other(format!("Lock failure!\n{e}"))),
+            }
+        }
+    }
+}
+
+pub(crate) struct TlsWriter(Arc<Mutex<SslStream<Wire>>>);
+
+impl Write for TlsWriter {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        match self.0.lock() {
+            Ok(mut s) => s.write(data),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.0.lock() {
+            Ok(mut s) => s.flush(),
+            Err(e) => Err(Error::other(format!("Lock failure!\n{e}"))),
+        }
+    }
+}
+
+fn invalid(e: ErrorStack) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Invalid TLS configuration!\n{e}"),
+    )
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use super::*;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::X509NameBuilder;
+
+    use std::net::Ipv4Addr;
+
+    use crate::mock::{fixture, Server};
+    use crate::{Client, Variable};
+
+    struct Certificate {
+        certificate: X509,
+        private_key: PKey<Private>,
+    }
+
+    impl Certificate {
+        /// Self-signed, and able to sign others, if there is no `issuer`
+        fn new(name: &str, issuer: Option<&Certificate>) -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+            let mut subject = X509NameBuilder::new().unwrap();
+            subject.append_entry_by_text("CN", name).unwrap();
+            let subject = subject.build();
+
+            let mut builder = X509::builder().unwrap();
+            builder.set_version(2).unwrap();
+            let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+            builder.set_serial_number(&serial).unwrap();
+            builder.set_subject_name(&subject).unwrap();
+            builder.set_pubkey(&private_key).unwrap();
+            builder
+                .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+                .unwrap();
+            builder
+                .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+                .unwrap();
+            match issuer {
+                Some(issuer) => {
+                    builder
+                        .set_issuer_name(issuer.certificate.subject_name())
+                        .unwrap();
+                    let mut names = SubjectAlternativeName::new();
+                    match name.parse::<IpAddr>() {
+                        Ok(_) => names.ip(name),
+                        Err(_) => names.dns(name),
+                    };
+                    let names = names
+                        .build(&builder.x509v3_context(Some(&issuer.certificate), None))
+                        .unwrap();
+                    builder.append_extension(names).unwrap();
+                    builder
+                        .sign(&issuer.private_key, MessageDigest::sha256())
+                        .unwrap();
+                }
+                None => {
+                    builder.set_issuer_name(&subject).unwrap();
+                    let ca = BasicConstraints::new().critical().ca().build().unwrap();
+                    builder.append_extension(ca).unwrap();
+                    builder.sign(&private_key, MessageDigest::sha256()).unwrap();
+                }
+            }
+
+            Self {
+                certificate: builder.build(),
+                private_key,
+            }
+        }
+
+        fn pem(&self) -> Vec<u8> {
+            self.certificate.to_pem().unwrap()
+        }
+
+        fn private_key_pem(&self) -> Vec<u8> {
+            self.private_key.private_key_to_pem_pkcs8().unwrap()
+        }
+    }
+
+    fn server(tls: impl Fn(crate::mock::ServerBuilder) -> crate::mock::ServerBuilder) -> Server {
+        tls(fixture(
+            &[("main.speed", "INT")],
+            &[("main.speed", Variable::I16(42))],
+        ))
+        .start()
+        .unwrap()
+    }
+
+    fn connect(server: &Server, tls_config: TlsConfig) -> Result<Client> {
+        Client::builder()
+            .with_tcp_target(server.address())
+            .with_tls(tls_config)
+            .connect()
+    }
+
+    #[test]
+    fn verify_target_certificate() {
+        let ca = Certificate::new("Plant CA", None);
+        let plc = Certificate::new("plc-1", Some(&ca));
+        let server = server(|s| s.with_tls_certificate(plc.pem(), plc.private_key_pem()));
+
+        let client = connect(
+            &server,
+            TlsConfig::new()
+                .with_ca_certificate(ca.pem())
+                .with_server_name("plc-1"),
+        )
+        .unwrap();
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::I16(42));
+
+        // Signed by another CA, or under another name
+        let other_ca = Certificate::new("Other CA", None);
+        assert!(connect(
+            &server,
+            TlsConfig::new().with_ca_certificate(other_ca.pem())
+        )
+        .is_err());
+        assert!(connect(
+            &server,
+            TlsConfig::new()
+                .with_ca_certificate(ca.pem())
+                .with_server_name("plc-2")
+        )
+        .is_err());
+        assert!(connect(&server, TlsConfig::new()).is_err());
+    }
+
+    #[test]
+    fn verify_target_address() {
+        let ca = Certificate::new("Plant CA", None);
+        let plc = Certificate::new("127.0.0.1", Some(&ca));
+        let by_address = server(|s| s.with_tls_certificate(plc.pem(), plc.private_key_pem()));
+        let client = connect(&by_address, TlsConfig::new().with_ca_certificate(ca.pem())).unwrap();
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::I16(42));
+
+        // The certificate does not carry the address
+        let plc = Certificate::new("plc-1", Some(&ca));
+        let by_name = server(|s| s.with_tls_certificate(plc.pem(), plc.private_key_pem()));
+        assert!(connect(&by_name, TlsConfig::new().with_ca_certificate(ca.pem())).is_err());
+        let client = connect(
+            &by_name,
+            TlsConfig::new()
+                .with_ca_certificate(ca.pem())
+                .with_any_server_name(),
+        )
+        .unwrap();
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::I16(42));
+    }
+
+    #[test]
+    fn present_client_certificate() {
+        let ca = Certificate::new("Plant CA", None);
+        let plc = Certificate::new("plc-1", Some(&ca));
+        let hmi = Certificate::new("hmi", Some(&ca));
+        let server = server(|s| {
+            s.with_tls_certificate(plc.pem(), plc.private_key_pem())
+                .with_tls_client_ca(ca.pem())
+        });
+
+        let tls_config = TlsConfig::new()
+            .with_ca_certificate(ca.pem())
+            .with_server_name("plc-1");
+        let client = connect(
+            &server,
+            tls_config
+                .clone()
+                .with_client_certificate(hmi.pem(), hmi.private_key_pem()),
+        )
+        .unwrap();
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::I16(42));
+
+        assert!(connect(&server, tls_config).is_err());
+    }
+
+    #[test]
+    fn share_key() {
+        let server = server(|s| s.with_tls_psk("hmi", *b"0123456789abcdef"));
+
+        let client = connect(
+            &server,
+            TlsConfig::new().with_psk("hmi", *b"0123456789abcdef"),
+        )
+        .unwrap();
+        assert_eq!(client.get_value("main.speed").unwrap(), Variable::I16(42));
+
+        assert!(connect(
+            &server,
+            TlsConfig::new().with_psk("hmi", *b"fedcba9876543210")
+        )
+        .is_err());
+        assert!(connect(
+            &server,
+            TlsConfig::new().with_psk("scada", *b"0123456789abcdef")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_invalid_certificate() {
+        let error = TlsConfig::new()
+            .with_ca_certificate("not a certificate")
+            .ssl(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
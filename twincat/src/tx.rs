@@ -1,80 +1,99 @@
 use std::io::{Error, ErrorKind, Result};
 
+use super::access::{self, Write};
+use super::beckhoff;
 use super::client::Client;
-use super::variables::{self, Variable};
-use super::{beckhoff, result};
+use super::symbols_and_data_types::Bits;
+use super::variables::Variable;
 
 impl Client {
+    /// A `Variable::Struct` which leaves out some fields is written as `ClientBuilder::with_partial_writes` says
     pub fn set_value(&self, value_name: impl AsRef<str>, value: Variable) -> Result<()> {
-        let data_types = self.symbols_and_data_types().data_types();
-        let (symbol_info, data_type_info) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-        let bytes = value.to_bytes(data_types, symbol_info, data_type_info)?;
-        self.set_raw_bytes(value_name.as_ref(), bytes)?;
-        Ok(())
+        self.with_current_symbols(|symbols_and_data_types| {
+            self.check_dereferences(symbols_and_data_types, value_name.as_ref())?;
+            let write = Write::of_value(
+                symbols_and_data_types,
+                value_name.as_ref(),
+                &value,
+                self.partial_writes(),
+            )?;
+            self.write_value(value_name.as_ref(), write)
+        })
     }
 
+    /// `value` is a Structured Text literal, such as `16#FF`, `'it$'s'`, `[1, 2, 8(0)]`
+    /// or `(top_shelf := [8(Food.Nothing)], light := TRUE)`; an unquoted `STRING` is taken as it is
     pub fn set_value_from_str(&self, value_name: impl AsRef<str>, value: &str) -> Result<()> {
-        let (symbol_info, data_type_info) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-        let bytes = variables::str_and_symbol_to_bytes(value, symbol_info, data_type_info)?;
-        self.set_raw_bytes(value_name.as_ref(), bytes)?;
-        Ok(())
+        self.with_current_symbols(|symbols_and_data_types| {
+            self.check_dereferences(symbols_and_data_types, value_name.as_ref())?;
+            let write = Write::of_str(symbols_and_data_types, value_name.as_ref(), value)?;
+            self.write_value(value_name.as_ref(), write)
+        })
     }
 
-    fn set_raw_bytes(&self, value_name: &str, bytes: Vec<u8>) -> Result<()> {
-        const SIZE_SYMBOL_ENTRY: u32 = std::mem::size_of::<beckhoff::AdsSymbolEntry>() as u32;
-
-        let ptr_address = &mut self.ams_address().to_owned() as *mut beckhoff::AmsAddr;
-
-        let mut symbol_entry = beckhoff::AdsSymbolEntry::default();
-        let ptr_symbol_entry =
-            &mut symbol_entry as *mut beckhoff::AdsSymbolEntry as *mut std::os::raw::c_void;
-
-        let mut bytes = bytes;
-        let ptr_bytes = bytes.as_mut_ptr() as *mut std::os::raw::c_void;
-
-        let ptr_name = value_name as *const str as *mut std::os::raw::c_void;
-
-        result::process(unsafe {
-            beckhoff::AdsSyncReadWriteReqEx2(
-                self.port(),
-                ptr_address,
-                beckhoff::ADSIGRP_SYM_INFOBYNAMEEX,
-                0,
-                SIZE_SYMBOL_ENTRY,
-                ptr_symbol_entry,
-                value_name.len() as u32,
-                ptr_name,
-                std::ptr::null_mut(),
-            )
-        })?;
+    fn write_value(&self, value_name: &str, write: Write) -> Result<()> {
+        match write {
+            Write::Bytes(bytes) => self.set_raw_bytes(value_name, bytes),
+            Write::Bits {
+                bits,
+                byte_offset,
+                value,
+            } => self.set_bits(value_name, bits, byte_offset, value),
+            Write::Partial { size_bytes, masked } => {
+                self.set_partial_struct(value_name, size_bytes, &masked)
+            }
+        }
+    }
 
-        let symbol_entry_size = symbol_entry.size;
-        if bytes.len() > symbol_entry_size as usize {
+    pub(super) fn set_raw_bytes(&self, value_name: &str, bytes: Vec<u8>) -> Result<()> {
+        let (index_group, index_offset, size) = self.symbol_address(value_name)?;
+        if bytes.len() > size as usize {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
                 format!(
-                    "{value_name} has size {}, cannot write {} bytes",
-                    symbol_entry_size,
+                    "{value_name} has size {size}, cannot write {} bytes",
                     bytes.len()
                 ),
             ));
         }
 
-        result::process(unsafe {
-            beckhoff::AdsSyncWriteReqEx(
-                self.port(),
-                ptr_address,
-                symbol_entry.iGroup,
-                symbol_entry.iOffs,
-                bytes.len() as u32,
-                ptr_bytes,
-            )
-        })?;
+        self.transport().write(index_group, index_offset, &bytes)?;
 
         Ok(())
     }
+
+    /// Of a `BIT` struct member, into the byte it shares with others.
+    /// The byte is read and written back, which is not atomic:
+    /// a change the target makes to the other bits in between is lost.
+    fn set_bits(&self, value_name: &str, bits: Bits, byte_offset: usize, value: u8) -> Result<()> {
+        let (index_group, index_offset) = self.bits_address(value_name, byte_offset)?;
+        let mut byte = [0];
+        self.transport()
+            .read(index_group, index_offset, &mut byte)?;
+        byte[0] = bits.set(byte[0], value);
+        self.transport().write(index_group, index_offset, &byte)
+    }
+
+    /// The index group, index offset and size of `value_name`, as the target reports them
+    pub(super) fn symbol_address(&self, value_name: &str) -> Result<(u32, u32, u32)> {
+        const SIZE_SYMBOL_ENTRY: usize = std::mem::size_of::<beckhoff::AdsSymbolEntry>();
+
+        let mut symbol_entry_bytes = [0; SIZE_SYMBOL_ENTRY];
+
+        self.transport().read_write(
+            beckhoff::ADSIGRP_SYM_INFOBYNAMEEX,
+            0,
+            &mut symbol_entry_bytes,
+            value_name.as_bytes(),
+        )?;
+
+        access::symbol_address(&symbol_entry_bytes)
+    }
+
+    /// Of the byte which holds a `BIT` struct member, `byte_offset` into the struct around it
+    pub(super) fn bits_address(&self, value_name: &str, byte_offset: usize) -> Result<(u32, u32)> {
+        let (index_group, index_offset, _) =
+            self.symbol_address(access::bits_parent(value_name)?)?;
+        Ok((index_group, index_offset + byte_offset as u32))
+    }
 }
@@ -0,0 +1,241 @@
+//! The messages which TwinCAT routers answer on UDP port 48899, outside of AMS
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::AmsNetId;
+
+pub(crate) const PORT: u16 = 48899;
+
+const MAGIC: u32 = 0x7114_6603;
+/// Set in the service ID of a response
+const RESPONSE: u32 = 0x8000_0000;
+const HEADER_LENGTH: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Service {
+    Identify = 1,
+    AddRoute = 6,
+}
+
+/// The ID of each item which may follow the header
+pub(crate) mod tag {
+    /// An ADS result code
+    pub(crate) const STATUS: u16 = 1;
+    pub(crate) const PASSWORD: u16 = 2;
+    pub(crate) const TWINCAT_VERSION: u16 = 3;
+    pub(crate) const OS_VERSION: u16 = 4;
+    pub(crate) const HOSTNAME: u16 = 5;
+    pub(crate) const AMS_NET_ID: u16 = 7;
+    pub(crate) const ROUTE_NAME: u16 = 12;
+    pub(crate) const USERNAME: u16 = 13;
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Message {
+    pub(crate) invoke_id: u32,
+    pub(crate) service: Service,
+    pub(crate) is_response: bool,
+    /// Of the sender
+    pub(crate) ams_net_id: AmsNetId,
+    pub(crate) ams_port: u16,
+    pub(crate) tags: Vec<(u16, Vec<u8>)>,
+}
+
+impl Message {
+    pub(crate) fn tag(&self, id: u16) -> Option<&[u8]> {
+        self.tags
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut service = self.service as u32;
+        if self.is_response {
+            service |= RESPONSE;
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+        bytes.extend(MAGIC.to_le_bytes());
+        bytes.extend(self.invoke_id.to_le_bytes());
+        bytes.extend(service.to_le_bytes());
+        bytes.extend(self.ams_net_id.bytes());
+        bytes.extend(self.ams_port.to_le_bytes());
+        bytes.extend((self.tags.len() as u32).to_le_bytes());
+        for (id, data) in &self.tags {
+            bytes.extend(id.to_le_bytes());
+            bytes.extend((data.len() as u16).to_le_bytes());
+            bytes.extend(data);
+        }
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if u32_at(bytes, 0)? != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Not a TwinCAT UDP message",
+            ));
+        }
+        let service = u32_at(bytes, 8)?;
+        let is_response = service & RESPONSE != 0;
+        let service = match service & !RESPONSE {
+            1 => Service::Identify,
+            6 => Service::AddRoute,
+            s => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown TwinCAT UDP service {s:#x}"),
+                ))
+            }
+        };
+        let mut ams_net_id = [0; 6];
+        ams_net_id.copy_from_slice(slice_at(bytes, 12, 6)?);
+
+        let n_tags = u32_at(bytes, 20)?;
+        let mut tags = Vec::new();
+        let mut index = HEADER_LENGTH;
+        for _ in 0..n_tags {
+            let id = u16_at(bytes, index)?;
+            let length = u16_at(bytes, index + 2)? as usize;
+            tags.push((id, slice_at(bytes, index + 4, length)?.to_vec()));
+            index += 4 + length;
+        }
+
+        Ok(Self {
+            invoke_id: u32_at(bytes, 4)?,
+            service,
+            is_response,
+            ams_net_id: AmsNetId::new(ams_net_id),
+            ams_port: u16_at(bytes, 18)?,
+            tags,
+        })
+    }
+}
+
+/// Sends `request` to `target`, and waits up to `timeout` for the response to it
+pub(crate) fn exchange(
+    target: SocketAddr,
+    request: &Message,
+    timeout: Duration,
+) -> Result<Message> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.send_to(&request.to_bytes(), target)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; 2048];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("No answer from {target} within {timeout:?}"),
+            ));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let length = match socket.recv_from(&mut buffer) {
+            Ok((length, _)) => length,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e),
+        };
+        match Message::from_bytes(&buffer[..length]) {
+            Ok(m)
+                if m.is_response
+                    && m.service == request.service
+                    && m.invoke_id == request.invoke_id =>
+            {
+                return Ok(m)
+            }
+            _ => continue,
+        }
+    }
+}
+
+/// Strings are sent NUL-terminated
+pub(crate) fn string_tag(s: &str) -> Vec<u8> {
+    let mut data = s.as_bytes().to_vec();
+    data.push(0);
+    data
+}
+
+pub(crate) fn tag_string(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn slice_at(bytes: &[u8], index: usize, length: usize) -> Result<&[u8]> {
+    match bytes.get(index..index + length) {
+        Some(b) => Ok(b),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "TwinCAT UDP message of {} bytes is too short for {} bytes at {index}",
+                bytes.len(),
+                length
+            ),
+        )),
+    }
+}
+
+pub(crate) fn u16_at(bytes: &[u8], index: usize) -> Result<u16> {
+    let mut b = [0; 2];
+    b.copy_from_slice(slice_at(bytes, index, 2)?);
+    Ok(u16::from_le_bytes(b))
+}
+
+pub(crate) fn u32_at(bytes: &[u8], index: usize) -> Result<u32> {
+    let mut b = [0; 4];
+    b.copy_from_slice(slice_at(bytes, index, 4)?);
+    Ok(u32::from_le_bytes(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn message_to_message() {
+        let message = Message {
+            invoke_id: 7,
+            service: Service::Identify,
+            is_response: true,
+            ams_net_id: AmsNetId::new([5, 21, 69, 109, 1, 1]),
+            ams_port: 10000,
+            tags: vec![
+                (tag::HOSTNAME, string_tag("CX-1234")),
+                (tag::TWINCAT_VERSION, vec![3, 1, 0xb8, 0x0f]),
+            ],
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(&bytes[..4], &[0x03, 0x66, 0x14, 0x71]);
+        assert_eq!(&bytes[8..12], &[1, 0, 0, 0x80]);
+
+        let parsed = Message::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, message);
+        assert_eq!(tag_string(parsed.tag(tag::HOSTNAME).unwrap()), "CX-1234");
+        assert_eq!(parsed.tag(tag::OS_VERSION), None);
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert!(Message::from_bytes(&[0; 24]).is_err());
+
+        let mut bytes = Message {
+            invoke_id: 0,
+            service: Service::Identify,
+            is_response: false,
+            ams_net_id: AmsNetId::default(),
+            ams_port: 10000,
+            tags: vec![(tag::HOSTNAME, string_tag("PLC"))],
+        }
+        .to_bytes();
+        bytes.pop();
+        assert_eq!(
+            Message::from_bytes(&bytes).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,586 @@
+//! Structured Text literals, as `Client::set_value_from_str` takes them: arrays such as
+//! `[1, 2, 8(0)]`, structure initialisers such as `(top_shelf := [1, 2], light := TRUE)`,
+//! and strings such as `'it$'s $R$N'`.
+//! Positions in errors count characters from the start of the input, from 0.
+//! Each value a literal gives takes at least a byte of its target, so a literal which would give
+//! more values than its target has bytes is rejected before repetitions are expanded.
+
+use std::io::{Error, ErrorKind, Result};
+use std::iter;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Literal {
+    /// A number, boolean, enum member or time, as written, and where it starts
+    Plain(String, usize),
+    /// `'...'` or `"..."`, with its `$` escapes undone
+    Quoted(String, usize),
+    /// With repetitions such as `8(0)` expanded
+    Array(Vec<Literal>, usize),
+    /// Fields by name, in the order given
+    Struct(Vec<(String, Literal)>, usize),
+}
+
+impl Literal {
+    pub(super) fn position(&self) -> usize {
+        match self {
+            Self::Plain(_, p) | Self::Quoted(_, p) | Self::Array(_, p) | Self::Struct(_, p) => *p,
+        }
+    }
+
+    /// The number of values it gives, where an empty array or struct counts as one
+    fn n_values(&self) -> usize {
+        let n_values = match self {
+            Self::Plain(..) | Self::Quoted(..) => return 1,
+            Self::Array(elements, _) => elements
+                .iter()
+                .fold(0usize, |n, e| n.saturating_add(e.n_values())),
+            Self::Struct(fields, _) => fields
+                .iter()
+                .fold(0usize, |n, (_, f)| n.saturating_add(f.n_values())),
+        };
+        n_values.max(1)
+    }
+}
+
+/// `size_bytes` is the size of the target, which bounds how many values the literal may give
+pub(super) fn parse(input: &str, size_bytes: usize) -> Result<Literal> {
+    let mut parser = Parser {
+        input,
+        index: 0,
+        size_bytes,
+    };
+    parser.skip_whitespace();
+    let literal = parser.literal()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.expected("the end"));
+    }
+    Ok(literal)
+}
+
+/// The text of `'...'` or `"..."`, or `None` if `value` is not quoted
+pub(super) fn unquote(value: &str) -> Result<Option<String>> {
+    if !value.starts_with(['\'', '"']) {
+        return Ok(None);
+    }
+    match parse(value, 1)? {
+        Literal::Quoted(text, _) => Ok(Some(text)),
+        _ => Ok(None),
+    }
+}
+
+/// Adds where in the input the literal at `position` is to `error`
+pub(super) fn at_position(error: Error, position: usize) -> Error {
+    Error::new(error.kind(), format!("{error} (at position {position})"))
+}
+
+/// `value` as a decimal integer, from `-5`, `1_000`, `16#FF`, `2#1010` or `8#17`
+pub(super) fn integer(value: &str) -> Option<i128> {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(u) => (true, u),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (radix, digits) = match unsigned.split_once('#') {
+        Some((radix, digits)) => (u32::from_str(radix).ok()?, digits),
+        None => (10, unsigned),
+    };
+    if !matches!(radix, 2 | 8 | 10 | 16) || digits.starts_with('_') {
+        return None;
+    }
+    let magnitude = i128::from_str_radix(&digits.replace('_', ""), radix).ok()?;
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    /// In bytes
+    index: usize,
+    size_bytes: usize,
+}
+
+impl Parser<'_> {
+    fn literal(&mut self) -> Result<Literal> {
+        match self.peek() {
+            Some('[') => self.array(),
+            Some('(') => self.structure(),
+            Some('\'' | '"') => self.quoted(),
+            _ => self.plain(),
+        }
+    }
+
+    fn array(&mut self) -> Result<Literal> {
+        let position = self.position();
+        self.index += 1;
+        let mut elements = Vec::new();
+        let mut n_values = 0usize;
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Literal::Array(elements, position));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let element = self.literal()?;
+            self.skip_whitespace();
+            // Such as `8(0)`: the element in brackets, 8 times
+            match (&element, self.peek()) {
+                (Literal::Plain(count, count_position), Some('(')) => {
+                    let count = match usize::from_str(count) {
+                        Ok(c) => c,
+                        Err(_) => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidInput,
+                                format!("Expected a number of repetitions at position {count_position}, got {count}"),
+                            ))
+                        }
+                    };
+                    self.index += 1;
+                    self.skip_whitespace();
+                    let repeated = self.literal()?;
+                    self.skip_whitespace();
+                    self.expect(')')?;
+                    self.skip_whitespace();
+                    let room = self.size_bytes.saturating_sub(n_values);
+                    match count.checked_mul(repeated.n_values()) {
+                        Some(n) if n <= room => n_values += n,
+                        _ => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidInput,
+                                format!("{count} repetitions at position {count_position} do not fit in the {} bytes of the value", self.size_bytes),
+                            ))
+                        }
+                    }
+                    elements.extend(iter::repeat_n(repeated, count));
+                }
+                _ => {
+                    n_values = n_values.saturating_add(element.n_values());
+                    elements.push(element);
+                }
+            }
+
+            if self.eat(']') {
+                return Ok(Literal::Array(elements, position));
+            }
+            if !self.eat(',') {
+                return Err(self.expected("',' or ']'"));
+            }
+        }
+    }
+
+    fn structure(&mut self) -> Result<Literal> {
+        let position = self.position();
+        self.index += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.eat(')') {
+            return Ok(Literal::Struct(fields, position));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name_start = self.index;
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                self.index += 1;
+            }
+            if name_start == self.index {
+                return Err(self.expected("a field name"));
+            }
+            let name = self.input[name_start..self.index].to_string();
+            self.skip_whitespace();
+            if !self.input[self.index..].starts_with(":=") {
+                return Err(self.expected("':='"));
+            }
+            self.index += 2;
+            self.skip_whitespace();
+            fields.push((name, self.literal()?));
+            self.skip_whitespace();
+
+            if self.eat(')') {
+                return Ok(Literal::Struct(fields, position));
+            }
+            if !self.eat(',') {
+                return Err(self.expected("',' or ')'"));
+            }
+        }
+    }
+
+    /// `$` escapes a quote, `$`, a control character (`$L`, `$N`, `$P`, `$R`, `$T`),
+    /// or gives a character by its code: two hex digits in `'...'`, and four in `"..."`
+    fn quoted(&mut self) -> Result<Literal> {
+        let position = self.position();
+        let quote = self.peek().unwrap_or('\'');
+        let code_length = if quote == '"' { 4 } else { 2 };
+        self.index += 1;
+
+        let mut text = String::new();
+        loop {
+            let c = match self.next() {
+                Some(c) => c,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Cannot find the end of the string at position {position}"),
+                    ))
+                }
+            };
+            if c == quote {
+                return Ok(Literal::Quoted(text, position));
+            }
+            if c != '$' {
+                text.push(c);
+                continue;
+            }
+
+            let escape_position = self.position();
+            let escaped = match self.next() {
+                Some('$') => '$',
+                Some('\'') => '\'',
+                Some('"') => '"',
+                Some('L' | 'l' | 'N' | 'n') => '\n',
+                Some('P' | 'p') => '\x0c',
+                Some('R' | 'r') => '\r',
+                Some('T' | 't') => '\t',
+                Some(c) if c.is_ascii_hexdigit() => {
+                    let start = self.index - 1;
+                    let code = self
+                        .input
+                        .get(start..start + code_length)
+                        .filter(|code| code.chars().all(|c| c.is_ascii_hexdigit()))
+                        .and_then(|code| u32::from_str_radix(code, 16).ok())
+                        .and_then(char::from_u32);
+                    match code {
+                        Some(code) => {
+                            self.index = start + code_length;
+                            code
+                        }
+                        None => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidInput,
+                                format!(
+                                "Expected {code_length} hex digits at position {escape_position}"
+                            ),
+                            ))
+                        }
+                    }
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Unknown escape at position {}", escape_position - 1),
+                    ))
+                }
+            };
+            text.push(escaped);
+        }
+    }
+
+    /// Up to the next delimiter or whitespace
+    fn plain(&mut self) -> Result<Literal> {
+        let start = self.index;
+        while self.peek().is_some_and(|c| {
+            !c.is_whitespace() && !matches!(c, ',' | '[' | ']' | '(' | ')' | '\'' | '"')
+        }) {
+            self.next();
+        }
+        let text = &self.input[start..self.index];
+        if text.is_empty() {
+            return Err(self.expected("a value"));
+        }
+        Ok(Literal::Plain(
+            text.to_string(),
+            self.input[..start].chars().count(),
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.index..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.expected(&format!("'{c}'"))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    /// In characters
+    fn position(&self) -> usize {
+        self.input[..self.index].chars().count()
+    }
+
+    fn expected(&self, what: &str) -> Error {
+        let got = match self.peek() {
+            Some(c) => format!("'{c}'"),
+            None => String::from("the end"),
+        };
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Expected {what} at position {}, got {got}\n{}",
+                self.position(),
+                self.input
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{connect, Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::{StartIndex, Variable as V};
+
+    fn plain(text: &str, position: usize) -> Literal {
+        Literal::Plain(text.to_string(), position)
+    }
+
+    #[test]
+    fn parse_arrays() {
+        assert_eq!(
+            parse("[1,2,3]", 64).unwrap(),
+            Literal::Array(vec![plain("1", 1), plain("2", 3), plain("3", 5)], 0)
+        );
+        assert_eq!(
+            parse("[[1, 2], [3, 4]]", 64).unwrap(),
+            Literal::Array(
+                vec![
+                    Literal::Array(vec![plain("1", 2), plain("2", 5)], 1),
+                    Literal::Array(vec![plain("3", 10), plain("4", 13)], 9),
+                ],
+                0
+            )
+        );
+        assert_eq!(
+            parse("[2(Food.Nothing), Food#Leek]", 64).unwrap(),
+            Literal::Array(
+                vec![
+                    plain("Food.Nothing", 3),
+                    plain("Food.Nothing", 3),
+                    plain("Food#Leek", 18),
+                ],
+                0
+            )
+        );
+        assert_eq!(
+            parse("[ 'hello', \"world, $\"again$\"\" ]", 64).unwrap(),
+            Literal::Array(
+                vec![
+                    Literal::Quoted(String::from("hello"), 2),
+                    Literal::Quoted(String::from("world, \"again\""), 11),
+                ],
+                0
+            )
+        );
+        assert_eq!(parse("[]", 64).unwrap(), Literal::Array(Vec::new(), 0));
+    }
+
+    #[test]
+    fn parse_structs() {
+        assert_eq!(
+            parse("(top_shelf := [8(0)], light:=TRUE)", 64).unwrap(),
+            Literal::Struct(
+                vec![
+                    (
+                        String::from("top_shelf"),
+                        Literal::Array(vec![plain("0", 17); 8], 14)
+                    ),
+                    (String::from("light"), plain("TRUE", 29)),
+                ],
+                0
+            )
+        );
+        assert_eq!(
+            parse("[2((a := 1))]", 64).unwrap(),
+            Literal::Array(
+                vec![Literal::Struct(vec![(String::from("a"), plain("1", 9))], 3); 2],
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn parse_strings() {
+        assert_eq!(
+            unquote("'it$'s $$5$R$N$09$4A'").unwrap(),
+            Some(String::from("it's $5\r\n\tJ"))
+        );
+        assert_eq!(unquote("\"$00e4\"").unwrap(), Some(String::from("ä")));
+        assert_eq!(unquote("plain").unwrap(), None);
+        assert!(unquote("'$0'").is_err());
+        assert!(unquote("'$q'").is_err());
+        assert!(unquote("'open").is_err());
+    }
+
+    #[test]
+    fn parse_integers() {
+        assert_eq!(integer("-5"), Some(-5));
+        assert_eq!(integer("1_000"), Some(1000));
+        assert_eq!(integer("16#FF"), Some(255));
+        assert_eq!(integer("16#ffff_ffff_ffff_ffff"), Some(u64::MAX as i128));
+        assert_eq!(integer("2#1010"), Some(10));
+        assert_eq!(integer("8#17"), Some(15));
+        assert_eq!(integer("-16#10"), Some(-16));
+        assert_eq!(integer("3#12"), None);
+        assert_eq!(integer("16#"), None);
+        assert_eq!(integer("1.5"), None);
+    }
+
+    #[test]
+    fn report_positions() {
+        let error = |input| parse(input, 64).unwrap_err().to_string();
+        assert!(error("[1,2,3").starts_with("Expected ',' or ']' at position 6, got the end"));
+        assert!(error("[1,2 3]").starts_with("Expected ',' or ']' at position 5, got '3'"));
+        assert!(error("[1,2](3)").starts_with("Expected the end at position 5, got '('"));
+        assert!(error("(a := 1 b := 2)").starts_with("Expected ',' or ')' at position 8, got 'b'"));
+        assert!(error("[1,2,3]x[4,5,6]").starts_with("Expected the end at position 7"));
+        assert!(error("(a := 1, b 2)").starts_with("Expected ':=' at position 11"));
+        assert!(error("(a := 1, := 2)").starts_with("Expected a field name at position 9"));
+        assert!(error("[1,,2]").starts_with("Expected a value at position 3"));
+        assert!(error("[x(1)]").starts_with("Expected a number of repetitions at position 1"));
+        assert!(error("[1,'2,3]").starts_with("Cannot find the end of the string at position 3"));
+        assert!(error("1,2,3,4]").starts_with("Expected the end at position 1"));
+        assert!(error("[1, 18446744073709551615(0)]")
+            .starts_with("18446744073709551615 repetitions at position 4 do not fit"));
+        assert!(
+            error("[100000000(0)]").starts_with("100000000 repetitions at position 1 do not fit")
+        );
+        assert!(error("[2([40(0)])]").starts_with("2 repetitions at position 1 do not fit"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn set_values_from_literals() {
+        let server = Server::builder()
+            .with_enum_members("Food", "INT", &[("Nothing", 0), ("Leek", 5)])
+            .with_struct(
+                "Larder",
+                &[
+                    Declaration::new("top_shelf", "ARRAY [0..7] OF Food"),
+                    Declaration::new("drawer", "ARRAY [0..1] OF INT"),
+                    Declaration::new("label", "STRING(12)"),
+                    Declaration::new("light", "BOOL"),
+                    Declaration::new("mask", "WORD"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.larder", "Larder"))
+            .with_symbol(Declaration::new("main.larders", "ARRAY [0..1] OF Larder"))
+            .with_symbol(Declaration::new("main.grid", "ARRAY [0..1,0..2] OF SINT"))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        let food = |name: &str, value| V::Enum {
+            type_name: String::from("Food"),
+            name: String::from(name),
+            value,
+        };
+        let larder = |top_shelf: V, drawer: [i16; 2], label: &str, light, mask| {
+            V::Struct(vec![
+                (
+                    String::from("top_shelf"),
+                    V::Array(StartIndex::Some(0), vec![top_shelf; 8]),
+                ),
+                (
+                    String::from("drawer"),
+                    V::Array(StartIndex::Some(0), drawer.map(V::I16).to_vec()),
+                ),
+                (String::from("label"), V::String(String::from(label))),
+                (String::from("light"), V::Bool(light)),
+                (String::from("mask"), V::U16(mask)),
+            ])
+        };
+
+        client
+            .set_value_from_str(
+                "main.larder",
+                "(top_shelf := [8(Food.Nothing)], drawer := [INT#5, -16#10], label := 'it$'s $$5', light := TRUE, mask := 2#1010)",
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.larder").unwrap(),
+            larder(food("Nothing", 0), [5, -16], "it's $5", true, 10)
+        );
+
+        client
+            .set_value_from_str(
+                "main.larders",
+                "[2((top_shelf := [8(Food#Leek)], drawer := [2(0)], label := '', light := 0, mask := 16#FFFF))]",
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.larders[1]").unwrap(),
+            larder(food("Leek", 5), [0, 0], "", false, 0xffff)
+        );
+
+        // Flat, as IEC 61131-3 gives them, or nested
+        let grid = |values: [i8; 6]| {
+            V::Array(
+                StartIndex::Some(0),
+                values
+                    .chunks(3)
+                    .map(|row| {
+                        V::Array(StartIndex::Some(0), row.iter().map(|&v| V::I8(v)).collect())
+                    })
+                    .collect(),
+            )
+        };
+        client
+            .set_value_from_str("main.grid", "[1, 2, 3, 3(-1)]")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.grid").unwrap(),
+            grid([1, 2, 3, -1, -1, -1])
+        );
+        client
+            .set_value_from_str("main.grid", "[[1_0, 2#11, 8#7], [3(SINT#0)]]")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.grid").unwrap(),
+            grid([10, 3, 7, 0, 0, 0])
+        );
+
+        let error = |value_name, value| {
+            client
+                .set_value_from_str(value_name, value)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("main.larder", "(drawer := [1, 70000])")
+            .ends_with("70000 is out of range of i16 (at position 15)"));
+        assert!(error("main.larder", "(drawer := [1, 2] label := '')")
+            .starts_with("Expected ',' or ')' at position 18, got 'l'"));
+        assert!(error("main.larder", "(shelf := [])")
+            .starts_with("Cannot find struct field shelf in Larder (at position 10)"));
+        assert!(error("main.larder", "(light := TRUE)").contains("gaps"));
+        assert!(error("main.larder.mask", "INT#5").starts_with("Expected a value of WORD"));
+        assert!(error("main.grid", "[1, 2, 3]").starts_with("Expected an array of length 6"));
+        assert!(error("main.grid", "[100000000(0)]").contains("do not fit in the 6 bytes"));
+    }
+}
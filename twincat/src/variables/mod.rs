@@ -1,13 +1,19 @@
 use std::io::{Error, ErrorKind, Result};
 use std::ops::RangeInclusive;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 use zerocopy::{FromBytes, IntoBytes};
 
 use crate::symbols_and_data_types::{DataType, DataTypes, Symbol};
 
-mod array;
-use array::str_array_split;
+mod literal;
+use literal::{at_position, Literal};
+mod string;
+pub use string::StringEncoding;
+use string::{bytes_to_string, bytes_to_wstring, str_to_bytes, wstr_to_bytes};
+mod time;
+use time::TimeType;
 mod try_into;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -25,8 +31,32 @@ pub enum Variable {
     F32(f32),
     F64(f64),
     String(String),
+    /// `WSTRING`, which TwinCAT stores as UTF-16
+    WString(String),
     Array(StartIndex, Vec<Variable>),
     Struct(Vec<(String, Variable)>),
+    /// A `UNION`, whose members share the same bytes.
+    /// Reads give every member, each as its own interpretation of the bytes
+    /// (or `Void` where the bytes are not valid for it, such as a `BOOL` over a 7).
+    /// Writes take exactly one member.
+    Union(Vec<(String, Variable)>),
+    /// A value of an enum, such as `Food.Carrot`.
+    /// Values which are not declared members are read as the enum's base type.
+    Enum {
+        type_name: String,
+        name: String,
+        value: i64,
+    },
+    /// `TIME`
+    Time(Duration),
+    /// `LTIME`
+    LTime(Duration),
+    /// `TOD` (`TIME_OF_DAY`), since midnight
+    TimeOfDay(Duration),
+    /// `DATE`, at midnight (UTC, as TwinCAT does not store a time zone)
+    Date(SystemTime),
+    /// `DT` (`DATE_AND_TIME`)
+    DateAndTime(SystemTime),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -100,6 +130,20 @@ impl Variable {
         symbol_data_type: &DataType,
         bytes: &[u8],
     ) -> Result<Self> {
+        if let Some(time_type) = TimeType::from_name(symbol_data_type.name()) {
+            return time_type.bytes_to_variable(bytes);
+        }
+        if symbol_data_type.is_enum() {
+            let value = bytes_to_integer(bytes, symbol.data_type_id())?;
+            if let Some(name) = symbol_data_type.enum_member(value) {
+                return Ok(Self::Enum {
+                    type_name: symbol_data_type.name().to_string(),
+                    name: name.to_string(),
+                    value,
+                });
+            }
+        }
+
         match symbol.data_type_id() {
             0 => {
                 if bytes.is_empty() {
@@ -134,16 +178,12 @@ impl Variable {
             21 => Ok(Self::U64(bytes_to_inner(bytes)?)),
             4 => Ok(Self::F32(bytes_to_inner(bytes)?)),
             5 => Ok(Self::F64(bytes_to_inner(bytes)?)),
-            30 => Ok(Self::String(bytes_to_string(bytes)?)),
+            30 => Ok(Self::String(bytes_to_string(
+                bytes,
+                data_types.string_encoding(),
+            )?)),
+            31 => Ok(Self::WString(bytes_to_wstring(bytes)?)),
             65 => Self::bytes_get_struct(data_types, symbol_data_type, bytes),
-            31 => Err(Error::new(
-                ErrorKind::Unsupported,
-                format!(
-                    "Type {} ({}) is not supported (value {bytes:?})",
-                    symbol.data_type(),
-                    symbol.data_type_id()
-                ),
-            )),
             32 | 34 => Err(Error::new(
                 ErrorKind::InvalidData,
                 format!(
@@ -168,6 +208,7 @@ impl Variable {
         symbol_data_type: &DataType,
         bytes: &[u8],
     ) -> Result<Self> {
+        let is_union = symbol_data_type.is_union();
         let mut elements = Vec::new();
         for field in symbol_data_type.fields() {
             let field_data_type_name = field.data_type().trim();
@@ -178,7 +219,11 @@ impl Variable {
             }
             let field_data_type = data_types.get(field_data_type_name)?;
             let index_start = field.offset();
-            let index_end = index_start + field_data_type.size_bytes();
+            // A `BIT` shares its byte with others, and is decoded as a `BOOL` of its own
+            let index_end = match field.bits() {
+                Some(_) => index_start + 1,
+                None => index_start + field_data_type.size_bytes(),
+            };
             if index_end > bytes.len() {
                 return Err(Error::new(
                     ErrorKind::InvalidData,
@@ -191,11 +236,28 @@ impl Variable {
                     ),
                 ));
             }
-            let field_bytes = &bytes[index_start..index_end];
-            let field_value = Self::from_bytes(data_types, field, field_data_type, field_bytes)?;
+            let field_value = match field.bits() {
+                Some(bits) => {
+                    let field_bytes = [bits.get(bytes[index_start])];
+                    Self::from_bytes(data_types, field, field_data_type, &field_bytes)
+                }
+                None => {
+                    let field_bytes = &bytes[index_start..index_end];
+                    Self::from_bytes(data_types, field, field_data_type, field_bytes)
+                }
+            };
+            let field_value = match field_value {
+                Ok(v) => v,
+                Err(_) if is_union => Self::Void,
+                Err(e) => return Err(e),
+            };
             elements.push((field.name().to_string(), field_value));
         }
-        Ok(Self::Struct(elements))
+        if is_union {
+            Ok(Self::Union(elements))
+        } else {
+            Ok(Self::Struct(elements))
+        }
     }
 
     pub(super) fn to_bytes(
@@ -213,7 +275,7 @@ impl Variable {
         symbol: &Symbol,
         array_ranges: &[RangeInclusive<i32>],
     ) -> Result<Vec<u8>> {
-        match (self, symbol.data_type_id()) {
+        let bytes = match (self, symbol.data_type_id()) {
             (Self::Void, 0) => Ok(Vec::new()),
             (Self::Bool(inner), 33) => {
                 let byte: u8 = if *inner { 1 } else { 0 };
@@ -229,16 +291,57 @@ impl Variable {
             (Self::U64(inner), 21) => Ok(inner.as_bytes().to_vec()),
             (Self::F32(inner), 4) => Ok(inner.as_bytes().to_vec()),
             (Self::F64(inner), 5) => Ok(inner.as_bytes().to_vec()),
-            (Self::String(inner), 30) => Ok(str_to_bytes(inner)),
+            (Self::String(inner), 30) => str_to_bytes(inner, data_types, symbol),
+            (Self::WString(inner), 31) => wstr_to_bytes(inner, data_types, symbol),
             (Self::Array(start_index, array_inner), _) => {
                 array_to_bytes(data_types, symbol, start_index, array_inner, array_ranges)
             }
             (Self::Struct(inner), 65) => struct_to_bytes(data_types, symbol, inner),
+            (Self::Union(inner), 65) => union_to_bytes(data_types, symbol, inner),
+            (
+                Self::Time(_)
+                | Self::LTime(_)
+                | Self::TimeOfDay(_)
+                | Self::Date(_)
+                | Self::DateAndTime(_),
+                _,
+            ) => match time_type(data_types, symbol).and_then(|t| t.variable_to_bytes(self)) {
+                Some(bytes) => bytes,
+                None => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unexpected data type; expected {symbol:?}, got {self:?}"),
+                )),
+            },
+            (
+                Self::Enum {
+                    type_name, name, ..
+                },
+                _,
+            ) => {
+                let enum_type = data_types.symbol_get_base_type(symbol, None)?;
+                if !enum_type.name().eq_ignore_ascii_case(type_name) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Unexpected data type; expected {symbol:?}, got {self:?}"),
+                    ));
+                }
+                match enum_type.enum_value(name) {
+                    Some(value) => integer_to_bytes(value, symbol.data_type_id()),
+                    None => Err(not_a_member(enum_type, name)),
+                }
+            }
             _ => Err(Error::new(
                 ErrorKind::InvalidInput,
                 format!("Unexpected data type; expected {symbol:?}, got {self:?}"),
             )),
+        }?;
+
+        // Each element of an array is checked on its own
+        if array_ranges.is_empty() {
+            check_subrange(&bytes, data_types, symbol)?;
+            check_enum_member(&bytes, data_types, symbol)?;
         }
+        Ok(bytes)
     }
 }
 
@@ -304,6 +407,20 @@ fn struct_to_bytes(
     symbol: &Symbol,
     fields: &[(String, Variable)],
 ) -> Result<Vec<u8>> {
+    let masked = struct_to_masked_bytes(data_types, symbol, fields)?;
+    if masked.iter().any(|&(_, mask)| mask != 0xff) {
+        return Err(Error::new(ErrorKind::Unsupported, format!("Cannot write {} as a single struct because the bytes have gaps; write the fields individually", symbol.name())));
+    }
+    Ok(masked.into_iter().map(|(byte, _)| byte).collect())
+}
+
+/// Each byte of the given fields at its offset in the struct, with a mask of the bits which
+/// they cover. Bytes of fields which are not given, and padding, are masked out.
+pub(super) fn struct_to_masked_bytes(
+    data_types: &DataTypes,
+    symbol: &Symbol,
+    fields: &[(String, Variable)],
+) -> Result<Vec<(u8, u8)>> {
     for i in 0..fields.len() {
         for j in i + 1..fields.len() {
             if fields[i].0 == fields[j].0 {
@@ -315,11 +432,23 @@ fn struct_to_bytes(
         }
     }
 
-    let mut some_bytes = Vec::new();
+    let symbol_data_type = data_types.symbol_get_base_type(symbol, None)?;
+    if symbol_data_type.is_union() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is a UNION; write one of its members as Variable::Union",
+                symbol.name()
+            ),
+        ));
+    }
+
+    let mut masked = Vec::new();
+    let symbol_fields = symbol_data_type.fields();
 
     for field in fields {
         let mut field_symbol = None;
-        for symbol_field in data_types.get(symbol.data_type())?.fields() {
+        for symbol_field in symbol_fields {
             if symbol_field.name() == field.0 {
                 field_symbol = Some(symbol_field);
                 break;
@@ -334,69 +463,345 @@ fn struct_to_bytes(
             ));
         };
 
-        let field_bytes = field.1.to_bytes(
-            data_types,
-            field_symbol,
-            data_types.get(field_symbol.data_type())?,
-        )?;
-
-        if field_symbol.offset() < some_bytes.len() {
-            for i in 0..field_bytes.len() {
-                if field_symbol.offset() + i < some_bytes.len() {
-                    some_bytes[field_symbol.offset() + i] = Some(field_bytes[i]);
-                } else {
-                    some_bytes.push(Some(field_bytes[i]));
-                }
+        // A struct within the struct may leave fields out too
+        let field_data_type = data_types.get(field_symbol.data_type())?;
+        let mut field_masked = match &field.1 {
+            Variable::Struct(inner) if field_symbol.data_type_id() == 65 => {
+                struct_to_masked_bytes(data_types, field_symbol, inner)?
             }
-        } else {
-            some_bytes.extend(vec![None; field_symbol.offset() - some_bytes.len()]);
-            some_bytes.extend(
-                field_bytes
-                    .iter()
-                    .map(|&b| Some(b))
-                    .collect::<Vec<Option<u8>>>(),
+            value => value
+                .to_bytes(data_types, field_symbol, field_data_type)?
+                .into_iter()
+                .map(|byte| (byte, 0xff))
+                .collect(),
+        };
+        // The rest of a string is zeroed, as TwinCAT does
+        if matches!(field.1, Variable::String(_) | Variable::WString(_)) {
+            field_masked.resize(
+                field_data_type.size_bytes().max(field_masked.len()),
+                (0, 0xff),
             );
         }
+
+        let offset = field_symbol.offset();
+        let length = match field_symbol.bits() {
+            Some(_) => 1,
+            None => field_masked.len(),
+        };
+        if offset + length > masked.len() {
+            masked.resize(offset + length, (0, 0));
+        }
+
+        // Merged into the byte it shares with the other `BIT`s
+        if let Some(bits) = field_symbol.bits() {
+            let value = field_masked.first().map(|&(b, _)| b).unwrap_or_default();
+            let (byte, mask) = &mut masked[offset];
+            *byte = bits.set(*byte, value);
+            *mask |= bits.set(0, 0xff);
+            continue;
+        }
+
+        for (i, (field_byte, field_mask)) in field_masked.into_iter().enumerate() {
+            let (byte, mask) = &mut masked[offset + i];
+            *byte = (*byte & !field_mask) | (field_byte & field_mask);
+            *mask |= field_mask;
+        }
+    }
+
+    // A byte whose `BIT`s are all given is covered, including the bits which none of them use
+    for (offset, (_, mask)) in masked.iter_mut().enumerate() {
+        let mut bit_fields = symbol_fields
+            .iter()
+            .filter(|sf| sf.bits().is_some() && sf.offset() == offset)
+            .peekable();
+        if bit_fields.peek().is_some()
+            && bit_fields.all(|sf| fields.iter().any(|f| f.0 == sf.name()))
+        {
+            *mask = 0xff;
+        }
+    }
+
+    Ok(masked)
+}
+
+/// The members of a union overlap, so only one may be written
+fn union_to_bytes(
+    data_types: &DataTypes,
+    symbol: &Symbol,
+    members: &[(String, Variable)],
+) -> Result<Vec<u8>> {
+    let symbol_data_type = data_types.symbol_get_base_type(symbol, None)?;
+    if !symbol_data_type.is_union() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is not a UNION, so cannot be written as one",
+                symbol.name()
+            ),
+        ));
     }
+    let (member_name, member) = match members {
+        [member] => member,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Write exactly one member of the UNION {}, not {}",
+                    symbol.name(),
+                    members.len()
+                ),
+            ))
+        }
+    };
 
-    some_bytes.iter().map(|bx| match bx {
-        Some(b) => Ok(*b),
-        None => Err(Error::new(ErrorKind::Unsupported, format!("Cannot write {} as a single struct because the bytes have gaps; write the fields individually", symbol.name()))),
-    }).collect()
+    let member_symbol = match symbol_data_type
+        .fields()
+        .iter()
+        .find(|f| f.name() == member_name)
+    {
+        Some(ms) => ms,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot find union member {member_name}"),
+            ))
+        }
+    };
+    member.to_bytes(
+        data_types,
+        member_symbol,
+        data_types.get(member_symbol.data_type())?,
+    )
 }
 
 pub(super) fn str_and_symbol_to_bytes(
     value: &str,
+    data_types: &DataTypes,
     symbol: &Symbol,
     symbol_data_type: &DataType,
 ) -> Result<Vec<u8>> {
-    str_and_symbol_to_bytes_inner(value, symbol, symbol_data_type.array_ranges())
+    str_and_symbol_to_bytes_inner(
+        value,
+        data_types,
+        symbol,
+        symbol_data_type.array_ranges(),
+        symbol_data_type.size_bytes(),
+    )
 }
 
 fn str_and_symbol_to_bytes_inner(
     value: &str,
+    data_types: &DataTypes,
     symbol: &Symbol,
     array_ranges: &[RangeInclusive<i32>],
+    size_bytes: usize,
 ) -> Result<Vec<u8>> {
-    match array_ranges.first() {
-        None => str_and_symbol_to_bytes_flat(value, symbol),
-        Some(array_range) => {
-            let array_length = 1 + *array_range.end() - *array_range.start();
-            let values = str_array_split(value)?;
-            if values.len() as i32 != array_length {
-                return Err(Error::new(ErrorKind::InvalidInput, format!("Expected an array of length {array_length}, got {value} which is of length {}", values.len())));
+    // Only arrays and structs are parsed as literals, so that an unquoted `STRING` may hold any text
+    if array_ranges.is_empty() && symbol.data_type_id() != 65 {
+        let bytes = str_and_symbol_to_bytes_flat(value, data_types, symbol)?;
+        check_subrange(&bytes, data_types, symbol)?;
+        return Ok(bytes);
+    }
+    literal_to_bytes(
+        &literal::parse(value, size_bytes)?,
+        data_types,
+        symbol,
+        array_ranges,
+    )
+}
+
+fn literal_to_bytes(
+    literal: &Literal,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+    array_ranges: &[RangeInclusive<i32>],
+) -> Result<Vec<u8>> {
+    match (literal, array_ranges.first()) {
+        (Literal::Array(elements, position), Some(array_range)) => {
+            // A multi-dimensional array may be given flat, as IEC 61131-3 does, or nested
+            let (element_ranges, array_length) = if array_ranges.len() > 1
+                && !matches!(elements.first(), Some(Literal::Array(..)))
+            {
+                let n_elements = array_ranges
+                    .iter()
+                    .map(|r| (1 + r.end() - r.start()) as usize)
+                    .product::<usize>();
+                (&[][..], n_elements)
+            } else {
+                let n_elements = (1 + array_range.end() - array_range.start()) as usize;
+                (&array_ranges[1..], n_elements)
+            };
+            if elements.len() != array_length {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Expected an array of length {array_length}, got {} elements (at position {position})",
+                        elements.len()
+                    ),
+                ));
             }
+
             let mut bytes = Vec::new();
-            for v in str_array_split(value)? {
-                let chunk = str_and_symbol_to_bytes_inner(&v, symbol, &array_ranges[1..])?;
-                bytes.extend(chunk);
+            for element in elements {
+                bytes.extend(literal_to_bytes(
+                    element,
+                    data_types,
+                    symbol,
+                    element_ranges,
+                )?);
             }
             Ok(bytes)
         }
+        (_, Some(_)) => Err(at_position(
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Expected an array, such as [1, 2], for {}", symbol.name()),
+            ),
+            literal.position(),
+        )),
+        (Literal::Struct(fields, position), None) => {
+            struct_literal_to_bytes(fields, *position, data_types, symbol)
+        }
+        (Literal::Plain(value, position), None) => {
+            str_and_symbol_to_bytes_flat(value, data_types, symbol)
+                .and_then(|bytes| {
+                    check_subrange(&bytes, data_types, symbol)?;
+                    Ok(bytes)
+                })
+                .map_err(|e| at_position(e, *position))
+        }
+        (Literal::Quoted(text, position), None) => match symbol.data_type_id() {
+            30 => str_to_bytes(text, data_types, symbol),
+            31 => wstr_to_bytes(text, data_types, symbol),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Expected a value of {}, got a string", symbol.data_type()),
+            )),
+        }
+        .map_err(|e| at_position(e, *position)),
+        (Literal::Array(_, position), None) => Err(at_position(
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not an array", symbol.data_type()),
+            ),
+            *position,
+        )),
     }
 }
 
-fn str_and_symbol_to_bytes_flat(value: &str, symbol: &Symbol) -> Result<Vec<u8>> {
+/// Each field is encoded as its own literal, then the struct as a `Variable::Struct`
+/// (or a `Variable::Union`), so that it is checked as `Client::set_value` would check it
+fn struct_literal_to_bytes(
+    fields: &[(String, Literal)],
+    position: usize,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+) -> Result<Vec<u8>> {
+    let data_type = data_types.symbol_get_base_type(symbol, None)?;
+    if symbol.data_type_id() != 65 {
+        return Err(at_position(
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Expected a value of {}, got a structure initialiser",
+                    data_type.name()
+                ),
+            ),
+            position,
+        ));
+    }
+
+    let mut variables = Vec::with_capacity(fields.len());
+    for (name, field_literal) in fields {
+        let field_symbol = match data_type
+            .fields()
+            .iter()
+            .find(|f| f.name().eq_ignore_ascii_case(name))
+        {
+            Some(f) => f,
+            None => {
+                return Err(at_position(
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Cannot find struct field {name} in {}", data_type.name()),
+                    ),
+                    field_literal.position(),
+                ))
+            }
+        };
+        let field_data_type = data_types.get(field_symbol.data_type())?;
+        let bytes = literal_to_bytes(
+            field_literal,
+            data_types,
+            field_symbol,
+            field_data_type.array_ranges(),
+        )?;
+        let field_value = Variable::from_bytes(data_types, field_symbol, field_data_type, &bytes)?;
+        variables.push((field_symbol.name().to_string(), field_value));
+    }
+
+    let value = if data_type.is_union() {
+        Variable::Union(variables)
+    } else {
+        Variable::Struct(variables)
+    };
+    value
+        .to_bytes_inner(data_types, symbol, &[])
+        .map_err(|e| at_position(e, position))
+}
+
+fn str_and_symbol_to_bytes_flat(
+    value: &str,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+) -> Result<Vec<u8>> {
+    if let Some(time_type) = time_type(data_types, symbol) {
+        return time_type.str_to_bytes(value);
+    }
+    // Enum values may be given by name, such as `Food.Carrot` or `Food#Carrot`,
+    // or as the integer of a member
+    if let Ok(enum_type) = data_types.symbol_get_base_type(symbol, None) {
+        if enum_type.is_enum() {
+            let enum_value = match value.split_once(['.', '#']) {
+                Some((type_name, member)) => {
+                    if !enum_type.name().eq_ignore_ascii_case(type_name.trim()) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Expected a value of {}, got {value}", enum_type.name()),
+                        ));
+                    }
+                    enum_type
+                        .enum_value(member.trim())
+                        .ok_or_else(|| not_a_member(enum_type, member.trim()))?
+                }
+                None => match literal::integer(value.trim()).map(i64::try_from) {
+                    Some(Ok(v)) if enum_type.enum_member(v).is_some() => v,
+                    Some(_) => return Err(not_a_member(enum_type, value.trim())),
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Cannot parse {value}"),
+                        ))
+                    }
+                },
+            };
+            return integer_to_bytes(enum_value, symbol.data_type_id());
+        }
+    }
+
+    // Strings are taken as they are, unless quoted
+    match symbol.data_type_id() {
+        30 | 31 => {
+            let text = literal::unquote(value)?.unwrap_or_else(|| value.to_string());
+            return match symbol.data_type_id() {
+                30 => str_to_bytes(&text, data_types, symbol),
+                _ => wstr_to_bytes(&text, data_types, symbol),
+            };
+        }
+        _ => (),
+    }
+
+    let value = strip_type(value.trim(), data_types, symbol)?;
     match symbol.data_type_id() {
         0 => {
             if value.is_empty() {
@@ -408,33 +813,29 @@ fn str_and_symbol_to_bytes_flat(value: &str, symbol: &Symbol) -> Result<Vec<u8>>
                 ))
             }
         }
-        33 => match bool::from_str(&value.to_lowercase()) {
-            Ok(b) => {
-                let byte = if b { 1 } else { 0 };
-                Ok(vec![byte])
-            }
-            Err(e) => Err(Error::new(
+        33 => match value.to_lowercase().as_str() {
+            "true" | "1" => Ok(vec![1]),
+            "false" | "0" => Ok(vec![0]),
+            _ => Err(Error::new(
                 ErrorKind::InvalidInput,
-                format!("Expected bool, got {value} ({e:?})"),
+                format!("Expected bool, got {value}"),
             )),
         },
-        16 => from_str_to_bytes::<i8>(value),
-        2 => from_str_to_bytes::<i16>(value),
-        3 => from_str_to_bytes::<i32>(value),
-        20 => from_str_to_bytes::<i64>(value),
-        17 => from_str_to_bytes::<u8>(value),
-        18 => from_str_to_bytes::<u16>(value),
-        19 => from_str_to_bytes::<u32>(value),
-        21 => from_str_to_bytes::<u64>(value),
-        4 => from_str_to_bytes::<f32>(value),
-        5 => from_str_to_bytes::<f64>(value),
-        30 => Ok(str_to_bytes(value)),
-        65 | 31 => Err(Error::new(
-            ErrorKind::Unsupported,
+        16 => integer_str_to_bytes::<i8>(value),
+        2 => integer_str_to_bytes::<i16>(value),
+        3 => integer_str_to_bytes::<i32>(value),
+        20 => integer_str_to_bytes::<i64>(value),
+        17 => integer_str_to_bytes::<u8>(value),
+        18 => integer_str_to_bytes::<u16>(value),
+        19 => integer_str_to_bytes::<u32>(value),
+        21 => integer_str_to_bytes::<u64>(value),
+        4 => from_str_to_bytes::<f32>(&value.replace('_', "")),
+        5 => from_str_to_bytes::<f64>(&value.replace('_', "")),
+        65 => Err(Error::new(
+            ErrorKind::InvalidInput,
             format!(
-                "Type {} ({}) is not supported (value {value})",
-                symbol.data_type(),
-                symbol.data_type_id()
+                "Expected a structure initialiser, such as (a := 1), for {}, got {value}",
+                symbol.data_type()
             ),
         )),
         32 | 34 => Err(Error::new(
@@ -462,6 +863,155 @@ pub(super) fn bytes_to_inner<T: FromBytes>(bytes: &[u8]) -> Result<T> {
     }
 }
 
+/// The value of an integer type, such as the base type of an enum
+pub(super) fn bytes_to_integer(bytes: &[u8], data_type_id: u8) -> Result<i64> {
+    match data_type_id {
+        16 => Ok(bytes_to_inner::<i8>(bytes)?.into()),
+        2 => Ok(bytes_to_inner::<i16>(bytes)?.into()),
+        3 => Ok(bytes_to_inner::<i32>(bytes)?.into()),
+        20 => bytes_to_inner::<i64>(bytes),
+        17 => Ok(bytes_to_inner::<u8>(bytes)?.into()),
+        18 => Ok(bytes_to_inner::<u16>(bytes)?.into()),
+        19 => Ok(bytes_to_inner::<u32>(bytes)?.into()),
+        21 => match i64::try_from(bytes_to_inner::<u64>(bytes)?) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot hold {bytes:?} as an i64\n{e}"),
+            )),
+        },
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Type {data_type_id} is not an integer (value {bytes:?})"),
+        )),
+    }
+}
+
+/// A subrange such as `INT(0..100)`, or an alias of one, holds only the values within its bounds
+fn check_subrange(bytes: &[u8], data_types: &DataTypes, symbol: &Symbol) -> Result<()> {
+    let range = match data_types.symbol_subrange(symbol) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+
+    let value = bytes_to_integer(bytes, symbol.data_type_id())?;
+    if !range.contains(&value) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{value} is outside {} ({}..{})",
+                symbol.data_type(),
+                range.start(),
+                range.end()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// An enum given as a plain integer must still hold the value of one of its members
+fn check_enum_member(bytes: &[u8], data_types: &DataTypes, symbol: &Symbol) -> Result<()> {
+    let enum_type = match data_types.symbol_get_base_type(symbol, None) {
+        Ok(t) if t.is_enum() => t,
+        _ => return Ok(()),
+    };
+
+    let value = bytes_to_integer(bytes, symbol.data_type_id())?;
+    match enum_type.enum_member(value) {
+        Some(_) => Ok(()),
+        None => Err(not_a_member(enum_type, &value.to_string())),
+    }
+}
+
+fn integer_to_bytes(value: i64, data_type_id: u8) -> Result<Vec<u8>> {
+    fn narrow<T: TryFrom<i64> + zerocopy::Immutable + IntoBytes>(value: i64) -> Result<Vec<u8>> {
+        match T::try_from(value) {
+            Ok(t) => Ok(t.as_bytes().to_vec()),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{value} is out of range of {}", std::any::type_name::<T>()),
+            )),
+        }
+    }
+
+    match data_type_id {
+        16 => narrow::<i8>(value),
+        2 => narrow::<i16>(value),
+        3 => narrow::<i32>(value),
+        20 => narrow::<i64>(value),
+        17 => narrow::<u8>(value),
+        18 => narrow::<u16>(value),
+        19 => narrow::<u32>(value),
+        21 => narrow::<u64>(value),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Type {data_type_id} is not an integer (value {value})"),
+        )),
+    }
+}
+
+/// Resolved from the data type name, as TwinCAT stores these as plain integers
+fn time_type(data_types: &DataTypes, symbol: &Symbol) -> Option<TimeType> {
+    let data_type = data_types.symbol_get_base_type(symbol, None).ok()?;
+    TimeType::from_name(data_type.name())
+}
+
+fn not_a_member(enum_type: &DataType, member: &str) -> Error {
+    let members = enum_type
+        .enum_members()
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<&str>>();
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "{member} is not a member of {} (expected one of {})",
+            enum_type.name(),
+            members.join(", ")
+        ),
+    )
+}
+
+/// `5` of a typed literal such as `INT#5`, whose type must be that of the symbol
+fn strip_type<'a>(value: &'a str, data_types: &DataTypes, symbol: &Symbol) -> Result<&'a str> {
+    let (type_name, untyped) = match value.split_once('#') {
+        Some((t, u)) if t.starts_with(|c: char| c.is_ascii_alphabetic()) => (t, u),
+        _ => return Ok(value),
+    };
+
+    // An alias may be named by itself or by the type it stands for
+    let base_name = match data_types.symbol_get_base_type(symbol, None) {
+        Ok(data_type) => data_type.name(),
+        Err(_) => symbol.data_type(),
+    };
+    if !type_name.eq_ignore_ascii_case(base_name)
+        && !type_name.eq_ignore_ascii_case(symbol.data_type())
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Expected a value of {base_name}, got {value}"),
+        ));
+    }
+    Ok(untyped)
+}
+
+/// From `-5`, `1_000`, or `16#FF` and the other bases
+fn integer_str_to_bytes<T: TryFrom<i128> + zerocopy::Immutable + IntoBytes>(
+    value: &str,
+) -> Result<Vec<u8>> {
+    match literal::integer(value).map(T::try_from) {
+        Some(Ok(t)) => Ok(t.as_bytes().to_vec()),
+        Some(Err(_)) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{value} is out of range of {}", std::any::type_name::<T>()),
+        )),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot parse {value}"),
+        )),
+    }
+}
+
 fn from_str_to_bytes<T: FromStr + zerocopy::Immutable + IntoBytes>(value: &str) -> Result<Vec<u8>> {
     match T::from_str(value) {
         Ok(t) => Ok(t.as_bytes().to_vec()),
@@ -472,25 +1022,169 @@ fn from_str_to_bytes<T: FromStr + zerocopy::Immutable + IntoBytes>(value: &str)
     }
 }
 
-fn bytes_to_string(bytes: &[u8]) -> Result<String> {
-    let end_index = match bytes.iter().position(|&c| c == 0) {
-        Some(ei) => ei,
-        None => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Cannot find null-terminator\n{bytes:?}"),
-            ))
-        }
-    };
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use crate::mock::{connect, Declaration, Server};
+    use crate::{StartIndex, Variable as V};
+
+    #[test]
+    fn get_and_set_enums() {
+        let food = |name: &str, value| V::Enum {
+            type_name: String::from("Food"),
+            name: String::from(name),
+            value,
+        };
+        let server = Server::builder()
+            .with_enum_members("Food", "INT", &[("Carrot", 0), ("Potato", 1), ("Leek", 5)])
+            .with_symbol(Declaration::new("main.dinner", "Food"))
+            .with_symbol(Declaration::new("main.shelf", "ARRAY [0..2] OF Food"))
+            .with_value("main.dinner", food("Potato", 1))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        assert_eq!(client.get_value("main.dinner").unwrap(), food("Potato", 1));
+
+        client
+            .set_value_from_str("main.dinner", "Food.Leek")
+            .unwrap();
+        assert_eq!(client.get_value("main.dinner").unwrap(), food("Leek", 5));
+        client.set_value_from_str("main.dinner", "0").unwrap();
+        assert_eq!(client.get_value("main.dinner").unwrap(), food("Carrot", 0));
+        assert!(client
+            .set_value_from_str("main.dinner", "Food.Cabbage")
+            .is_err());
+        assert!(client
+            .set_value_from_str("main.dinner", "Plant.Leek")
+            .is_err());
+        assert!(client.set_value("main.dinner", food("Cabbage", 2)).is_err());
 
-    match String::from_utf8(bytes[..end_index].to_vec()) {
-        Ok(s) => Ok(s),
-        Err(err) => Err(Error::other(err.to_string())),
+        // Integers are taken only if they are the value of a member
+        assert!(client.set_value_from_str("main.dinner", "7").is_err());
+        assert!(client.set_value("main.dinner", V::I16(7)).is_err());
+        client.set_value("main.dinner", V::I16(1)).unwrap();
+        assert_eq!(client.get_value("main.dinner").unwrap(), food("Potato", 1));
+
+        assert!(client
+            .set_value_from_str("main.shelf", "[Food.Leek,7,Food.Potato]")
+            .is_err());
+        client
+            .set_value_from_str("main.shelf", "[Food.Leek,0,Food.Potato]")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.shelf").unwrap(),
+            V::Array(
+                StartIndex::Some(0),
+                vec![food("Leek", 5), food("Carrot", 0), food("Potato", 1)]
+            )
+        );
     }
-}
 
-fn str_to_bytes(inner: &str) -> Vec<u8> {
-    let mut output = inner.as_bytes().to_vec();
-    output.push(0);
-    output
+    #[test]
+    fn get_and_set_unions() {
+        let server = Server::builder()
+            .with_union(
+                "Register",
+                &[
+                    Declaration::new("raw", "DWORD"),
+                    Declaration::new("bytes", "ARRAY [0..3] OF BYTE"),
+                    Declaration::new("flag", "BOOL"),
+                ],
+            )
+            .with_struct(
+                "Device",
+                &[
+                    Declaration::new("id", "INT"),
+                    Declaration::new("register", "Register"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.device", "Device"))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        let register = |raw, bytes: [u8; 4], flag| {
+            V::Union(vec![
+                (String::from("raw"), V::U32(raw)),
+                (
+                    String::from("bytes"),
+                    V::Array(
+                        StartIndex::Some(0),
+                        bytes.iter().map(|b| V::U8(*b)).collect(),
+                    ),
+                ),
+                (String::from("flag"), flag),
+            ])
+        };
+
+        client
+            .set_value(
+                "main.device.register",
+                V::Union(vec![(String::from("raw"), V::U32(0x0403_0201))]),
+            )
+            .unwrap();
+        // 1 is a valid BOOL
+        assert_eq!(
+            client.get_value("main.device.register").unwrap(),
+            register(0x0403_0201, [1, 2, 3, 4], V::Bool(true))
+        );
+
+        client
+            .set_value("main.device.register.bytes[0]", V::U8(7))
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.device").unwrap(),
+            V::Struct(vec![
+                (String::from("id"), V::I16(0)),
+                (
+                    String::from("register"),
+                    register(0x0403_0207, [7, 2, 3, 4], V::Void)
+                ),
+            ])
+        );
+
+        // Members overlap, so only one may be written
+        assert!(client
+            .set_value(
+                "main.device.register",
+                V::Union(vec![
+                    (String::from("raw"), V::U32(1)),
+                    (String::from("flag"), V::Bool(true)),
+                ]),
+            )
+            .is_err());
+        assert!(client
+            .set_value(
+                "main.device.register",
+                V::Struct(vec![(String::from("raw"), V::U32(1))]),
+            )
+            .is_err());
+        assert!(client
+            .set_value(
+                "main.device",
+                V::Union(vec![(String::from("id"), V::I16(1))]),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn set_unions_of_one_member() {
+        let server = Server::builder()
+            .with_union("Raw", &[Declaration::new("raw", "DWORD")])
+            .with_struct("Wrapper", &[Declaration::new("raw", "DWORD")])
+            .with_symbol(Declaration::new("main.raw", "Raw"))
+            .with_symbol(Declaration::new("main.wrapper", "Wrapper"))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        // The type flags tell them apart, though their layouts are the same
+        let raw = |value| vec![(String::from("raw"), V::U32(value))];
+        client.set_value("main.raw", V::Union(raw(7))).unwrap();
+        assert_eq!(client.get_value("main.raw").unwrap(), V::Union(raw(7)));
+        assert!(client.set_value("main.raw", V::Struct(raw(8))).is_err());
+        client.set_value("main.wrapper", V::Struct(raw(8))).unwrap();
+        assert_eq!(client.get_value("main.wrapper").unwrap(), V::Struct(raw(8)));
+        assert!(client.set_value("main.wrapper", V::Union(raw(9))).is_err());
+    }
 }
@@ -0,0 +1,303 @@
+//! `STRING` and `WSTRING`, null-terminated and no longer than their declared length
+
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use crate::symbols_and_data_types::{DataTypes, Symbol};
+
+/// The length of `STRING` and `WSTRING`, without `(n)`
+const LENGTH_DEFAULT: usize = 80;
+
+/// Windows-1252 characters 0x80 to 0x9F, where they differ from Latin-1.
+/// The five which are undefined decode to the control character of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// How the target stores `STRING`s.
+/// TwinCAT uses Windows-1252 unless the project says otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StringEncoding {
+    #[default]
+    Windows1252,
+    Utf8,
+}
+
+impl StringEncoding {
+    fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            Self::Windows1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => char::from(b),
+                })
+                .collect()),
+            Self::Utf8 => match String::from_utf8(bytes.to_vec()) {
+                Ok(s) => Ok(s),
+                Err(err) => Err(Error::new(ErrorKind::InvalidData, err.to_string())),
+            },
+        }
+    }
+
+    fn encode(self, inner: &str) -> Result<Vec<u8>> {
+        match self {
+            Self::Windows1252 => inner
+                .chars()
+                .map(|c| match u8::try_from(c) {
+                    Ok(b) if !(0x80..=0x9f).contains(&b) => Ok(b),
+                    _ => match WINDOWS_1252_HIGH.iter().position(|&h| h == c) {
+                        Some(i) => Ok(0x80 + i as u8),
+                        None => Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("{inner} has {c:?}, which Windows-1252 cannot represent"),
+                        )),
+                    },
+                })
+                .collect(),
+            Self::Utf8 => Ok(inner.as_bytes().to_vec()),
+        }
+    }
+}
+
+pub(super) fn bytes_to_string(bytes: &[u8], encoding: StringEncoding) -> Result<String> {
+    let end_index = match bytes.iter().position(|&c| c == 0) {
+        Some(ei) => ei,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot find null-terminator\n{bytes:?}"),
+            ))
+        }
+    };
+
+    encoding.decode(&bytes[..end_index])
+}
+
+/// In the target's encoding and null-terminated, and no longer than the declared `STRING(n)`
+pub(super) fn str_to_bytes(
+    inner: &str,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+) -> Result<Vec<u8>> {
+    let mut output = data_types.string_encoding().encode(inner)?;
+    check_length(inner, output.len(), data_types, symbol, "STRING", 1)?;
+    output.push(0);
+    Ok(output)
+}
+
+pub(super) fn bytes_to_wstring(bytes: &[u8]) -> Result<String> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect::<Vec<u16>>();
+    let end_index = match units.iter().position(|&u| u == 0) {
+        Some(ei) => ei,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot find null-terminator\n{bytes:?}"),
+            ))
+        }
+    };
+
+    match String::from_utf16(&units[..end_index]) {
+        Ok(s) => Ok(s),
+        Err(err) => Err(Error::new(ErrorKind::InvalidData, err.to_string())),
+    }
+}
+
+/// UTF-16LE and null-terminated, and no longer than the declared `WSTRING(n)`
+pub(super) fn wstr_to_bytes(
+    inner: &str,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    for unit in inner.encode_utf16() {
+        output.extend(unit.to_le_bytes());
+    }
+    check_length(inner, output.len() / 2, data_types, symbol, "WSTRING", 2)?;
+    output.extend([0, 0]);
+    Ok(output)
+}
+
+/// `length` is in characters of `unit_bytes` each (bytes for `STRING`, UTF-16 units for `WSTRING`)
+fn check_length(
+    inner: &str,
+    length: usize,
+    data_types: &DataTypes,
+    symbol: &Symbol,
+    string_type: &str,
+    unit_bytes: usize,
+) -> Result<()> {
+    let data_type = data_types.symbol_get_base_type(symbol, None)?;
+    let declared_length = match declared_length(data_type.name(), string_type) {
+        Some(l) => l,
+        // Such as an alias of `STRING(255)`
+        None => (data_type.size_bytes() / unit_bytes).saturating_sub(1),
+    };
+
+    if length > declared_length {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{inner} is {length} characters long, but {} holds at most {declared_length}",
+                data_type.name()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `n` of `STRING(n)`, or the default length of `STRING`
+fn declared_length(data_type_name: &str, string_type: &str) -> Option<usize> {
+    let remainder = data_type_name.strip_prefix(string_type)?.trim();
+    if remainder.is_empty() {
+        return Some(LENGTH_DEFAULT);
+    }
+    let length = remainder.strip_prefix('(')?.strip_suffix(')')?;
+    usize::from_str(length.trim()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{connect, Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::{Client, StartIndex, Variable as V};
+
+    #[test]
+    fn windows_1252() {
+        let bytes = [b'G', 0x72, 0xfc, 0xdf, 0x65, b' ', 0x80, 0x85];
+        let text = StringEncoding::Windows1252.decode(&bytes).unwrap();
+        assert_eq!(text, "Grüße €…");
+        assert_eq!(StringEncoding::Windows1252.encode(&text).unwrap(), bytes);
+
+        assert_eq!(
+            StringEncoding::Windows1252
+                .encode("Größe 温度")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            StringEncoding::Windows1252.decode(&[0x81]).unwrap(),
+            "\u{81}"
+        );
+        assert_eq!(
+            StringEncoding::Windows1252.encode("\u{81}").unwrap(),
+            [0x81]
+        );
+    }
+
+    #[test]
+    fn utf_8() {
+        assert_eq!(StringEncoding::Utf8.encode("ü").unwrap(), [0xc3, 0xbc]);
+        assert_eq!(StringEncoding::Utf8.decode(&[0xc3, 0xbc]).unwrap(), "ü");
+        assert!(StringEncoding::Utf8.decode(&[0xfc]).is_err());
+    }
+
+    #[test]
+    fn parse_declared_lengths() {
+        assert_eq!(declared_length("STRING", "STRING"), Some(80));
+        assert_eq!(declared_length("STRING(255)", "STRING"), Some(255));
+        assert_eq!(declared_length("WSTRING(12)", "WSTRING"), Some(12));
+        assert_eq!(declared_length("WSTRING(12)", "STRING"), None);
+        assert_eq!(declared_length("T_MaxString", "STRING"), None);
+        assert_eq!(declared_length("STRING(n)", "STRING"), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_and_set_wstrings() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.greeting", "WSTRING(8)"))
+            .with_symbol(Declaration::new("main.labels", "ARRAY [1..2] OF WSTRING"))
+            .with_value("main.greeting", V::WString(String::from("Grüße")))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        assert_eq!(
+            client.get_value("main.greeting").unwrap(),
+            V::WString(String::from("Grüße"))
+        );
+
+        client
+            .set_value_from_str("main.greeting", "温度 🌡")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.greeting").unwrap(),
+            V::WString(String::from("温度 🌡"))
+        );
+        client
+            .set_value("main.labels[2]", V::WString(String::from("Küche")))
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.labels").unwrap(),
+            V::Array(
+                StartIndex::Some(1),
+                vec![V::WString(String::new()), V::WString(String::from("Küche"))]
+            )
+        );
+
+        // 8 characters fit, but the emoji takes two UTF-16 units
+        assert!(client
+            .set_value_from_str("main.greeting", "12345678")
+            .is_ok());
+        assert!(client
+            .set_value_from_str("main.greeting", "1234567🌡")
+            .is_err());
+        assert!(client
+            .set_value("main.greeting", V::String(String::from("abc")))
+            .is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_and_set_strings() {
+        let server = Server::builder()
+            .with_symbol(Declaration::new("main.street", "STRING(6)"))
+            .with_value("main.street", V::String(String::from("Straße")))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        assert_eq!(
+            client.get_value("main.street").unwrap(),
+            V::String(String::from("Straße"))
+        );
+        client.set_value_from_str("main.street", "Wäldle").unwrap();
+        assert_eq!(
+            client.get_value("main.street").unwrap(),
+            V::String(String::from("Wäldle"))
+        );
+
+        // Rejected before anything is sent
+        let error = client
+            .set_value_from_str("main.street", "Dorfstraße")
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(client
+            .set_value("main.street", V::String(String::from("Ωmega")))
+            .is_err());
+
+        // In UTF-8, "ä" takes two bytes
+        let utf_8 = Client::builder()
+            .with_tcp_target(server.address())
+            .with_string_encoding(StringEncoding::Utf8)
+            .connect()
+            .unwrap();
+        assert!(utf_8.get_value("main.street").is_err());
+        assert!(utf_8.set_value_from_str("main.street", "Wäldle").is_err());
+        utf_8.set_value_from_str("main.street", "Wälde").unwrap();
+        assert_eq!(
+            utf_8.get_value("main.street").unwrap(),
+            V::String(String::from("Wälde"))
+        );
+    }
+}
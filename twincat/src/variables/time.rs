@@ -0,0 +1,451 @@
+//! `TIME`, `LTIME`, `TOD`, `DATE` and `DT`, which TwinCAT stores as plain integers
+
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use zerocopy::IntoBytes;
+
+use super::{bytes_to_inner, Variable};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum TimeType {
+    /// Milliseconds, in a `UDINT`
+    Time,
+    /// Nanoseconds, in a `ULINT`
+    LTime,
+    /// Milliseconds since midnight, in a `UDINT`
+    TimeOfDay,
+    /// Seconds since 1970-01-01, at midnight, in a `UDINT`
+    Date,
+    /// Seconds since 1970-01-01, in a `UDINT`
+    DateAndTime,
+}
+
+impl TimeType {
+    pub(super) fn from_name(data_type_name: &str) -> Option<Self> {
+        match data_type_name {
+            "TIME" => Some(Self::Time),
+            "LTIME" => Some(Self::LTime),
+            "TOD" | "TIME_OF_DAY" => Some(Self::TimeOfDay),
+            "DATE" => Some(Self::Date),
+            "DT" | "DATE_AND_TIME" => Some(Self::DateAndTime),
+            _ => None,
+        }
+    }
+
+    pub(super) fn bytes_to_variable(self, bytes: &[u8]) -> Result<Variable> {
+        if self == Self::LTime {
+            return Ok(Variable::LTime(Duration::from_nanos(bytes_to_inner(
+                bytes,
+            )?)));
+        }
+
+        let value: u32 = bytes_to_inner(bytes)?;
+        Ok(match self {
+            Self::Time => Variable::Time(Duration::from_millis(value.into())),
+            Self::TimeOfDay => Variable::TimeOfDay(Duration::from_millis(value.into())),
+            Self::Date => Variable::Date(UNIX_EPOCH + Duration::from_secs(value.into())),
+            _ => Variable::DateAndTime(UNIX_EPOCH + Duration::from_secs(value.into())),
+        })
+    }
+
+    /// `None` if `variable` is not of this type.
+    /// Durations and times are truncated to the resolution of the type, but a `Date` must be at midnight.
+    pub(super) fn variable_to_bytes(self, variable: &Variable) -> Option<Result<Vec<u8>>> {
+        match (self, variable) {
+            (Self::Time, Variable::Time(d)) => Some(millis_to_bytes(*d)),
+            (Self::LTime, Variable::LTime(d)) => Some(nanos_to_bytes(*d)),
+            (Self::TimeOfDay, Variable::TimeOfDay(d)) => Some(time_of_day_to_bytes(*d)),
+            (Self::Date, Variable::Date(t)) => Some(date_to_bytes(*t)),
+            (Self::DateAndTime, Variable::DateAndTime(t)) => Some(system_time_to_bytes(*t)),
+            _ => None,
+        }
+    }
+
+    /// An IEC literal, such as `T#1h30m`, `LTIME#5us`, `TOD#12:00:00.5`, `D#2024-01-01`
+    /// or `DT#2024-01-01-12:00:00`
+    pub(super) fn str_to_bytes(self, value: &str) -> Result<Vec<u8>> {
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot parse {value} as {self:?}"),
+            )
+        };
+
+        let (prefix, literal) = value.trim().split_once('#').ok_or_else(invalid)?;
+        let prefix = prefix.to_uppercase();
+        let literal = literal.replace('_', "");
+        match (self, prefix.as_str()) {
+            (Self::Time, "T" | "TIME") => millis_to_bytes(str_to_duration(&literal)?),
+            (Self::LTime, "LT" | "LTIME") => nanos_to_bytes(str_to_duration(&literal)?),
+            (Self::TimeOfDay, "TOD" | "TIME_OF_DAY") => {
+                time_of_day_to_bytes(str_to_time_of_day(&literal).ok_or_else(invalid)?)
+            }
+            (Self::Date, "D" | "DATE") => {
+                system_time_to_bytes(str_to_date(&literal).ok_or_else(invalid)?)
+            }
+            (Self::DateAndTime, "DT" | "DATE_AND_TIME") => {
+                // The date and the time of day are joined by a '-'
+                let (date, time_of_day) = match literal.match_indices('-').nth(2) {
+                    Some((i, _)) => (&literal[..i], &literal[i + 1..]),
+                    None => return Err(invalid()),
+                };
+                let date = str_to_date(date).ok_or_else(invalid)?;
+                let time_of_day = str_to_time_of_day(time_of_day).ok_or_else(invalid)?;
+                system_time_to_bytes(date + time_of_day)
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Such as `1d2h3m4s5ms`, `1.5s` or `250us`
+fn str_to_duration(literal: &str) -> Result<Duration> {
+    /// Longest first, so that `ms` is not taken for minutes
+    const UNITS_NANOS: &[(&str, u128)] = &[
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+        ("d", SECONDS_PER_DAY as u128 * NANOS_PER_SECOND),
+        ("h", 3600 * NANOS_PER_SECOND),
+        ("m", 60 * NANOS_PER_SECOND),
+        ("s", NANOS_PER_SECOND),
+    ];
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot parse {literal} as a duration"),
+        )
+    };
+
+    let lowercase = literal.to_lowercase();
+    if lowercase.is_empty() {
+        return Err(invalid());
+    }
+    let mut remainder = lowercase.as_str();
+    let mut total_nanos = 0;
+    while !remainder.is_empty() {
+        let number_end = remainder
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let number = &remainder[..number_end];
+        remainder = &remainder[number_end..];
+        let (unit, unit_nanos) = UNITS_NANOS
+            .iter()
+            .find(|(unit, _)| remainder.starts_with(unit))
+            .ok_or_else(invalid)?;
+        remainder = &remainder[unit.len()..];
+        total_nanos = decimal_to_nanos(number, *unit_nanos)
+            .and_then(|nanos| nanos.checked_add(total_nanos))
+            .ok_or_else(invalid)?;
+    }
+
+    match u64::try_from(total_nanos) {
+        Ok(ns) => Ok(Duration::from_nanos(ns)),
+        Err(_) => Err(invalid()),
+    }
+}
+
+/// Such as `12:30`, `12:30:15` or `12:30:15.25`
+fn str_to_time_of_day(literal: &str) -> Option<Duration> {
+    let mut parts = literal.split(':');
+    let hours = u64::from_str(parts.next()?).ok()?;
+    let minutes = u64::from_str(parts.next()?).ok()?;
+    let seconds_nanos = match parts.next() {
+        Some(s) => decimal_to_nanos(s, NANOS_PER_SECOND)?,
+        None => 0,
+    };
+    if parts.next().is_some()
+        || hours > 23
+        || minutes > 59
+        || seconds_nanos >= 60 * NANOS_PER_SECOND
+    {
+        return None;
+    }
+    Some(
+        Duration::from_secs(hours * 3600 + minutes * 60)
+            + Duration::from_nanos(seconds_nanos as u64),
+    )
+}
+
+/// A number such as `12` or `1.25`, of a unit which is `unit_nanos` long,
+/// truncated to whole nanoseconds
+fn decimal_to_nanos(number: &str, unit_nanos: u128) -> Option<u128> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole = u128::from_str(whole).ok()?;
+    let fraction_nanos = fraction.bytes().rev().fold(0, |nanos, digit| {
+        (nanos + u128::from(digit - b'0') * unit_nanos) / 10
+    });
+    whole.checked_mul(unit_nanos)?.checked_add(fraction_nanos)
+}
+
+/// Such as `2024-01-31`
+fn str_to_date(literal: &str) -> Option<SystemTime> {
+    let mut parts = literal.split('-');
+    let year = i64::from_str(parts.next()?).ok()?;
+    let month = u32::from_str(parts.next()?).ok()?;
+    let day = u32::from_str(parts.next()?).ok()?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if day > days_in_month {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(days * SECONDS_PER_DAY))
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+/// (Howard Hinnant's `days_from_civil`)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn millis_to_bytes(duration: Duration) -> Result<Vec<u8>> {
+    match u32::try_from(duration.as_millis()) {
+        Ok(ms) => Ok(ms.as_bytes().to_vec()),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{duration:?} is too long for TIME"),
+        )),
+    }
+}
+
+fn nanos_to_bytes(duration: Duration) -> Result<Vec<u8>> {
+    match u64::try_from(duration.as_nanos()) {
+        Ok(ns) => Ok(ns.as_bytes().to_vec()),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{duration:?} is too long for LTIME"),
+        )),
+    }
+}
+
+fn time_of_day_to_bytes(since_midnight: Duration) -> Result<Vec<u8>> {
+    if since_midnight.as_secs() >= SECONDS_PER_DAY {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{since_midnight:?} is not a time of day"),
+        ));
+    }
+    millis_to_bytes(since_midnight)
+}
+
+fn date_to_bytes(date: SystemTime) -> Result<Vec<u8>> {
+    let since_epoch = date.duration_since(UNIX_EPOCH).unwrap_or_default();
+    if !since_epoch.as_secs().is_multiple_of(SECONDS_PER_DAY) || since_epoch.subsec_nanos() != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{date:?} is not at midnight, so is not a DATE"),
+        ));
+    }
+    system_time_to_bytes(date)
+}
+
+fn system_time_to_bytes(time: SystemTime) -> Result<Vec<u8>> {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|d| u32::try_from(d.as_secs()).ok());
+    match seconds {
+        Some(s) => Ok(s.as_bytes().to_vec()),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{time:?} is outside of 1970 to 2106"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    use crate::mock::{connect, Declaration, Server};
+    #[cfg(feature = "mock")]
+    use crate::Variable as V;
+
+    fn u32_bytes(value: u32) -> Vec<u8> {
+        value.to_le_bytes().to_vec()
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(
+            TimeType::Time.str_to_bytes("T#30S").unwrap(),
+            u32_bytes(30_000)
+        );
+        assert_eq!(
+            TimeType::Time.str_to_bytes("time#1d2h3m4s5ms").unwrap(),
+            u32_bytes(93_784_005)
+        );
+        assert_eq!(
+            TimeType::Time.str_to_bytes("T#1.5s").unwrap(),
+            u32_bytes(1_500)
+        );
+        assert_eq!(
+            TimeType::LTime.str_to_bytes("LTIME#1ms_250us").unwrap(),
+            1_250_000u64.to_le_bytes().to_vec()
+        );
+
+        assert!(TimeType::Time.str_to_bytes("T#").is_err());
+        assert!(TimeType::Time.str_to_bytes("T#5 parsecs").is_err());
+        assert!(TimeType::Time.str_to_bytes("30000").is_err());
+        assert!(TimeType::Time.str_to_bytes("TOD#12:00").is_err());
+        assert!(TimeType::Time.str_to_bytes("T#50d").is_err());
+        assert!(TimeType::LTime
+            .str_to_bytes("LT#340282366920938463463374607431768211455ns1ns")
+            .is_err());
+    }
+
+    #[test]
+    fn parse_dates_and_times() {
+        assert_eq!(
+            TimeType::TimeOfDay.str_to_bytes("TOD#12:30:15.5").unwrap(),
+            u32_bytes(45_015_500)
+        );
+        assert_eq!(
+            TimeType::Date.str_to_bytes("D#1970-01-02").unwrap(),
+            u32_bytes(86_400)
+        );
+        assert_eq!(
+            TimeType::Date.str_to_bytes("DATE#2024-02-29").unwrap(),
+            u32_bytes(1_709_164_800)
+        );
+        assert_eq!(
+            TimeType::DateAndTime
+                .str_to_bytes("DT#2024-01-01-12:00:00")
+                .unwrap(),
+            u32_bytes(1_704_110_400)
+        );
+
+        assert!(TimeType::TimeOfDay.str_to_bytes("TOD#24:00").is_err());
+        assert!(TimeType::Date.str_to_bytes("D#2023-02-29").is_err());
+        assert!(TimeType::Date.str_to_bytes("D#1969-12-31").is_err());
+        assert!(TimeType::DateAndTime.str_to_bytes("DT#2024-01-01").is_err());
+    }
+
+    #[test]
+    fn bytes_to_variables() {
+        assert_eq!(
+            TimeType::Time.bytes_to_variable(&u32_bytes(1_500)).unwrap(),
+            Variable::Time(Duration::from_millis(1_500))
+        );
+        assert_eq!(
+            TimeType::DateAndTime
+                .bytes_to_variable(&u32_bytes(1_704_110_400))
+                .unwrap(),
+            Variable::DateAndTime(UNIX_EPOCH + Duration::from_secs(1_704_110_400))
+        );
+        assert!(TimeType::LTime.bytes_to_variable(&u32_bytes(1)).is_err());
+        assert!(TimeType::Date
+            .variable_to_bytes(&Variable::Time(Duration::ZERO))
+            .is_none());
+        assert_eq!(
+            TimeType::Date
+                .variable_to_bytes(&Variable::Date(UNIX_EPOCH + Duration::from_secs(86_400)))
+                .unwrap()
+                .unwrap(),
+            u32_bytes(86_400)
+        );
+        assert!(TimeType::Date
+            .variable_to_bytes(&Variable::Date(UNIX_EPOCH + Duration::from_secs(86_401)))
+            .unwrap()
+            .is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_and_set_times() {
+        let server = Server::builder()
+            .with_struct(
+                "TON",
+                &[
+                    Declaration::new("IN", "BOOL"),
+                    Declaration::new("PT", "TIME"),
+                    Declaration::new("Q", "BOOL"),
+                    Declaration::new("ET", "TIME"),
+                ],
+            )
+            .with_symbol(Declaration::new("main.timer", "TON"))
+            .with_symbol(Declaration::new("main.cycle", "LTIME"))
+            .with_symbol(Declaration::new("main.alarm", "TOD"))
+            .with_symbol(Declaration::new("main.holidays", "ARRAY [0..1] OF DATE"))
+            .with_symbol(Declaration::new("main.installed", "DT"))
+            .with_value("main.timer.PT", V::Time(Duration::from_secs(5)))
+            .start()
+            .unwrap();
+        let client = connect(&server);
+
+        assert_eq!(
+            client.get_value("main.timer").unwrap(),
+            V::Struct(vec![
+                (String::from("IN"), V::Bool(false)),
+                (String::from("PT"), V::Time(Duration::from_secs(5))),
+                (String::from("Q"), V::Bool(false)),
+                (String::from("ET"), V::Time(Duration::ZERO)),
+            ])
+        );
+
+        client.set_value_from_str("main.timer.PT", "T#30S").unwrap();
+        assert_eq!(
+            client.get_value("main.timer.PT").unwrap(),
+            V::Time(Duration::from_secs(30))
+        );
+        client
+            .set_value_from_str("main.cycle", "LTIME#1ms250us")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.cycle").unwrap(),
+            V::LTime(Duration::from_micros(1250))
+        );
+        client
+            .set_value_from_str("main.alarm", "TOD#06:30")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.alarm").unwrap(),
+            V::TimeOfDay(Duration::from_secs(6 * 3600 + 30 * 60))
+        );
+        client
+            .set_value_from_str("main.holidays", "[D#2024-12-25,D#2025-01-01]")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.holidays[1]").unwrap(),
+            V::Date(UNIX_EPOCH + Duration::from_secs(1_735_689_600))
+        );
+        client
+            .set_value_from_str("main.installed", "DT#2024-01-01-12:00:00")
+            .unwrap();
+        assert_eq!(
+            client.get_value("main.installed").unwrap(),
+            V::DateAndTime(UNIX_EPOCH + Duration::from_secs(1_704_110_400))
+        );
+
+        assert!(client
+            .set_value("main.timer.PT", V::LTime(Duration::from_secs(1)))
+            .is_err());
+        assert!(client.set_value_from_str("main.alarm", "T#5s").is_err());
+    }
+}
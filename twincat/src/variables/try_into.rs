@@ -161,7 +161,7 @@ impl TryInto<String> for Variable {
 
     fn try_into(self) -> Result<String> {
         match self {
-            Self::String(inner) => Ok(inner),
+            Self::String(inner) | Self::WString(inner) => Ok(inner),
             other => Err(Error::new(
                 ErrorKind::InvalidInput,
                 format!("Expected String, got {other:?}"),
@@ -1,6 +1,7 @@
 use std::io::Result;
 
-use super::variables::{self, Variable};
+use super::access::Write;
+use super::variables::Variable;
 use super::Client;
 
 impl Client {
@@ -13,17 +14,19 @@ impl Client {
     }
 
     /// A function for verifying an ADS path and associated Variable type
-    /// without actually invoking an ADS Client call
+    /// without actually invoking an ADS Client call.
+    /// A `Variable::Struct` which leaves out some fields is checked as `Client::set_value` would write it
     pub fn verify_ads_path_and_variable_type(
         &self,
         value_name: impl AsRef<str>,
         value: Variable,
     ) -> Result<()> {
-        let data_types = self.symbols_and_data_types().data_types();
-        let (symbol_info, data_type_info) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-        let _ = value.to_bytes(data_types, symbol_info, data_type_info)?;
+        let _ = Write::of_value(
+            &self.symbols_and_data_types(),
+            value_name.as_ref(),
+            &value,
+            self.partial_writes(),
+        )?;
         Ok(())
     }
 
@@ -34,10 +37,7 @@ impl Client {
         value_name: impl AsRef<str>,
         value: &str,
     ) -> Result<()> {
-        let (symbol_info, data_type_info) = self
-            .symbols_and_data_types()
-            .get_symbol_and_data_type(value_name.as_ref())?;
-        let _ = variables::str_and_symbol_to_bytes(value, symbol_info, data_type_info)?;
+        let _ = Write::of_str(&self.symbols_and_data_types(), value_name.as_ref(), value)?;
         Ok(())
     }
 }